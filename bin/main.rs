@@ -22,26 +22,46 @@ extern crate fdlimit;
 extern crate signal_hook;
 #[macro_use]
 extern crate clap;
+extern crate ethereum_types;
 extern crate failure;
 extern crate log;
 extern crate oasis_chain;
 extern crate simple_logger;
 
-use std::{io::Read, os::unix::net::UnixStream};
+use std::{io::Read, os::unix::net::UnixStream, time::Duration};
 
 use clap::{App, Arg};
-use failure::Fallible;
+use ethereum_types::{Address, U256};
+use failure::{format_err, Fallible};
 use fdlimit::raise_fd_limit;
 use log::{error, info};
 
-use oasis_chain::{util, BLOCK_GAS_LIMIT, MIN_GAS_PRICE_GWEI};
+use oasis_chain::{
+    default_simulator_threads, util, ApiSet, BlockTime, BlockchainConfig, MiningLog, MiningMode,
+    BLOCK_GAS_LIMIT, CALL_GAS_CAP, CALL_TIMEOUT, DEFAULT_ACCOUNT_BALANCE, DEFAULT_ACCOUNT_COUNT,
+    DEFAULT_MNEMONIC, MAX_LOGS_BLOCK_RANGE, MAX_MINE_BLOCKS, MAX_TRANSACTION_SIZE,
+    MIN_GAS_PRICE_GWEI, SLOW_REQUEST_THRESHOLD,
+};
 
 fn main() -> Fallible<()> {
     // Increase max number of open files.
     raise_fd_limit();
 
     let block_gas_limit = BLOCK_GAS_LIMIT.to_string();
+    let call_gas_cap = CALL_GAS_CAP.to_string();
+    let call_timeout = CALL_TIMEOUT.as_secs().to_string();
+    let simulator_threads = default_simulator_threads().to_string();
+    let max_transaction_size = MAX_TRANSACTION_SIZE.to_string();
     let gas_price = MIN_GAS_PRICE_GWEI.to_string();
+    let max_logs_block_range = MAX_LOGS_BLOCK_RANGE.to_string();
+    let max_mine_blocks = MAX_MINE_BLOCKS.to_string();
+    let slow_request_threshold = (SLOW_REQUEST_THRESHOLD.as_secs() * 1_000).to_string();
+    let account_count = DEFAULT_ACCOUNT_COUNT.to_string();
+    let account_balance_help = format!(
+        "Genesis balance of each development account (in DEV). Defaults to the balance the \
+         genesis spec gives the account, or {} DEV if the spec does not fund it.",
+        DEFAULT_ACCOUNT_BALANCE
+    );
 
     let args = App::new("Oasis chain")
         .arg(
@@ -72,6 +92,13 @@ fn main() -> Fallible<()> {
                 .default_value("10000")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("slow-request-threshold")
+                .long("slow-request-threshold")
+                .help("Latency (in milliseconds) above which a JSON-RPC request is logged.")
+                .default_value(&slow_request_threshold)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("pubsub-interval")
                 .long("pubsub-interval")
@@ -93,6 +120,172 @@ fn main() -> Fallible<()> {
                 .default_value(&block_gas_limit)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("call-gas-cap")
+                .long("call-gas-cap")
+                .help("Maximum gas of an eth_call or eth_estimateGas.")
+                .default_value(&call_gas_cap)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("call-timeout")
+                .long("call-timeout")
+                .help("Timeout (in seconds) of an eth_call or eth_estimateGas.")
+                .default_value(&call_timeout)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("simulator-threads")
+                .long("simulator-threads")
+                .help("Number of threads simulating eth_call and eth_estimateGas (at least 1).")
+                .default_value(&simulator_threads)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-transaction-size")
+                .long("max-transaction-size")
+                .help(
+                    "Maximum size (in bytes) of a raw transaction, rejected before decoding. \
+                     Unlike the gas cost of its data, this bounds the memory a transaction uses.",
+                )
+                .default_value(&max_transaction_size)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("coinbase")
+                .long("coinbase")
+                .help("Address credited with block rewards.")
+                .default_value("0x0000000000000000000000000000000000000000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("block-reward")
+                .long("block-reward")
+                .help("Reward credited to the coinbase for each mined block (in Gwei).")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mining-mode")
+                .long("mining-mode")
+                .help("Block production mode.")
+                .possible_values(&["auto", "interval", "manual"])
+                .default_value("auto")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mining-interval")
+                .long("mining-interval")
+                .help("Time interval between blocks in interval mining mode (in sec).")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("instamine-delay-ms")
+                .long("instamine-delay-ms")
+                .help(
+                    "Delay before mining submitted transactions in auto mining mode (in ms). \
+                     Transactions stay in the pending buffer during the delay.",
+                )
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mining-log")
+                .long("mining-log")
+                .help("Logging of mined blocks (structured logs a JSON record per block).")
+                .possible_values(&["quiet", "normal", "structured"])
+                .default_value("normal")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("deterministic-time")
+                .long("deterministic-time")
+                .help("Derive block timestamps from the genesis timestamp instead of the clock."),
+        )
+        .arg(
+            Arg::with_name("genesis-timestamp")
+                .long("genesis-timestamp")
+                .help("Genesis block timestamp in deterministic time mode.")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("block-time-step")
+                .long("block-time-step")
+                .help("Seconds between consecutive blocks in deterministic time mode.")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mine-pending-on-shutdown")
+                .long("mine-pending-on-shutdown")
+                .help("Mine the pending transactions into a final block on shutdown."),
+        )
+        .arg(
+            Arg::with_name("max-logs-block-range")
+                .long("max-logs-block-range")
+                .help("Maximum number of blocks a log query may span.")
+                .default_value(&max_logs_block_range)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("chain")
+                .long("chain")
+                .help("Chain spec: a built-in spec name (oasis) or the path to a spec JSON file.")
+                .default_value("oasis")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("account-start-nonce")
+                .long("account-start-nonce")
+                .help("Nonce of new accounts (defaults to the genesis spec's).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("genesis-alloc")
+                .long("genesis-alloc")
+                .help("JSON file of extra genesis accounts (balance, nonce, code, storage).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mnemonic")
+                .long("mnemonic")
+                .help("BIP-39 mnemonic from which the unlocked development accounts are derived.")
+                .default_value(DEFAULT_MNEMONIC)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("accounts")
+                .long("accounts")
+                .help("Number of development accounts.")
+                .default_value(&account_count)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("account-balance")
+                .long("account-balance")
+                .help(&account_balance_help)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-mine-blocks")
+                .long("max-mine-blocks")
+                .help("Maximum number of blocks a single evm_mine call may mine.")
+                .default_value(&max_mine_blocks)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("jsonrpc-apis")
+                .long("jsonrpc-apis")
+                .help(
+                    "Comma-separated APIs exposed over HTTP and WebSocket: web3, net, eth, \
+                     pubsub, oasis, evm, personal, debug, safe (the APIs safe to expose) or all. \
+                     An API prefixed with - is removed.",
+                )
+                .default_value("safe")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("interface")
                 .long("interface")
@@ -119,14 +312,92 @@ fn main() -> Fallible<()> {
     let num_threads = value_t!(args, "threads", usize)?;
     let interface = value_t!(args, "interface", String)?;
     let http_port = value_t!(args, "http-port", u16)?;
+    let apis = value_t!(args, "jsonrpc-apis", ApiSet)?;
     let ws_port = value_t!(args, "ws-port", u16)?;
     let ws_max_connections = value_t!(args, "ws-max-connections", usize)?;
+    let slow_request_threshold =
+        Duration::from_millis(value_t!(args, "slow-request-threshold", u64)?);
     let pubsub_interval_secs = value_t!(args, "pubsub-interval", u64)?;
     let gas_price = util::gwei_to_wei(value_t!(args, "gas-price", u64)?);
     let block_gas_limit = value_t!(args, "block-gas-limit", usize)?;
+    let call_gas_cap = value_t!(args, "call-gas-cap", usize)?;
+    let call_timeout = Duration::from_secs(value_t!(args, "call-timeout", u64)?);
+    let simulator_threads = value_t!(args, "simulator-threads", usize)?;
+    if simulator_threads == 0 {
+        return Err(format_err!("Simulator threads must be positive"));
+    }
+    let max_transaction_size = value_t!(args, "max-transaction-size", usize)?;
+    let coinbase = value_t!(args, "coinbase", String)?;
+    let coinbase: Address = coinbase
+        .trim_start_matches("0x")
+        .parse()
+        .map_err(|_| format_err!("Invalid coinbase address: {}", coinbase))?;
+    let block_reward = util::gwei_to_wei(value_t!(args, "block-reward", u64)?);
+    let mining_mode = match args.value_of("mining-mode") {
+        Some("interval") => {
+            let mining_interval_secs = value_t!(args, "mining-interval", u64)?;
+            if mining_interval_secs == 0 {
+                return Err(format_err!("Mining interval must be positive"));
+            }
+            MiningMode::Interval(Duration::from_secs(mining_interval_secs))
+        }
+        Some("manual") => MiningMode::Manual,
+        _ => MiningMode::Auto,
+    };
+    let instamine_delay = Duration::from_millis(value_t!(args, "instamine-delay-ms", u64)?);
+    let max_logs_block_range = value_t!(args, "max-logs-block-range", u64)?;
+    let max_mine_blocks = value_t!(args, "max-mine-blocks", u64)?;
+    let mining_log = match args.value_of("mining-log") {
+        Some("quiet") => MiningLog::Quiet,
+        Some("structured") => MiningLog::Structured,
+        _ => MiningLog::Normal,
+    };
+    let block_time = if args.is_present("deterministic-time") {
+        let step = value_t!(args, "block-time-step", u64)?;
+        if step == 0 {
+            return Err(format_err!("Block time step must be positive"));
+        }
+        BlockTime::Deterministic {
+            genesis_timestamp: value_t!(args, "genesis-timestamp", u64)?,
+            step,
+        }
+    } else {
+        BlockTime::WallClock
+    };
+    let chain = value_t!(args, "chain", String)?;
+    let account_start_nonce = if args.is_present("account-start-nonce") {
+        Some(U256::from(value_t!(args, "account-start-nonce", u64)?))
+    } else {
+        None
+    };
+    let genesis_alloc = args.value_of("genesis-alloc").map(String::from);
+    let config = BlockchainConfig {
+        gas_price,
+        block_gas_limit: block_gas_limit.into(),
+        call_gas_cap: call_gas_cap.into(),
+        call_timeout,
+        simulator_threads,
+        max_transaction_size,
+        coinbase,
+        block_reward,
+        mining_mode,
+        instamine_delay,
+        max_logs_block_range,
+        mining_log,
+        block_time,
+        account_start_nonce,
+        genesis_alloc: Default::default(),
+    };
+    let mnemonic = value_t!(args, "mnemonic", String)?;
+    let account_count = value_t!(args, "accounts", usize)?;
+    let account_balance = if args.is_present("account-balance") {
+        Some(util::dev_to_wei(value_t!(args, "account-balance", u64)?))
+    } else {
+        None
+    };
+    let mine_pending_on_shutdown = args.is_present("mine-pending-on-shutdown");
 
-    let chain_info = include_str!("../resources/info.txt");
-    info!("Starting Oasis local chain\n{}", chain_info);
+    info!("Starting Oasis local chain");
 
     let client = oasis_chain::start(
         args,
@@ -136,11 +407,18 @@ fn main() -> Fallible<()> {
         num_threads,
         ws_port,
         ws_max_connections,
-        gas_price,
-        block_gas_limit.into(),
+        slow_request_threshold,
+        apis,
+        config,
+        max_mine_blocks,
+        &chain,
+        genesis_alloc.as_ref().map(String::as_str),
+        &mnemonic,
+        account_count,
+        account_balance,
     );
 
-    let client = match client {
+    let mut client = match client {
         Ok(client) => client,
         Err(err) => {
             error!("Failed to initialize Oasis local chain: {:?}", err);
@@ -148,6 +426,8 @@ fn main() -> Fallible<()> {
         }
     };
 
+    client.set_mine_pending_on_shutdown(mine_pending_on_shutdown);
+
     info!("Oasis local chain is running");
 
     // Register a self-pipe for handing the SIGTERM and SIGINT signals.
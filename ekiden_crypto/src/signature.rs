@@ -27,6 +27,12 @@ impl PrivateKey {
         PrivateKey(key)
     }
 
+    /// Derives the private key pair from a 32-byte seed.
+    pub fn from_seed(seed: &[u8]) -> Fallible<Self> {
+        let key = Ed25519KeyPair::from_seed_unchecked(untrusted::Input::from(seed))?;
+        Ok(PrivateKey(key))
+    }
+
     /// Loads the private key pair from PKCS8 encoded data.
     pub fn from_pkcs8(key: &[u8]) -> Fallible<Self> {
         let key = Ed25519KeyPair::from_pkcs8(untrusted::Input::from(key))?;
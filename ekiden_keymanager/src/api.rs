@@ -1,15 +1,22 @@
 //! Key manager API.
 
+use failure::Fallible;
 use rand::{rngs::OsRng, Rng};
 use serde_derive::{Deserialize, Serialize};
 
-use ekiden_crypto::{impl_bytes, signature::Signature};
+use ekiden_crypto::{
+    impl_bytes,
+    signature::{PublicKey as SigningPublicKey, Signature},
+};
 
 impl_bytes!(ContractId, 32, "A 256-bit contract identifier.");
 impl_bytes!(PrivateKey, 32, "A private key.");
 impl_bytes!(PublicKey, 32, "A public key.");
 impl_bytes!(StateKey, 32, "A state key.");
 
+/// Signature context of signed contract public keys.
+pub const PUBLIC_KEY_CONTEXT: &[u8] = b"EkKmPubK";
+
 /// Keys for a contract.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ContractKey {
@@ -92,6 +99,25 @@ pub struct SignedPublicKey {
     /// Checksum of the key manager state.
     #[serde(with = "serde_bytes")]
     pub checksum: Vec<u8>,
-    /// Sign(sk, (key || checksum)) from the key manager.
+    /// Key creation time (seconds since the epoch).
+    pub timestamp: u64,
+    /// Sign(sk, (key || checksum || timestamp)) from the key manager.
     pub signature: Signature,
 }
+
+impl SignedPublicKey {
+    /// The signed message, i.e. key || checksum || timestamp, with the
+    /// timestamp in big-endian order.
+    pub fn message(key: &PublicKey, checksum: &[u8], timestamp: u64) -> Vec<u8> {
+        let mut message = key.as_ref().to_vec();
+        message.extend_from_slice(checksum);
+        message.extend_from_slice(&timestamp.to_be_bytes());
+        message
+    }
+
+    /// Verify the signature with the key manager's signing key.
+    pub fn verify(&self, signer: &SigningPublicKey) -> Fallible<()> {
+        let message = Self::message(&self.key, &self.checksum, self.timestamp);
+        self.signature.verify(signer, PUBLIC_KEY_CONTEXT, &message)
+    }
+}
@@ -1,43 +1,76 @@
 //! Mock key manager client which stores everything locally.
 
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use ekiden_crypto::signature::Signature;
+use ekiden_crypto::{
+    hash::Hash,
+    signature::{PrivateKey, PublicKey as SigningPublicKey, Signer},
+};
 
-use crate::api::{ContractId, ContractKey, SignedPublicKey};
+use crate::api::{ContractId, ContractKey, SignedPublicKey, PUBLIC_KEY_CONTEXT};
+
+/// Seed of the mock key manager's signing key. The key is deterministic, so
+/// clients can pin it across restarts; it is public and provides no security.
+const MOCK_SIGNING_KEY_SEED: &[u8] = b"oasis-chain mock key manager signing key";
 
 /// Mock key manager client which stores everything locally.
 pub struct MockClient {
-    keys: Mutex<HashMap<ContractId, ContractKey>>,
+    /// Contract keys along with their creation timestamps.
+    keys: Mutex<HashMap<ContractId, (ContractKey, u64)>>,
+    /// Long-term key signing the contract public keys.
+    signer: PrivateKey,
 }
 
 impl MockClient {
     /// Create a new mock key manager client.
     pub fn new() -> Self {
+        let seed = Hash::digest_bytes(MOCK_SIGNING_KEY_SEED);
         Self {
             keys: Mutex::new(HashMap::new()),
+            signer: PrivateKey::from_seed(seed.as_ref()).expect("seed must be valid"),
         }
     }
+
+    /// Public key of the key manager's long-term signing key, which signs the
+    /// contract public keys.
+    pub fn signing_public_key(&self) -> SigningPublicKey {
+        self.signer.public_key()
+    }
 }
 
 impl MockClient {
     pub fn get_or_create_keys(&self, contract_id: ContractId) -> ContractKey {
-        let mut keys = self.keys.lock().unwrap();
-        match keys.get(&contract_id) {
-            Some(key) => key.clone(),
-            None => {
-                let key = ContractKey::generate_mock();
-                keys.insert(contract_id, key.clone());
-                key
-            }
-        }
+        self.get_or_create_entry(contract_id).0
     }
 
     pub fn get_public_key(&self, contract_id: ContractId) -> Option<SignedPublicKey> {
+        let (key, timestamp) = self.get_or_create_entry(contract_id);
+        let key = key.input_keypair.get_pk();
+        let checksum = vec![];
+        let message = SignedPublicKey::message(&key, &checksum, timestamp);
+        let signature = self.signer.sign(PUBLIC_KEY_CONTEXT, &message).ok()?;
         Some(SignedPublicKey {
-            key: self.get_or_create_keys(contract_id).input_keypair.get_pk(),
-            checksum: vec![],
-            signature: Signature::default(),
+            key,
+            checksum,
+            timestamp,
+            signature,
         })
     }
+
+    fn get_or_create_entry(&self, contract_id: ContractId) -> (ContractKey, u64) {
+        let mut keys = self.keys.lock().unwrap();
+        keys.entry(contract_id)
+            .or_insert_with(|| {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                (ContractKey::generate_mock(), timestamp)
+            })
+            .clone()
+    }
 }
@@ -1,4 +1,5 @@
 extern crate ekiden_crypto;
+extern crate failure;
 extern crate rand;
 extern crate rustc_hex;
 extern crate serde;
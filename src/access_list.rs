@@ -0,0 +1,280 @@
+//! Access list collection (see `eth_createAccessList`).
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethcore::trace::VMTracer;
+use ethereum_types::{Address, H256, U256};
+
+/// Accounts accessed by a transaction, and the storage slots accessed in
+/// each of them.
+pub type AccessList = BTreeMap<Address, BTreeSet<H256>>;
+
+/// VM tracer collecting the accounts and storage slots accessed during an
+/// execution.
+///
+/// The tracer is only told the instructions executed and the values they
+/// push, so it shadows the EVM stack to learn the operands of storage and
+/// account accesses. WebAssembly contracts are not traced, so only their
+/// accounts are collected.
+pub struct AccessListTracer {
+    /// Storage address of the frame, unknown for contract creations until
+    /// they return.
+    address: Option<Address>,
+    /// Shadow of the EVM stack of the frame.
+    stack: Vec<U256>,
+    /// Instruction being executed.
+    instruction: u8,
+    /// Storage address of the frame of the call being made, if known.
+    callee: Option<Address>,
+    /// Accounts and slots accessed by the frame and its subframes.
+    accesses: AccessList,
+    /// Slots accessed by the frame while its address is unknown.
+    unresolved: BTreeSet<H256>,
+    /// Slots accessed by the frame of the contract creation being made.
+    created: BTreeSet<H256>,
+}
+
+impl AccessListTracer {
+    /// Create a tracer for a transaction to the given account, or creating a
+    /// contract at the given address.
+    pub fn new(address: Address) -> Self {
+        // The executive runs the top-level frame as a subtrace of this one.
+        AccessListTracer {
+            callee: Some(address),
+            ..Self::frame(Some(address))
+        }
+    }
+
+    fn frame(address: Option<Address>) -> Self {
+        AccessListTracer {
+            address,
+            stack: vec![],
+            instruction: 0,
+            callee: None,
+            accesses: AccessList::new(),
+            unresolved: BTreeSet::new(),
+            created: BTreeSet::new(),
+        }
+    }
+
+    /// Operand `n` of the instruction being executed, from the top.
+    fn operand(&self, n: usize) -> Option<U256> {
+        self.stack.len().checked_sub(n + 1).map(|i| self.stack[i])
+    }
+
+    fn touch_account(&mut self, operand: usize) {
+        if let Some(value) = self.operand(operand) {
+            self.accesses.entry(to_address(value)).or_default();
+        }
+    }
+
+    fn touch_slot(&mut self) {
+        let slot = match self.operand(0) {
+            Some(value) => H256::from(value),
+            None => return,
+        };
+        match self.address {
+            Some(address) => {
+                self.accesses.entry(address).or_default().insert(slot);
+            }
+            None => {
+                self.unresolved.insert(slot);
+            }
+        }
+    }
+}
+
+impl VMTracer for AccessListTracer {
+    type Output = AccessList;
+
+    fn trace_next_instruction(&mut self, _pc: usize, _instruction: u8, _gas: U256) -> bool {
+        true
+    }
+
+    fn trace_prepare_execute(&mut self, _pc: usize, instruction: u8, _gas_cost: U256) {
+        self.instruction = instruction;
+        match instruction {
+            // SLOAD, SSTORE.
+            0x54 | 0x55 => self.touch_slot(),
+            // BALANCE, EXTCODESIZE, EXTCODECOPY, EXTCODEHASH, SELFDESTRUCT.
+            0x31 | 0x3b | 0x3c | 0x3f | 0xff => self.touch_account(0),
+            // CALL, STATICCALL: the callee's storage.
+            0xf1 | 0xfa => {
+                self.touch_account(1);
+                self.callee = self.operand(1).map(to_address);
+            }
+            // CALLCODE, DELEGATECALL: the caller's storage.
+            0xf2 | 0xf4 => {
+                self.touch_account(1);
+                self.callee = self.address;
+            }
+            // CREATE, CREATE2: resolved once the contract is created.
+            0xf0 | 0xf5 => self.callee = None,
+            _ => {}
+        }
+    }
+
+    fn trace_executed(
+        &mut self,
+        _gas_used: U256,
+        stack_push: &[U256],
+        _mem_diff: Option<(usize, &[u8])>,
+        _store_diff: Option<(U256, U256)>,
+    ) {
+        let len = self.stack.len();
+        self.stack
+            .truncate(len.saturating_sub(stack_inputs(self.instruction)));
+        self.stack.extend_from_slice(stack_push);
+
+        if let 0xf0 | 0xf5 = self.instruction {
+            let created = ::std::mem::replace(&mut self.created, BTreeSet::new());
+            match stack_push.first().map(|value| to_address(*value)) {
+                Some(address) if !address.is_zero() => {
+                    self.accesses.entry(address).or_default().extend(created);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn prepare_subtrace(&self, _code: &[u8]) -> Self {
+        Self::frame(self.callee)
+    }
+
+    fn done_subtrace(&mut self, sub: Self) {
+        for (address, slots) in sub.accesses {
+            self.accesses.entry(address).or_default().extend(slots);
+        }
+        if sub.address.is_none() {
+            self.created = sub.unresolved;
+        }
+    }
+
+    fn drain(self) -> Option<AccessList> {
+        Some(self.accesses)
+    }
+}
+
+/// Address held in the low 20 bytes of a stack item.
+fn to_address(value: U256) -> Address {
+    Address::from(H256::from(value))
+}
+
+/// Number of stack items an EVM instruction takes.
+fn stack_inputs(instruction: u8) -> usize {
+    match instruction {
+        // ISZERO, NOT, BALANCE, CALLDATALOAD, EXTCODESIZE, EXTCODEHASH,
+        // BLOCKHASH, POP, MLOAD, SLOAD, JUMP, SELFDESTRUCT.
+        0x15 | 0x19 | 0x31 | 0x35 | 0x3b | 0x3f | 0x40 | 0x50 | 0x51 | 0x54 | 0x56 | 0xff => 1,
+        // Binary arithmetic, comparison and bitwise operations, SHA3,
+        // MSTORE, MSTORE8, SSTORE, JUMPI, RETURN, REVERT.
+        0x01..=0x07 | 0x0a | 0x0b | 0x10..=0x14 | 0x16..=0x18 | 0x1a..=0x1d | 0x20 => 2,
+        0x52 | 0x53 | 0x55 | 0x57 | 0xf3 | 0xfd => 2,
+        // ADDMOD, MULMOD, CALLDATACOPY, CODECOPY, RETURNDATACOPY, CREATE.
+        0x08 | 0x09 | 0x37 | 0x39 | 0x3e | 0xf0 => 3,
+        // EXTCODECOPY, CREATE2.
+        0x3c | 0xf5 => 4,
+        // DUP1 to DUP16.
+        0x80..=0x8f => (instruction - 0x7f) as usize,
+        // SWAP1 to SWAP16.
+        0x90..=0x9f => (instruction - 0x8e) as usize,
+        // LOG0 to LOG4.
+        0xa0..=0xa4 => (instruction - 0x9e) as usize,
+        // DELEGATECALL, STATICCALL.
+        0xf4 | 0xfa => 6,
+        // CALL, CALLCODE.
+        0xf1 | 0xf2 => 7,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execute(tracer: &mut AccessListTracer, instruction: u8, stack_push: &[U256]) {
+        assert!(tracer.trace_next_instruction(0, instruction, U256::zero()));
+        tracer.trace_prepare_execute(0, instruction, U256::zero());
+        tracer.trace_executed(U256::zero(), stack_push, None, None);
+    }
+
+    #[test]
+    fn test_shadow_stack() {
+        let contract = Address::from(1);
+        let mut tracer = AccessListTracer::new(contract);
+
+        // PUSH1 7, SLOAD, PUSH1 9, PUSH1 8, SSTORE.
+        execute(&mut tracer, 0x60, &[7.into()]);
+        execute(&mut tracer, 0x54, &[42.into()]);
+        execute(&mut tracer, 0x60, &[9.into()]);
+        execute(&mut tracer, 0x60, &[8.into()]);
+        execute(&mut tracer, 0x55, &[]);
+        assert_eq!(tracer.stack, vec![U256::from(42)]);
+
+        // BALANCE of the SLOAD result.
+        execute(&mut tracer, 0x31, &[0.into()]);
+
+        let accesses = tracer.drain().unwrap();
+        let slots: Vec<H256> = accesses[&contract].iter().cloned().collect();
+        assert_eq!(slots, vec![H256::from(7), H256::from(8)]);
+        assert!(accesses[&Address::from(42)].is_empty());
+    }
+
+    #[test]
+    fn test_top_level_frame() {
+        let contract = Address::from(1);
+        let mut tracer = AccessListTracer::new(contract);
+
+        // The executive traces the transaction's own frame as a subtrace.
+        let mut sub = tracer.prepare_subtrace(&[]);
+        // PUSH1 7, SLOAD.
+        execute(&mut sub, 0x60, &[7.into()]);
+        execute(&mut sub, 0x54, &[0.into()]);
+        tracer.done_subtrace(sub);
+
+        let accesses = tracer.drain().unwrap();
+        let slots: Vec<H256> = accesses[&contract].iter().cloned().collect();
+        assert_eq!(slots, vec![H256::from(7)]);
+    }
+
+    #[test]
+    fn test_subframes() {
+        let contract = Address::from(1);
+        let callee = Address::from(2);
+        let created = Address::from(3);
+        let mut tracer = AccessListTracer::new(contract);
+
+        // CALL the callee, which reads slot 5.
+        let mut args: Vec<U256> = vec![0.into(); 7];
+        args[5] = U256::from(2);
+        for arg in &args {
+            execute(&mut tracer, 0x60, &[*arg]);
+        }
+        tracer.trace_next_instruction(0, 0xf1, U256::zero());
+        tracer.trace_prepare_execute(0, 0xf1, U256::zero());
+        let mut sub = tracer.prepare_subtrace(&[]);
+        execute(&mut sub, 0x60, &[5.into()]);
+        execute(&mut sub, 0x54, &[0.into()]);
+        tracer.done_subtrace(sub);
+        tracer.trace_executed(U256::zero(), &[1.into()], None, None);
+        assert_eq!(tracer.stack, vec![U256::from(1)]);
+
+        // CREATE a contract, whose constructor writes slot 6.
+        for _ in 0..3 {
+            execute(&mut tracer, 0x60, &[0.into()]);
+        }
+        tracer.trace_next_instruction(0, 0xf0, U256::zero());
+        tracer.trace_prepare_execute(0, 0xf0, U256::zero());
+        let mut sub = tracer.prepare_subtrace(&[]);
+        execute(&mut sub, 0x60, &[0.into()]);
+        execute(&mut sub, 0x60, &[6.into()]);
+        execute(&mut sub, 0x55, &[]);
+        tracer.done_subtrace(sub);
+        let address = U256::from(H256::from(created));
+        tracer.trace_executed(U256::zero(), &[address], None, None);
+
+        let accesses = tracer.drain().unwrap();
+        assert_eq!(accesses.len(), 2);
+        assert!(accesses[&callee].contains(&H256::from(5)));
+        assert!(accesses[&created].contains(&H256::from(6)));
+    }
+}
@@ -1,33 +1,57 @@
 //! Oasis blockchain simulator.
 use std::{
-    collections::{BTreeMap, HashMap},
-    sync::{Arc, RwLock},
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    mem,
+    sync::{Arc, Mutex, RwLock, RwLockWriteGuard},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    confidential::ConfidentialCtx, genesis, parity::NullBackend, storage::MemoryMKVS, util,
+    access_list::{AccessList, AccessListTracer},
+    confidential::ConfidentialCtx,
+    parity::{NullBackend, StateExt},
+    storage::MemoryMKVS,
+    util,
 };
 use ekiden_keymanager::client::MockClient;
 use ethcore::{
-    error::CallError,
+    error::{CallError, ExecutionError},
     executive::{contract_address, Executed, Executive, TransactOptions},
     filter::Filter,
+    header::Header,
     log_entry::{LocalizedLogEntry, LogEntry},
-    receipt::{LocalizedReceipt, TransactionOutcome},
-    state::State,
+    mkvs::MKVS,
+    receipt::{LocalizedReceipt, Receipt, TransactionOutcome},
+    spec::Spec,
+    state::{backend::Backend, CleanupMode, State},
+    trace::{trace::Res, FlatTrace, NoopTracer, NoopVMTracer, TraceError, Tracer, VMTracer},
     transaction::{Action, LocalizedTransaction, SignedTransaction, UnverifiedTransaction},
     types::ids::BlockId,
-    vm::{EnvInfo, Error as VmError},
+    vm::{ConfidentialCtx as EthConfidentialCtx, EnvInfo, Error as VmError},
+};
+use ethereum_types::{Address, Bloom, H256, H64, U256, U512};
+use failure::{format_err, Error, Fail, Fallible};
+use futures::{
+    future,
+    future::Either,
+    prelude::*,
+    sync::{
+        mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
 };
-use ethereum_types::{Bloom, H256, H64, U256};
-use failure::{format_err, Error, Fallible};
-use futures::{future, prelude::*, stream};
 use hash::{keccak, KECCAK_EMPTY_LIST_RLP};
 use lazy_static::lazy_static;
 use parity_rpc::v1::types::{
     Block as EthRpcBlock, BlockTransactions as EthRpcBlockTransactions, Header as EthRpcHeader,
     RichBlock as EthRpcRichBlock, RichHeader as EthRpcRichHeader, Transaction as EthRpcTransaction,
 };
+use rlp::RlpStream;
+use tokio::{
+    runtime::{Builder as RuntimeBuilder, Runtime},
+    timer::{Delay, Interval},
+};
 use tokio_threadpool::{Builder as ThreadPoolBuilder, ThreadPool};
 
 /// Boxed future type.
@@ -35,35 +59,231 @@ type BoxFuture<T> = Box<dyn futures::Future<Item = T, Error = failure::Error> +
 
 /// Block gas limit.
 pub const BLOCK_GAS_LIMIT: usize = 16_000_000;
+/// Default gas cap of simulated calls, that of a block, which also bounds
+/// how long a simulation runs past its timeout.
+pub const CALL_GAS_CAP: usize = BLOCK_GAS_LIMIT;
+/// Default wall-clock timeout of simulated calls.
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+/// Default maximum size of a raw transaction (in bytes).
+pub const MAX_TRANSACTION_SIZE: usize = 128 * 1024;
+/// Default number of threads simulating calls: one per CPU.
+pub fn default_simulator_threads() -> usize {
+    num_cpus::get()
+}
 /// Minimum gas price (in gwei).
 pub const MIN_GAS_PRICE_GWEI: usize = 1;
+/// Default maximum block range of a log query.
+pub const MAX_LOGS_BLOCK_RANGE: u64 = 100_000;
+/// Default maximum number of blocks mined by a single `evm_mine` call.
+pub const MAX_MINE_BLOCKS: u64 = 10_000;
+/// Largest EIP-2718 transaction type byte.
+const MAX_TRANSACTION_TYPE: u8 = 0x7f;
+/// Number of blocks scanned by a single log scan task.
+const LOG_SCAN_CHUNK_SIZE: u64 = 1_000;
+/// Number of recent block hashes available to the BLOCKHASH opcode.
+const LAST_HASHES_COUNT: u64 = 256;
+/// Storage metadata key of the state root of the genesis spec a store was
+/// initialized with.
+const GENESIS_ROOT_KEY: &[u8] = b"oasis-chain:genesis_root";
+
+/// Account fields overridden for the duration of a simulated transaction.
+#[derive(Clone, Debug, Default)]
+pub struct AccountOverride {
+    /// Balance of the account.
+    pub balance: Option<U256>,
+    /// Nonce of the account. Must not be lower than the current nonce.
+    pub nonce: Option<U256>,
+    /// Code of the account.
+    pub code: Option<Vec<u8>>,
+    /// Storage slots replacing the entire account storage.
+    pub state: Option<HashMap<H256, H256>>,
+    /// Storage slots merged into the existing account storage.
+    pub state_diff: Option<HashMap<H256, H256>>,
+}
+
+/// Per-account state overrides for a simulated transaction.
+pub type StateOverride = HashMap<Address, AccountOverride>;
+
+/// Apply state overrides to a (not to be committed) state.
+fn apply_state_override<B: Backend>(
+    state: &mut State<B>,
+    overrides: &StateOverride,
+) -> Fallible<()> {
+    for (address, account) in overrides {
+        if let Some(ref balance) = account.balance {
+            state.set_balance(address, balance)?;
+        }
+        if let Some(ref nonce) = account.nonce {
+            state.set_nonce(address, nonce)?;
+        }
+        if let Some(ref code) = account.code {
+            state.set_code(address, code.clone())?;
+        }
+
+        let slots = match (&account.state, &account.state_diff) {
+            (Some(_), Some(_)) => {
+                return Err(format_err!(
+                    "account {:?} has both state and stateDiff overrides",
+                    address
+                ));
+            }
+            (Some(state_slots), None) => {
+                state.clear_storage(address)?;
+                state_slots
+            }
+            (None, Some(diff_slots)) => diff_slots,
+            (None, None) => continue,
+        };
+        for (key, value) in slots {
+            state.set_storage(address, *key, *value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Initialize the genesis state and allocation in a storage view.
+fn init_genesis(
+    mkvs: &MemoryMKVS,
+    spec: &Spec,
+    account_start_nonce: U256,
+    genesis_alloc: &StateOverride,
+) -> Fallible<()> {
+    spec.ensure_db_good(Box::new(mkvs.clone()), NullBackend, &Default::default())
+        .map_err(|err| format_err!("genesis initialization failed: {}", err))?;
+    if !genesis_alloc.is_empty() {
+        let mut state = State::from_existing(
+            Box::new(mkvs.clone()),
+            NullBackend,
+            account_start_nonce,
+            Default::default(), /* factories */
+            None,               /* confidential_ctx */
+        )?;
+        apply_state_override(&mut state, genesis_alloc)
+            .map_err(|err| format_err!("invalid genesis allocation: {}", err))?;
+        state.commit().map_err(|err| format_err!("{}", err))?;
+    }
+    mkvs.set_metadata(GENESIS_ROOT_KEY, &spec.state_root());
+    Ok(())
+}
 
 /// Simulated blockchain state.
 pub struct ChainState {
+    /// Versioned state storage.
     mkvs: MemoryMKVS,
+    /// Latest state version. Every mined block and every direct state change
+    /// (e.g., `evm_setBalance`) commits a new version.
+    state_version: u64,
+    /// State version of each block, by the number of the first block using
+    /// it. Blocks skipped by `evm_setBlockNumber` share the state of the block
+    /// before them.
+    block_state_versions: BTreeMap<u64, u64>,
     block_number: u64,
+    /// Mined blocks. Blocks skipped by `evm_setBlockNumber` are not stored,
+    /// but synthesized on demand.
     blocks: HashMap<H256, EthereumBlock>,
-    block_number_to_hash: HashMap<u64, H256>,
+    block_number_to_hash: BTreeMap<u64, H256>,
     transactions: HashMap<H256, LocalizedTransaction>,
     receipts: HashMap<H256, LocalizedReceipt>,
+    /// Reasons of the failed transactions (see `ExecutionResult`), by hash.
+    failure_reasons: HashMap<H256, String>,
+    /// Hash of the transaction which created each contract deployed by a
+    /// successful contract creation transaction.
+    contract_creations: HashMap<Address, H256>,
+    /// Transactions waiting to be mined (manual and interval mining only).
+    pending: Vec<SignedTransaction>,
+    /// Transactions whose nonce is ahead of their sender's nonce, by sender
+    /// and nonce. They are mined once the nonce gap is filled.
+    queued: HashMap<Address, BTreeMap<U256, SignedTransaction>>,
+    /// Positions (block number, log index) of logs emitted by each contract,
+    /// in block order.
+    logs_by_address: HashMap<Address, Vec<(u64, usize)>>,
+    /// Timestamp forced for the next block (see `evm_setNextBlockTimestamp`).
+    next_timestamp: Option<u64>,
+    /// Best block number left by each reorg (see `evm_reorg`), in order.
+    /// Blocks mined after a reorg get hashes distinct from the discarded ones.
+    reorgs: Vec<u64>,
+    /// Source of the timestamps of mined blocks.
+    block_time: BlockTime,
+    /// Nonce of accounts which have not sent any transaction yet.
+    account_start_nonce: U256,
+    /// Accounts of the genesis spec and allocation.
+    genesis_accounts: BTreeSet<Address>,
 }
 
 impl ChainState {
-    pub fn new() -> Self {
-        // Initialize genesis state.
-        let mkvs = MemoryMKVS::new();
-        genesis::SPEC
-            .ensure_db_good(Box::new(mkvs.clone()), NullBackend, &Default::default())
-            .expect("genesis initialization must succeed");
+    pub fn new(
+        spec: &Spec,
+        block_time: BlockTime,
+        account_start_nonce: U256,
+        genesis_alloc: &StateOverride,
+    ) -> Fallible<Self> {
+        Self::with_store(
+            MemoryMKVS::new(),
+            spec,
+            block_time,
+            account_start_nonce,
+            genesis_alloc,
+        )
+    }
+
+    /// Create the chain state on top of an existing store.
+    ///
+    /// An empty store is initialized with the genesis state and allocation.
+    /// A store initialized with the same genesis spec is used as is, without
+    /// applying the genesis again, from its genesis version on: the state of
+    /// later blocks is discarded, and so is the given allocation, with a
+    /// warning. Fails if the store was initialized with another genesis spec.
+    pub fn with_store(
+        store: MemoryMKVS,
+        spec: &Spec,
+        block_time: BlockTime,
+        account_start_nonce: U256,
+        genesis_alloc: &StateOverride,
+    ) -> Fallible<Self> {
+        let mkvs = store.at(0);
+        let genesis_root = spec.state_root();
+        match mkvs.get_metadata(GENESIS_ROOT_KEY) {
+            Some(ref root) if root[..] == genesis_root[..] => {
+                // Blocks are not stored, so neither is their state.
+                if mkvs.truncate(0) {
+                    warn!("Discarding the state of the blocks after genesis in the store");
+                }
+                if !genesis_alloc.is_empty() {
+                    warn!("Ignoring the genesis allocation, the store already has a genesis state");
+                }
+            }
+            Some(root) => {
+                return Err(format_err!(
+                    "store was initialized with genesis state root {:?}, but the genesis spec \
+                     expects {:?}",
+                    H256::from_slice(&root),
+                    genesis_root
+                ));
+            }
+            None if !mkvs.is_empty() => {
+                return Err(format_err!("store is not empty, but has no genesis state"));
+            }
+            None => {
+                init_genesis(&mkvs, spec, account_start_nonce, genesis_alloc)?;
+                mkvs.commit(0);
+            }
+        }
 
         // Initialize chain state.
         let block_number = 0;
         let mut blocks = HashMap::new();
-        let mut block_number_to_hash = HashMap::new();
+        let mut block_number_to_hash = BTreeMap::new();
+        let genesis_timestamp = match block_time {
+            BlockTime::WallClock => 0,
+            BlockTime::Deterministic {
+                genesis_timestamp, ..
+            } => genesis_timestamp,
+        };
         let genesis_block = EthereumBlock::new(
             block_number,
             H256::zero(),
-            0,
+            Address::zero(),
+            genesis_timestamp,
             U256::from(0),
             BLOCK_GAS_LIMIT.into(),
             Default::default(),
@@ -71,589 +291,2593 @@ impl ChainState {
         let block_hash = genesis_block.hash();
         blocks.insert(block_hash, genesis_block);
         block_number_to_hash.insert(block_number, block_hash);
+        let mut block_state_versions = BTreeMap::new();
+        block_state_versions.insert(block_number, 0);
 
-        Self {
+        Ok(Self {
             mkvs,
+            state_version: 0,
+            block_state_versions,
             block_number,
             blocks,
             block_number_to_hash,
             transactions: HashMap::new(),
             receipts: HashMap::new(),
-        }
+            failure_reasons: HashMap::new(),
+            contract_creations: HashMap::new(),
+            pending: vec![],
+            queued: HashMap::new(),
+            logs_by_address: HashMap::new(),
+            next_timestamp: None,
+            reorgs: vec![],
+            block_time,
+            account_start_nonce,
+            genesis_accounts: spec
+                .genesis_state()
+                .get()
+                .keys()
+                .chain(genesis_alloc.keys())
+                .cloned()
+                .collect(),
+        })
     }
 
     pub fn get_block_by_number(&self, number: u64) -> Option<EthereumBlock> {
-        self.block_number_to_hash
-            .get(&number)
-            .and_then(|hash| self.blocks.get(hash))
-            .cloned()
+        match self.block_number_to_hash.get(&number) {
+            Some(hash) => self.blocks.get(hash).cloned(),
+            None if number <= self.block_number => Some(self.synthetic_block(number)),
+            None => None,
+        }
     }
-}
 
-/// Simulated blockchain.
-pub struct Blockchain {
-    gas_price: U256,
-    block_gas_limit: U256,
-    simulator_pool: Arc<ThreadPool>,
-    km_client: Arc<MockClient>,
-    chain_state: Arc<RwLock<ChainState>>,
-}
+    pub fn get_block_by_hash(&self, hash: &H256) -> Option<EthereumBlock> {
+        if let Some(block) = self.blocks.get(hash) {
+            return Some(block.clone());
+        }
 
-impl Blockchain {
-    /// Create new simulated blockchain.
-    pub fn new(gas_price: U256, block_gas_limit: U256, km_client: Arc<MockClient>) -> Self {
-        Self {
-            gas_price,
-            block_gas_limit,
-            simulator_pool: Arc::new(
-                ThreadPoolBuilder::new()
-                    .name_prefix("simulator-pool-")
-                    .build(),
-            ),
-            km_client,
-            chain_state: Arc::new(RwLock::new(ChainState::new())),
+        synthetic_block_number(hash)
+            .filter(|number| *number <= self.block_number)
+            .filter(|number| !self.block_number_to_hash.contains_key(number))
+            .map(|number| self.synthetic_block(number))
+    }
+
+    /// Hash of the block with the given number, if it exists.
+    fn block_hash(&self, number: u64) -> Option<H256> {
+        match self.block_number_to_hash.get(&number) {
+            Some(hash) => Some(*hash),
+            None if number <= self.block_number => Some(synthetic_block_hash(number)),
+            None => None,
         }
     }
 
-    /// Ethereum state snapshot at given block.
-    pub fn state(&self, _id: BlockId) -> Fallible<State<NullBackend>> {
-        let chain_state = self.chain_state.read().unwrap();
+    /// Empty block skipped by `evm_setBlockNumber`.
+    ///
+    /// It is derived from the last mined block before it, with timestamps one
+    /// second apart.
+    fn synthetic_block(&self, number: u64) -> EthereumBlock {
+        let (_, base_hash) = self
+            .block_number_to_hash
+            .range(..number)
+            .next_back()
+            .expect("genesis block must exist");
+        let base = &self.blocks[base_hash];
+
+        EthereumBlock {
+            hash: synthetic_block_hash(number),
+            ..EthereumBlock::new(
+                number,
+                self.block_hash(number - 1)
+                    .expect("parent block must exist"),
+                base.author,
+                base.timestamp + (number - base.number),
+                U256::zero(),
+                base.gas_limit,
+                Default::default(),
+            )
+        }
+    }
+
+    /// Number of the given block, which must exist.
+    fn resolve_block_number(&self, id: BlockId) -> Fallible<u64> {
+        match id {
+            BlockId::Hash(hash) => self
+                .get_block_by_hash(&hash)
+                .map(|blk| blk.number)
+                .ok_or_else(|| format_err!("block not found")),
+            BlockId::Number(number) if number > self.block_number => {
+                Err(format_err!("block not found"))
+            }
+            BlockId::Number(number) => Ok(number),
+            BlockId::Latest => Ok(self.block_number),
+            BlockId::Earliest => Ok(0),
+        }
+    }
+
+    /// Resolve the inclusive block range of a log filter.
+    ///
+    /// An end block past the best block is clamped to it, and a start block
+    /// past the best block yields an empty range rather than an error.
+    fn resolve_log_range(&self, filter: &Filter) -> Fallible<(u64, u64)> {
+        let from_block = match filter.from_block {
+            BlockId::Number(number) => number,
+            id => self.resolve_block_number(id)?,
+        };
+        let to_block = match filter.to_block {
+            BlockId::Number(number) => cmp::min(number, self.block_number),
+            id => self.resolve_block_number(id)?,
+        };
+        Ok((from_block, to_block))
+    }
+
+    /// Hashes of the most recent blocks up to the given block, backwards, as
+    /// seen by the block after it.
+    fn last_hashes(&self, number: u64) -> Arc<Vec<H256>> {
+        let first = (number + 1).saturating_sub(LAST_HASHES_COUNT);
+        Arc::new(
+            (first..=number)
+                .rev()
+                .map(|number| self.block_hash(number).expect("block must exist"))
+                .collect(),
+        )
+    }
+
+    /// Ethereum state at the best block.
+    fn best_state(&self) -> Fallible<State<NullBackend>> {
+        self.state_at(self.block_number)
+    }
 
-        // TODO: support previous block states
+    /// Ethereum state after the given block.
+    fn state_at(&self, number: u64) -> Fallible<State<NullBackend>> {
         Ok(State::from_existing(
-            Box::new(chain_state.mkvs.clone()),
+            Box::new(self.state_view(number)),
             NullBackend,
-            U256::zero(),       /* account_start_nonce */
+            self.account_start_nonce,
             Default::default(), /* factories */
             None,               /* confidential_ctx */
         )?)
     }
 
-    /// Gas price.
-    pub fn gas_price(&self) -> U256 {
-        self.gas_price
-    }
+    /// Template of the block following the best block.
+    fn block_template(&self, author: Address, gas_limit: U256) -> BlockTemplate {
+        let parent = self
+            .get_block_by_number(self.block_number)
+            .expect("must have a best block");
 
-    /// Retrieve an Ethereum block given a block identifier.
-    pub fn get_block(
-        &self,
-        id: BlockId,
-    ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
-        let block: BoxFuture<Option<EthereumBlock>> = match id {
-            BlockId::Hash(hash) => Box::new(self.get_block_by_hash(hash)),
-            BlockId::Number(number) => Box::new(self.get_block_by_number(number)),
-            BlockId::Latest => Box::new(self.get_latest_block().map(|blk| Some(blk))),
-            BlockId::Earliest => Box::new(self.get_block_by_number(0)),
-        };
+        BlockTemplate {
+            parent_hash: parent.hash,
+            state_version: self.state_version,
+            next_timestamp: self.next_timestamp,
+            mkvs: self.state_view(self.block_number),
+            account_start_nonce: self.account_start_nonce,
+            env_info: EnvInfo {
+                number: self.block_number + 1,
+                author,
+                timestamp: self.next_block_timestamp(),
+                difficulty: Default::default(),
+                gas_limit,
+                last_hashes: self.last_hashes(self.block_number),
+                gas_used: Default::default(),
+            },
+        }
+    }
 
-        block
+    /// View of the state storage after the given block.
+    fn state_view(&self, number: u64) -> MemoryMKVS {
+        let (_, version) = self
+            .block_state_versions
+            .range(..=number)
+            .next_back()
+            .expect("genesis state must exist");
+        self.mkvs.at(*version)
     }
 
-    /// The current best block number.
-    pub fn best_block_number(&self) -> u64 {
-        let chain_state = self.chain_state.read().unwrap();
-        chain_state.block_number
+    /// Whether the best block and state are still those a block template was
+    /// created from.
+    fn is_parent_of(&self, template: &BlockTemplate) -> bool {
+        self.block_number + 1 == template.env_info.number
+            && self.state_version == template.state_version
+            && self.next_timestamp == template.next_timestamp
     }
 
-    /// Retrieve an Ethereum block given a block identifier.
-    ///
-    /// If the block is not found it returns an error.
-    pub fn get_block_unwrap(
-        &self,
-        id: BlockId,
-    ) -> impl Future<Item = EthereumBlock, Error = Error> {
-        self.get_block(id).and_then(|blk| match blk {
-            Some(blk) => Ok(blk),
-            None => Err(format_err!("block not found")),
-        })
+    /// Commit the writes of a state storage view as a new state version.
+    fn commit_state_version(&mut self, mkvs: &MemoryMKVS) -> u64 {
+        self.state_version += 1;
+        mkvs.commit(self.state_version);
+        self.state_version
     }
 
-    /// Retrieve the latest Ethereum block.
-    pub fn get_latest_block(&self) -> impl Future<Item = EthereumBlock, Error = Error> {
-        let chain_state = self.chain_state.read().unwrap();
+    /// Apply direct changes to the state of the best block, without mining a
+    /// block. The state of earlier blocks is left untouched.
+    fn update_best_state<F>(
+        &mut self,
+        confidential_ctx: Option<ConfidentialCtx>,
+        update: F,
+    ) -> Fallible<()>
+    where
+        F: FnOnce(&mut State<NullBackend>) -> Fallible<()>,
+    {
+        let mkvs = self.state_view(self.block_number);
+        let mut state = State::from_existing(
+            Box::new(mkvs.clone()),
+            NullBackend,
+            self.account_start_nonce,
+            Default::default(), /* factories */
+            confidential_ctx.map(|ctx| Box::new(ctx) as Box<dyn EthConfidentialCtx>),
+        )?;
+        update(&mut state)?;
+        state.commit().map_err(|err| format_err!("{}", err))?;
 
-        future::ok(
-            chain_state
-                .get_block_by_number(chain_state.block_number)
-                .expect("best block must exist"),
-        )
+        let version = self.commit_state_version(&mkvs);
+        self.block_state_versions.insert(self.block_number, version);
+        Ok(())
     }
 
-    /// Retrieve a specific Ethereum block, identified by its number.
-    pub fn get_block_by_number(
-        &self,
-        number: u64,
-    ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
-        let chain_state = self.chain_state.read().unwrap();
+    /// Nonce expected for the next transaction of the sender, accounting for
+    /// its transactions in the pending buffer.
+    fn next_nonce(&self, sender: &Address) -> Fallible<U256> {
+        let pending = self
+            .pending
+            .iter()
+            .filter(|txn| txn.sender() == *sender)
+            .count();
+        Ok(self.best_state()?.nonce(sender)? + U256::from(pending))
+    }
 
-        future::ok(chain_state.get_block_by_number(number))
+    /// Move the pending transactions which no longer continue their sender's
+    /// nonce sequence, e.g., after a rewind lowered its nonce, to the queued
+    /// transactions, until the gap is filled.
+    fn requeue_pending(&mut self) -> Fallible<()> {
+        let pending = mem::replace(&mut self.pending, vec![]);
+        for txn in pending {
+            let sender = txn.sender();
+            if txn.nonce == self.next_nonce(&sender)? {
+                self.pending.push(txn);
+            } else {
+                self.queued
+                    .entry(sender)
+                    .or_insert_with(BTreeMap::new)
+                    .insert(txn.nonce, txn);
+            }
+        }
+        Ok(())
     }
 
-    /// Retrieve a specific Ethereum block, identified by its block hash.
-    pub fn get_block_by_hash(
-        &self,
-        hash: H256,
-    ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
-        let chain_state = self.chain_state.read().unwrap();
+    /// Timestamp of the block that would be mined next.
+    ///
+    /// Block timestamps are strictly increasing: unless a timestamp was forced
+    /// for the next block, the wall clock is used, bumped to one second past
+    /// the parent if it has not advanced yet. In deterministic time, the
+    /// block is a fixed step past its parent instead.
+    fn next_block_timestamp(&self) -> u64 {
+        if let Some(timestamp) = self.next_timestamp {
+            return timestamp;
+        }
 
-        future::ok(chain_state.blocks.get(&hash).cloned())
+        let parent = self
+            .get_block_by_number(self.block_number)
+            .expect("best block must exist");
+        // Saturate rather than overflow past a timestamp forced near the
+        // end of time.
+        match self.block_time {
+            BlockTime::WallClock => {
+                cmp::max(util::get_timestamp(), parent.timestamp().saturating_add(1))
+            }
+            BlockTime::Deterministic { step, .. } => parent.timestamp().saturating_add(step),
+        }
     }
 
-    /// Retrieve a specific Ethereum transaction, identified by its transaction hash.
-    pub fn get_txn_by_hash(
-        &self,
-        hash: H256,
-    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
-        let chain_state = self.chain_state.read().unwrap();
+    /// Discard the blocks after the given one, along with their transactions,
+    /// logs and states, making it the best block.
+    fn rewind(&mut self, head: u64) {
+        let discarded = self.block_number_to_hash.split_off(&(head + 1));
+        for hash in discarded.values() {
+            let block = match self.blocks.remove(hash) {
+                Some(block) => block,
+                None => continue,
+            };
+            for txn in &block.transactions {
+                let txn_hash = txn.hash();
+                self.transactions.remove(&txn_hash);
+                self.receipts.remove(&txn_hash);
+                self.failure_reasons.remove(&txn_hash);
+            }
+        }
+        let transactions = &self.transactions;
+        self.contract_creations
+            .retain(|_, txn_hash| transactions.contains_key(txn_hash));
+        self.logs_by_address.retain(|_, positions| {
+            positions.retain(|(number, _)| *number <= head);
+            !positions.is_empty()
+        });
 
-        future::ok(chain_state.transactions.get(&hash).cloned())
+        // Versions stay monotonic, so the state of a discarded block is never
+        // mistaken for a later one.
+        self.block_state_versions.split_off(&(head + 1));
+        let (_, version) = self
+            .block_state_versions
+            .range(..=head)
+            .next_back()
+            .expect("genesis state must exist");
+        self.mkvs.truncate(*version);
+        self.block_number = head;
     }
 
-    /// Retrieve a specific Ethereum transaction receipt, identified by its transaction
-    /// hash.
-    pub fn get_txn_receipt_by_hash(
-        &self,
-        hash: H256,
-    ) -> impl Future<Item = Option<LocalizedReceipt>, Error = Error> {
-        let chain_state = self.chain_state.read().unwrap();
+    /// Add the given logs to the address index.
+    fn index_logs(&mut self, logs: &[LocalizedLogEntry]) {
+        for log in logs {
+            self.logs_by_address
+                .entry(log.entry.address)
+                .or_insert_with(Vec::new)
+                .push((log.block_number, log.log_index));
+        }
+    }
 
-        future::ok(chain_state.receipts.get(&hash).cloned())
+    /// Retrieve the logs matching the filter within the block range by
+    /// scanning each block whose bloom may contain matching logs.
+    fn scan_logs(&self, from_block: u64, to_block: u64, filter: &Filter) -> Vec<LocalizedLogEntry> {
+        let blooms = filter.bloom_possibilities();
+
+        // Blocks skipped by `evm_setBlockNumber` have no logs.
+        self.block_number_to_hash
+            .range(from_block..=to_block)
+            .map(|(_, hash)| &self.blocks[hash])
+            .filter(|blk| {
+                blooms
+                    .iter()
+                    .any(|bloom| blk.log_bloom.contains_bloom(bloom))
+            })
+            .flat_map(|blk| blk.logs.iter().filter(|log| filter.matches(log)).cloned())
+            .collect()
     }
 
-    /// Retrieve a specific Ethereum transaction, identified by the block round and
-    /// transaction index within the block.
-    pub fn get_txn_by_number_and_index(
+    /// Retrieve the logs emitted by any of the given addresses within the
+    /// block range, touching only the indexed entries.
+    fn indexed_logs(
         &self,
-        number: u64,
-        index: u32,
-    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
-        let chain_state = self.chain_state.read().unwrap();
+        addresses: &[Address],
+        from_block: u64,
+        to_block: u64,
+    ) -> Vec<LocalizedLogEntry> {
+        let mut addresses = addresses.to_vec();
+        addresses.sort();
+        addresses.dedup();
 
-        future::ok(
-            chain_state
-                .block_number_to_hash
-                .get(&number)
-                .and_then(|hash| chain_state.blocks.get(hash))
-                .and_then(|blk| blk.transactions.get(index as usize))
-                .cloned(),
-        )
+        addresses
+            .iter()
+            .filter_map(|address| self.logs_by_address.get(address))
+            .flat_map(|positions| {
+                // Positions are sorted, so skip straight to the first block in range.
+                let start = match positions.binary_search(&(from_block, 0)) {
+                    Ok(idx) | Err(idx) => idx,
+                };
+
+                positions[start..]
+                    .iter()
+                    .take_while(|&&(number, _)| number <= to_block)
+                    .map(|&(number, log_index)| {
+                        self.block_number_to_hash
+                            .get(&number)
+                            .and_then(|hash| self.blocks.get(hash))
+                            .and_then(|blk| blk.logs.get(log_index))
+                            .cloned()
+                            .expect("indexed log should exist")
+                    })
+            })
+            .collect()
     }
+}
 
-    /// Retrieve a specific Ethereum transaction, identified by the block hash and
-    /// transaction index within the block.
-    pub fn get_txn_by_block_hash_and_index(
-        &self,
-        block_hash: H256,
-        index: u32,
-    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
-        let chain_state = self.chain_state.read().unwrap();
+/// Block whose state a query runs against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateId {
+    /// The state after the given block.
+    Block(BlockId),
+    /// The state of the pending block, i.e. the best state with the pending
+    /// transactions applied as if the block was mined now.
+    Pending,
+}
 
-        future::ok(
-            chain_state
-                .blocks
-                .get(&block_hash)
-                .and_then(|blk| blk.transactions.get(index as usize))
-                .cloned(),
-        )
+impl From<BlockId> for StateId {
+    fn from(id: BlockId) -> Self {
+        StateId::Block(id)
     }
+}
 
-    /// Retrieve a specific Ethereum transaction, identified by a block identifier
-    /// and transaction index within the block.
-    pub fn get_txn(
-        &self,
-        id: BlockId,
-        index: u32,
-    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
-        let txn: BoxFuture<Option<LocalizedTransaction>> = match id {
-            BlockId::Hash(hash) => Box::new(self.get_txn_by_block_hash_and_index(hash, index)),
-            BlockId::Number(number) => Box::new(self.get_txn_by_number_and_index(number, index)),
-            BlockId::Latest => {
-                Box::new(self.get_txn_by_number_and_index(self.best_block_number(), index))
-            }
-            BlockId::Earliest => Box::new(self.get_txn_by_number_and_index(0, index)),
-        };
+/// Block production mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MiningMode {
+    /// Mine a block as soon as a transaction is submitted.
+    Auto,
+    /// Mine a block with the pending transactions at a fixed interval.
+    Interval(Duration),
+    /// Only mine blocks on request (see `evm_mine`).
+    Manual,
+}
 
-        txn
+/// Timer of interval mining, if in interval mining mode.
+fn new_mining_interval(mining_mode: MiningMode) -> Option<Interval> {
+    match mining_mode {
+        MiningMode::Interval(period) => Some(Interval::new(Instant::now() + period, period)),
+        MiningMode::Auto | MiningMode::Manual => None,
     }
+}
 
-    /// Submit a raw Ethereum transaction to the chain.
-    pub fn send_raw_transaction(
-        &self,
-        raw: Vec<u8>,
-    ) -> impl Future<Item = (H256, ExecutionResult), Error = Error> {
-        // Decode transaction.
-        let decoded: UnverifiedTransaction = match rlp::decode(&raw) {
-            Ok(t) => t,
-            Err(_) => return Err(format_err!("Could not decode transaction")).into_future(),
-        };
+/// Interval mining task, restarting its timer whenever the mining mode
+/// changes.
+struct IntervalMining {
+    blockchain: Arc<Blockchain>,
+    updates: UnboundedReceiver<MiningMode>,
+    interval: Option<Interval>,
+}
 
-        // Check that gas < block gas limit.
-        if decoded.as_unsigned().gas > self.block_gas_limit {
-            return Err(format_err!("Requested gas greater than block gas limit")).into_future();
-        }
+impl Future for IntervalMining {
+    type Item = ();
+    type Error = ();
 
-        // Check signature.
-        let txn = match SignedTransaction::new(decoded.clone()) {
-            Ok(t) => t,
-            Err(_) => return Err(format_err!("Invalid signature")).into_future(),
-        };
+    fn poll(&mut self) -> Poll<(), ()> {
+        while let Async::Ready(update) = self.updates.poll()? {
+            match update {
+                Some(mining_mode) => self.interval = new_mining_interval(mining_mode),
+                None => return Ok(Async::Ready(())),
+            }
+        }
 
-        // Check gas price.
-        if txn.gas_price < self.gas_price.into() {
-            return Err(format_err!("Insufficient gas price")).into_future();
+        if let Some(interval) = self.interval.as_mut() {
+            while let Async::Ready(_) = interval
+                .poll()
+                .map_err(|err| error!("Interval mining timer error: {:?}", err))?
+            {
+                self.blockchain.mine_pending_block();
+            }
         }
 
-        // Mine a block with the transaction.
-        future::done(self.mine_block(txn))
+        Ok(Async::NotReady)
     }
+}
 
-    /// Mine a block containing the transaction.
-    fn mine_block(&self, txn: SignedTransaction) -> Result<(H256, ExecutionResult), Error> {
-        let mut chain_state = self.chain_state.write().unwrap();
+/// Logging of mined blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MiningLog {
+    /// Do not log mined blocks or queued transactions.
+    Quiet,
+    /// Log a line for every mined transaction.
+    Normal,
+    /// Log a single JSON record for every mined block.
+    Structured,
+}
 
-        // Initialize Ethereum state access functions.
-        let best_block = chain_state
-            .get_block_by_number(chain_state.block_number)
-            .expect("must have a best block");
-        let mut state = State::from_existing(
-            Box::new(chain_state.mkvs.clone()),
-            NullBackend,
-            U256::zero(),       /* account_start_nonce */
-            Default::default(), /* factories */
-            Some(Box::new(ConfidentialCtx::new(
-                best_block.hash,
-                self.km_client.clone(),
-            ))),
-        )
-        .expect("state initialization must succeed");
+/// Source of block timestamps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockTime {
+    /// Blocks are timestamped with the wall clock. The genesis block has
+    /// timestamp zero.
+    WallClock,
+    /// Blocks are timestamped `step` seconds after their parent, starting from
+    /// the genesis timestamp, regardless of the wall clock, so that chains are
+    /// reproducible.
+    Deterministic { genesis_timestamp: u64, step: u64 },
+}
 
-        // Initialize Ethereum environment information.
-        let number = chain_state.block_number + 1;
-        let timestamp = util::get_timestamp();
-        let env_info = EnvInfo {
-            number,
-            author: Default::default(),
-            timestamp,
-            difficulty: Default::default(),
-            gas_limit: self.block_gas_limit,
-            // TODO: Get 256 last_hashes.
-            last_hashes: Arc::new(vec![best_block.hash]),
-            gas_used: Default::default(),
-        };
+impl Default for BlockTime {
+    fn default() -> Self {
+        BlockTime::WallClock
+    }
+}
 
-        // Execute the transaction.
-        let outcome =
-            match state.apply(&env_info, genesis::SPEC.engine.machine(), &txn, false, true) {
-                Ok(outcome) => outcome,
-                Err(err) => return Err(format_err!("{}", err)),
-            };
+/// Structured log record of a mined block.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MinedBlockRecord {
+    number: u64,
+    hash: H256,
+    gas_used: U256,
+    transactions: Vec<MinedTransactionRecord>,
+}
 
-        // Commit the state updates.
-        state.commit().expect("state commit must succeed");
+/// Structured log record of a mined transaction.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MinedTransactionRecord {
+    hash: H256,
+    gas_used: U256,
+}
 
-        // Create a block.
-        let mut block = EthereumBlock::new(
-            number,
-            best_block.hash,
-            timestamp,
-            outcome.receipt.gas_used,
-            self.block_gas_limit,
-            outcome.receipt.log_bloom,
-        );
-        let block_hash = block.hash();
-        chain_state.block_number = number;
+/// Parent and environment of a block to be mined.
+struct BlockTemplate {
+    parent_hash: H256,
+    /// State version of the parent, to detect direct state changes.
+    state_version: u64,
+    /// Timestamp forced for the block, to detect changes of it.
+    next_timestamp: Option<u64>,
+    /// View of the parent's state, receiving the state updates of the block.
+    mkvs: MemoryMKVS,
+    account_start_nonce: U256,
+    env_info: EnvInfo,
+}
 
-        // Store the txn.
-        let txn_hash = txn.hash();
-        let localized_txn = LocalizedTransaction {
-            signed: txn.clone().into(),
-            block_number: number,
-            block_hash,
-            transaction_index: 0,
-            cached_sender: None,
-        };
-        block.transactions = vec![localized_txn.clone()];
-        chain_state.transactions.insert(txn_hash, localized_txn);
-
-        // Store the logs.
-        let logs: Vec<LocalizedLogEntry> = outcome
-            .receipt
-            .logs
-            .clone()
-            .into_iter()
-            .enumerate()
-            .map(|(i, log)| LocalizedLogEntry {
-                entry: log,
-                block_hash: block_hash,
-                block_number: number,
-                transaction_hash: txn_hash,
-                transaction_index: 0,
-                transaction_log_index: i,
-                log_index: i,
-            })
-            .collect();
-        block.logs = logs.clone();
-
-        // Store the receipt.
-        let localized_receipt = LocalizedReceipt {
-            transaction_hash: txn_hash,
-            transaction_index: 0,
-            block_hash: block_hash,
-            block_number: number,
-            cumulative_gas_used: outcome.receipt.gas_used,
-            gas_used: outcome.receipt.gas_used,
-            contract_address: match txn.action {
-                Action::Call(_) => None,
-                Action::Create => Some(
-                    contract_address(
-                        genesis::SPEC.engine.create_address_scheme(number),
-                        &txn.sender(),
-                        &txn.nonce,
-                        &txn.data,
-                    )
-                    .0,
-                ),
-            },
-            logs: logs,
-            log_bloom: outcome.receipt.log_bloom,
-            outcome: outcome.receipt.outcome.clone(),
-        };
-        chain_state.receipts.insert(txn_hash, localized_receipt);
+/// Block whose transactions were executed, but which is not sealed yet.
+struct ExecutedBlock {
+    template: BlockTemplate,
+    gas_used: U256,
+    results: Vec<Result<(H256, ExecutionResult), Error>>,
+    /// Applied transactions with their receipt, gas used and failure reason.
+    applied: Vec<(SignedTransaction, Receipt, U256, Option<String>)>,
+}
 
-        // Store the block.
-        chain_state.blocks.insert(block_hash, block.clone());
-        chain_state.block_number_to_hash.insert(number, block_hash);
+/// Configuration of a simulated blockchain (see `Blockchain::new`).
+#[derive(Clone, Debug)]
+pub struct BlockchainConfig {
+    /// Gas price reported by `eth_gasPrice` and used for simulated calls.
+    pub gas_price: U256,
+    /// Gas limit of mined blocks.
+    pub block_gas_limit: U256,
+    /// Gas available to a simulated call, regardless of the block gas limit.
+    pub call_gas_cap: U256,
+    /// Time after which a simulated call is aborted.
+    pub call_timeout: Duration,
+    /// Number of threads simulating calls, which bounds how many run at once.
+    /// It must be positive.
+    pub simulator_threads: usize,
+    /// Raw transactions larger than this many bytes are rejected before
+    /// being decoded, regardless of the gas they would pay for their data.
+    pub max_transaction_size: usize,
+    /// Account credited with the block reward.
+    pub coinbase: Address,
+    /// Reward credited to the coinbase for every mined block.
+    pub block_reward: U256,
+    pub mining_mode: MiningMode,
+    /// In auto mining mode, transactions are mined this long after they are
+    /// submitted (see `delayed_instamine`), or right away if it is zero.
+    pub instamine_delay: Duration,
+    /// Maximum number of blocks a log query may span.
+    pub max_logs_block_range: u64,
+    pub mining_log: MiningLog,
+    /// Source of the timestamps of mined blocks.
+    pub block_time: BlockTime,
+    /// Overrides the account start nonce of the spec. It is the nonce of the
+    /// first transaction of every account, so it also determines the address
+    /// of the first contract an account deploys.
+    pub account_start_nonce: Option<U256>,
+    /// Accounts written into the genesis state on top of the spec's accounts.
+    pub genesis_alloc: StateOverride,
+}
 
-        // Return the ExecutionResult.
-        let result = ExecutionResult {
-            cumulative_gas_used: outcome.receipt.gas_used,
-            gas_used: outcome.receipt.gas_used,
-            log_bloom: outcome.receipt.log_bloom,
-            logs: outcome.receipt.logs,
-            status_code: match outcome.receipt.outcome {
-                TransactionOutcome::StatusCode(code) => code,
-                _ => unreachable!("we always use EIP-658 semantics"),
-            },
-            output: outcome.output.into(),
-        };
+impl Default for BlockchainConfig {
+    fn default() -> Self {
+        BlockchainConfig {
+            gas_price: util::gwei_to_wei(MIN_GAS_PRICE_GWEI as u64),
+            block_gas_limit: BLOCK_GAS_LIMIT.into(),
+            call_gas_cap: CALL_GAS_CAP.into(),
+            call_timeout: CALL_TIMEOUT,
+            simulator_threads: default_simulator_threads(),
+            max_transaction_size: MAX_TRANSACTION_SIZE,
+            coinbase: Address::zero(),
+            block_reward: U256::zero(),
+            mining_mode: MiningMode::Auto,
+            instamine_delay: Duration::from_secs(0),
+            max_logs_block_range: MAX_LOGS_BLOCK_RANGE,
+            mining_log: MiningLog::Normal,
+            block_time: BlockTime::WallClock,
+            account_start_nonce: None,
+            genesis_alloc: StateOverride::new(),
+        }
+    }
+}
 
-        info!(
-            "Mined block number {:?} containing transaction {:?}. Gas used: {:?}",
-            number, txn_hash, result.gas_used
-        );
+/// Simulated blockchain.
+pub struct Blockchain {
+    gas_price: U256,
+    block_gas_limit: U256,
+    call_gas_cap: U256,
+    call_timeout: Duration,
+    max_transaction_size: usize,
+    coinbase: Address,
+    block_reward: U256,
+    mining_mode: RwLock<MiningMode>,
+    mining_mode_updates: UnboundedSender<MiningMode>,
+    mining_mode_receiver: Mutex<Option<UnboundedReceiver<MiningMode>>>,
+    instamine_delay: Duration,
+    instamine_requests: UnboundedSender<Instant>,
+    instamine_deadlines: Mutex<Option<UnboundedReceiver<Instant>>>,
+    max_logs_block_range: u64,
+    mining_log: MiningLog,
+    spec: Arc<Spec>,
+    simulator_pool: Arc<ThreadPool>,
+    /// Runs the timeouts of simulations, which must not wait for a busy
+    /// simulator thread.
+    simulator_timer: Mutex<Runtime>,
+    km_client: Arc<MockClient>,
+    chain_state: Arc<RwLock<ChainState>>,
+}
 
-        Ok((txn_hash, result))
+impl Blockchain {
+    /// Create new simulated blockchain.
+    ///
+    /// The `spec` provides the genesis state and the hardfork transitions.
+    pub fn new(config: BlockchainConfig, spec: Spec, km_client: Arc<MockClient>) -> Fallible<Self> {
+        if config.simulator_threads == 0 {
+            return Err(format_err!("the simulator pool needs at least one thread"));
+        }
+        let account_start_nonce = config
+            .account_start_nonce
+            .unwrap_or(spec.params().account_start_nonce);
+        if let BlockTime::Deterministic { step: 0, .. } = config.block_time {
+            return Err(format_err!("block time step must be positive"));
+        }
+        let chain_state = ChainState::new(
+            &spec,
+            config.block_time,
+            account_start_nonce,
+            &config.genesis_alloc,
+        )?;
+        let (mining_mode_updates, mining_mode_receiver) = unbounded();
+        let (instamine_requests, instamine_deadlines) = unbounded();
+
+        Ok(Self {
+            gas_price: config.gas_price,
+            block_gas_limit: config.block_gas_limit,
+            call_gas_cap: config.call_gas_cap,
+            call_timeout: config.call_timeout,
+            max_transaction_size: config.max_transaction_size,
+            coinbase: config.coinbase,
+            block_reward: config.block_reward,
+            mining_mode: RwLock::new(config.mining_mode),
+            mining_mode_updates,
+            mining_mode_receiver: Mutex::new(Some(mining_mode_receiver)),
+            instamine_delay: config.instamine_delay,
+            instamine_requests,
+            instamine_deadlines: Mutex::new(Some(instamine_deadlines)),
+            max_logs_block_range: config.max_logs_block_range,
+            mining_log: config.mining_log,
+            spec: Arc::new(spec),
+            simulator_pool: Arc::new(
+                ThreadPoolBuilder::new()
+                    .pool_size(config.simulator_threads)
+                    .name_prefix("simulator-pool-")
+                    .build(),
+            ),
+            simulator_timer: Mutex::new(
+                RuntimeBuilder::new()
+                    .core_threads(1)
+                    .name_prefix("simulator-timer-")
+                    .build()?,
+            ),
+            km_client,
+            chain_state: Arc::new(RwLock::new(chain_state)),
+        })
     }
 
-    /// Simulate a transaction against a given block.
+    /// Ethereum state snapshot at given block, or of the pending block.
+    pub fn state(&self, id: impl Into<StateId>) -> Fallible<State<NullBackend>> {
+        let chain_state = self.chain_state.read().unwrap();
+        let number = match id.into() {
+            StateId::Block(id) => chain_state.resolve_block_number(id)?,
+            StateId::Pending => {
+                let (_, mkvs) = self.pending_state_view(&chain_state);
+                return Ok(State::from_existing(
+                    Box::new(mkvs),
+                    NullBackend,
+                    chain_state.account_start_nonce,
+                    Default::default(), /* factories */
+                    None,               /* confidential_ctx */
+                )?);
+            }
+        };
+        chain_state.state_at(number)
+    }
+
+    /// View of the state of the pending block, along with the number of its
+    /// parent, the best block.
     ///
-    /// The simulated transaction is executed in a dedicated thread pool to
-    /// avoid blocking I/O processing.
+    /// The pending transactions are executed on top of the best state, as
+    /// they would be if the block was mined now, including the block reward.
+    /// Those which cannot be applied are left out.
+    fn pending_state_view(&self, chain_state: &ChainState) -> (u64, MemoryMKVS) {
+        let template = self.block_template(chain_state);
+        let executed = self.execute_block(template, chain_state.pending.clone());
+        (chain_state.block_number, executed.template.mkvs)
+    }
+
+    /// Advance the best block number without mining, returning it.
     ///
-    /// # Notes
+    /// The skipped blocks are empty, synthesized on demand, and share the
+    /// state of the previous best block. Their timestamps are one second
+    /// apart. The block number cannot be decreased, and must leave room for
+    /// the numbers and timestamps of the next blocks.
+    pub fn set_block_number(&self, number: u64) -> Fallible<u64> {
+        let mut chain_state = self.chain_state.write().unwrap();
+        if number < chain_state.block_number {
+            return Err(format_err!(
+                "block number cannot be decreased (current block number is {})",
+                chain_state.block_number
+            ));
+        }
+        if number >= u64::max_value() - 1 {
+            return Err(format_err!(
+                "block number must be below {}",
+                u64::max_value() - 1
+            ));
+        }
+        let best_timestamp = chain_state
+            .get_block_by_number(chain_state.block_number)
+            .expect("best block must exist")
+            .timestamp();
+        if best_timestamp
+            .checked_add(number - chain_state.block_number + 1)
+            .is_none()
+        {
+            return Err(format_err!(
+                "timestamps of the skipped blocks would overflow"
+            ));
+        }
+
+        chain_state.block_number = number;
+        Ok(number)
+    }
+
+    /// Discard the top `depth` blocks and mine `replacements` empty blocks in
+    /// their place, returning the new best block number.
     ///
-    /// Confidential contracts are not supported.
-    pub fn simulate_transaction(
-        &self,
-        transaction: SignedTransaction,
-        _id: BlockId,
-    ) -> impl Future<Item = Executed, Error = CallError> {
-        let simulator_pool = self.simulator_pool.clone();
-        let chain_state = self.chain_state.clone();
+    /// The transactions of the discarded blocks are dropped, and blocks mined
+    /// from then on get new hashes. The genesis block cannot be discarded.
+    /// Pending transactions whose sender's nonce the discarded transactions
+    /// advanced are queued until the nonce gap is filled again.
+    pub fn reorg(&self, depth: u64, replacements: u64) -> Fallible<u64> {
+        let mut chain_state = self.chain_state.write().unwrap();
+        if depth == 0 {
+            return Err(format_err!("reorg depth must be positive"));
+        }
+        if depth > chain_state.block_number {
+            return Err(format_err!(
+                "reorg depth cannot exceed the best block number ({})",
+                chain_state.block_number
+            ));
+        }
 
-        // Execute simulation in a dedicated thread pool to avoid blocking
-        // I/O processing with simulations.
-        simulator_pool.spawn_handle(future::lazy(move || {
-            let chain_state = chain_state.read().unwrap();
+        let head = chain_state.block_number - depth;
+        chain_state.rewind(head);
+        chain_state.requeue_pending()?;
+        chain_state.reorgs.push(head);
+        if self.mining_log != MiningLog::Quiet {
+            info!("Reorganized chain to block number {}", head);
+        }
+        for _ in 0..replacements {
+            self.mine_block(&mut chain_state, vec![], true, false);
+        }
 
-            let best_block = chain_state
-                .get_block_by_number(chain_state.block_number)
-                .expect("must have a best block");
+        Ok(chain_state.block_number)
+    }
 
-            let env_info = EnvInfo {
-                number: chain_state.block_number + 1,
-                author: Default::default(),
-                timestamp: util::get_timestamp(),
-                difficulty: Default::default(),
-                // TODO: Get 256 last hashes.
-                last_hashes: Arc::new(vec![best_block.hash]),
-                gas_used: Default::default(),
-                gas_limit: U256::max_value(),
-            };
-            let machine = genesis::SPEC.engine.machine();
-            let options = TransactOptions::with_no_tracing()
-                .dont_check_nonce()
-                .save_output_from_contract();
-            let mut state = State::from_existing(
-                Box::new(chain_state.mkvs.clone()),
-                NullBackend,
-                U256::zero(),       /* account_start_nonce */
-                Default::default(), /* factories */
-                None,               /* confidential_ctx */
-            )
-            .expect("state initialization must succeed");
+    /// Best block numbers left by the reorgs after the first `skip` ones.
+    pub fn reorgs_since(&self, skip: usize) -> Vec<u64> {
+        let chain_state = self.chain_state.read().unwrap();
+        chain_state.reorgs.iter().skip(skip).cloned().collect()
+    }
 
-            Ok(Executive::new(&mut state, &env_info, machine)
-                .transact_virtual(&transaction, options)?)
-        }))
+    /// Set the balance of an account in the latest state, without mining a
+    /// block.
+    pub fn set_balance(&self, address: Address, balance: U256) -> Fallible<()> {
+        let mut chain_state = self.chain_state.write().unwrap();
+        chain_state.update_best_state(None, |state| state.set_balance(&address, &balance))
     }
 
-    /// Estimates gas against a given block.
-    ///
-    /// Uses `simulate_transaction` internally.
-    ///
-    /// # Notes
-    ///
-    /// Confidential contracts are not supported.
-    pub fn estimate_gas(
-        &self,
-        transaction: SignedTransaction,
-        id: BlockId,
-    ) -> impl Future<Item = U256, Error = CallError> {
-        self.simulate_transaction(transaction, id)
-            .inspect(|executed| match &executed.exception {
-                Some(VmError::Reverted) | Some(VmError::OutOfGas) => {
-                    eprintln!("vm error: {:?}", executed.exception.as_ref().unwrap());
-                }
-                _ => {}
-            })
-            .map(|executed| executed.gas_used + executed.refunded)
+    /// Set the nonce of an account in the latest state, without mining a
+    /// block. See `StateExt::set_nonce` for the accepted values.
+    pub fn set_nonce(&self, address: Address, nonce: U256) -> Fallible<()> {
+        let mut chain_state = self.chain_state.write().unwrap();
+        chain_state.update_best_state(None, |state| state.set_nonce(&address, &nonce))
     }
 
-    /// Looks up logs based on the given filter.
-    pub fn logs(
-        &self,
-        filter: Filter,
-    ) -> impl Future<Item = Vec<LocalizedLogEntry>, Error = Error> {
-        // Resolve starting and ending blocks.
-        let block_numbers = future::join_all(vec![
-            Box::new(self.get_block_unwrap(filter.from_block)),
-            Box::new(self.get_block_unwrap(filter.to_block)),
-        ]);
+    /// Replace the code of an account in the latest state, without mining a
+    /// block.
+    pub fn set_code(&self, address: Address, code: Vec<u8>) -> Fallible<()> {
+        let mut chain_state = self.chain_state.write().unwrap();
+        chain_state.update_best_state(None, |state| state.set_code(&address, code))
+    }
 
-        // Get blocks.
-        let chain_state = self.chain_state.clone();
-        let blocks = block_numbers.and_then(move |nums| {
-            let from_block = nums[0].number_u64();
-            let to_block = nums[1].number_u64();
+    /// Write a storage slot of an account in the latest state, without mining
+    /// a block.
+    ///
+    /// The value must be plaintext. For confidential contracts, the slot is
+    /// encrypted under the contract's state key, as for writes made by the
+    /// contract itself.
+    pub fn set_storage(&self, address: Address, key: H256, value: H256) -> Fallible<()> {
+        let mut chain_state = self.chain_state.write().unwrap();
+        let confidential_ctx = if chain_state.best_state()?.is_confidential(&address)? {
+            // Storage nonces are derived from this hash. Include the state
+            // version so they are not reused by the next mined block.
+            let best_block = chain_state
+                .get_block_by_number(chain_state.block_number)
+                .expect("must have a best block");
+            let mut buffer = best_block.hash.to_vec();
+            buffer.extend_from_slice(&(chain_state.state_version + 1).to_be_bytes());
 
-            stream::iter_ok(from_block..=to_block)
-                .map(move |number| {
-                    let chain_state = chain_state.read().unwrap();
-                    chain_state
-                        .get_block_by_number(number)
-                        .expect("block should exist")
-                })
-                .collect()
-        });
+            let mut ctx = ConfidentialCtx::new(keccak(&buffer), self.km_client.clone());
+            ctx.activate(Some(address))
+                .map_err(|err| format_err!("{}", err))?;
+            Some(ctx)
+        } else {
+            None
+        };
 
-        // Get logs.
-        let logs = blocks
-            .map(move |blocks| {
-                blocks
-                    .into_iter()
-                    .flat_map(move |blk| blk.logs.clone())
-                    .filter(|log| filter.matches(log))
-                    .collect()
-            })
-            .and_then(|logs: Vec<LocalizedLogEntry>| {
-                let mut logs = logs;
-                logs.sort_by(|a, b| a.block_number.partial_cmp(&b.block_number).unwrap());
-                future::ok(logs)
-            });
+        chain_state.update_best_state(confidential_ctx, |state| {
+            Ok(state.set_storage(&address, key, value)?)
+        })
+    }
 
-        Box::new(logs)
+    /// Nonce of the account's next transaction, counting its transactions in
+    /// the pending buffer.
+    pub fn pending_nonce(&self, address: &Address) -> Fallible<U256> {
+        let chain_state = self.chain_state.read().unwrap();
+        chain_state.next_nonce(address)
     }
-}
 
-lazy_static! {
-    // Dummy-valued PoW-related block extras.
-    static ref BLOCK_EXTRA_INFO: BTreeMap<String, String> = {
-        let mut map = BTreeMap::new();
-        map.insert("mixHash".into(), format!("0x{:x}", H256::default()));
-        map.insert("nonce".into(), format!("0x{:x}", H64::default()));
-        map
-    };
-}
+    /// Chain spec.
+    pub fn spec(&self) -> &Spec {
+        &self.spec
+    }
 
-/// Transaction execution result.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ExecutionResult {
-    pub cumulative_gas_used: U256,
-    pub gas_used: U256,
-    pub log_bloom: Bloom,
-    pub logs: Vec<LogEntry>,
-    pub status_code: u8,
-    #[serde(with = "serde_bytes")]
-    pub output: Vec<u8>,
-}
+    /// Gas price.
+    pub fn gas_price(&self) -> U256 {
+        self.gas_price
+    }
 
-/// A wrapper that exposes a simulated Ethereum block.
-#[derive(Clone, Debug)]
-pub struct EthereumBlock {
-    number: u64,
-    timestamp: u64,
-    hash: H256,
-    parent_hash: H256,
-    gas_used: U256,
-    gas_limit: U256,
-    log_bloom: Bloom,
-    logs: Vec<LocalizedLogEntry>,
-    transactions: Vec<LocalizedTransaction>,
-}
+    /// Block production mode.
+    pub fn mining_mode(&self) -> MiningMode {
+        *self.mining_mode.read().unwrap()
+    }
 
-impl EthereumBlock {
-    /// Create a new Ethereum block.
-    pub fn new(
-        number: u64,
-        parent_hash: H256,
-        timestamp: u64,
-        gas_used: U256,
-        gas_limit: U256,
-        log_bloom: Bloom,
-    ) -> Self {
-        // TODO: better blockhash
-        Self {
-            number,
-            parent_hash,
-            timestamp,
-            logs: vec![],
-            transactions: vec![],
-            hash: keccak(number.to_string()).into(),
-            gas_used,
-            gas_limit,
-            log_bloom,
+    /// Switch the block production mode, e.g., to change the block period
+    /// of interval mining, without restarting.
+    ///
+    /// Switching to auto mining first mines the transactions in the pending
+    /// buffer, as auto mining would not mine them otherwise. In the other
+    /// modes, they stay there until the next block is mined.
+    pub fn set_mining_mode(&self, mining_mode: MiningMode) {
+        let mut chain_state = self.chain_state.write().unwrap();
+        if mining_mode == MiningMode::Auto {
+            while !chain_state.pending.is_empty() {
+                self.mine_pending(&mut chain_state);
+            }
         }
+        *self.mining_mode.write().unwrap() = mining_mode;
+        let _ = self.mining_mode_updates.unbounded_send(mining_mode);
     }
 
-    /// Ethereum block number as an u64.
-    pub fn number_u64(&self) -> u64 {
-        self.number
+    /// Block gas limit.
+    pub fn block_gas_limit(&self) -> U256 {
+        self.block_gas_limit
     }
 
-    /// Block hash.
-    pub fn hash(&self) -> H256 {
-        self.hash
+    /// Gas cap of simulated calls.
+    pub fn call_gas_cap(&self) -> U256 {
+        self.call_gas_cap
     }
 
-    /// Ethereum transactions contained in the block.
-    pub fn transactions(&self) -> Vec<LocalizedTransaction> {
-        self.transactions.clone()
+    /// Address credited with block rewards.
+    pub fn coinbase(&self) -> Address {
+        self.coinbase
     }
 
-    /// Retrieve an Ethereum block header with additional metadata.
-    pub fn rich_header(&self) -> EthRpcRichHeader {
-        EthRpcRichHeader {
-            inner: EthRpcHeader {
-                hash: Some(self.hash.into()),
-                size: None,
-                parent_hash: self.parent_hash.into(),
-                uncles_hash: KECCAK_EMPTY_LIST_RLP.into(), /* empty list */
-                author: Default::default(),
-                miner: Default::default(),
+    /// Force the timestamp of the next mined block.
+    ///
+    /// The timestamp must be greater than the latest block's timestamp, and
+    /// below 2^64 - 1 so the blocks after it can have greater ones.
+    pub fn set_next_block_timestamp(&self, timestamp: u64) -> Fallible<()> {
+        if timestamp == u64::max_value() {
+            return Err(format_err!("timestamp must be below {}", u64::max_value()));
+        }
+        let mut chain_state = self.chain_state.write().unwrap();
+        let best_block = chain_state
+            .get_block_by_number(chain_state.block_number)
+            .expect("best block must exist");
+        if timestamp <= best_block.timestamp() {
+            return Err(format_err!(
+                "timestamp {} must be greater than the latest block timestamp {}",
+                timestamp,
+                best_block.timestamp()
+            ));
+        }
+
+        chain_state.next_timestamp = Some(timestamp);
+        Ok(())
+    }
+
+    /// Retrieve an Ethereum block given a block identifier.
+    pub fn get_block(
+        &self,
+        id: BlockId,
+    ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
+        let block: BoxFuture<Option<EthereumBlock>> = match id {
+            BlockId::Hash(hash) => Box::new(self.get_block_by_hash(hash)),
+            BlockId::Number(number) => Box::new(self.get_block_by_number(number)),
+            BlockId::Latest => Box::new(self.get_latest_block().map(|blk| Some(blk))),
+            BlockId::Earliest => Box::new(self.get_block_by_number(0)),
+        };
+
+        block
+    }
+
+    /// The current best block number.
+    pub fn best_block_number(&self) -> u64 {
+        let chain_state = self.chain_state.read().unwrap();
+        chain_state.block_number
+    }
+
+    /// Retrieve an Ethereum block given a block identifier.
+    ///
+    /// If the block is not found it returns an error.
+    pub fn get_block_unwrap(
+        &self,
+        id: BlockId,
+    ) -> impl Future<Item = EthereumBlock, Error = Error> {
+        self.get_block(id).and_then(|blk| match blk {
+            Some(blk) => Ok(blk),
+            None => Err(format_err!("block not found")),
+        })
+    }
+
+    /// Retrieve the latest Ethereum block.
+    pub fn get_latest_block(&self) -> impl Future<Item = EthereumBlock, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::ok(
+            chain_state
+                .get_block_by_number(chain_state.block_number)
+                .expect("best block must exist"),
+        )
+    }
+
+    /// Retrieve the pending Ethereum block, i.e. the block that would be mined next.
+    ///
+    /// The pending block lists the transactions in the pending buffer. With auto
+    /// mining, transactions are mined as soon as they are submitted, so the
+    /// pending block is always empty and its state is identical to the latest one.
+    pub fn get_pending_block(&self) -> impl Future<Item = EthereumBlock, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+        let best_block = chain_state
+            .get_block_by_number(chain_state.block_number)
+            .expect("best block must exist");
+        let number = chain_state.block_number + 1;
+
+        let mut block = EthereumBlock::new_pending(
+            number,
+            best_block.hash,
+            self.coinbase,
+            chain_state.next_block_timestamp(),
+            self.block_gas_limit,
+        );
+        block.transactions = chain_state
+            .pending
+            .iter()
+            .enumerate()
+            .map(|(transaction_index, txn)| LocalizedTransaction {
+                signed: txn.clone().into(),
+                block_number: number,
+                // The pending block has no hash yet.
+                block_hash: H256::zero(),
+                transaction_index,
+                cached_sender: None,
+            })
+            .collect();
+
+        future::ok(block)
+    }
+
+    /// Retrieve a specific Ethereum block, identified by its number.
+    pub fn get_block_by_number(
+        &self,
+        number: u64,
+    ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::ok(chain_state.get_block_by_number(number))
+    }
+
+    /// Retrieve a specific Ethereum block, identified by its block hash.
+    pub fn get_block_by_hash(
+        &self,
+        hash: H256,
+    ) -> impl Future<Item = Option<EthereumBlock>, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::ok(chain_state.get_block_by_hash(&hash))
+    }
+
+    /// Retrieve a specific Ethereum transaction, identified by its transaction hash.
+    pub fn get_txn_by_hash(
+        &self,
+        hash: H256,
+    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::ok(chain_state.transactions.get(&hash).cloned())
+    }
+
+    /// Retrieve a specific Ethereum transaction receipt, identified by its transaction
+    /// hash.
+    pub fn get_txn_receipt_by_hash(
+        &self,
+        hash: H256,
+    ) -> impl Future<Item = Option<LocalizedReceipt>, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::ok(chain_state.receipts.get(&hash).cloned())
+    }
+
+    /// Retrieve the receipts of all transactions of a block, along with the
+    /// transactions, in transaction index order.
+    ///
+    /// Fails if there is no such block.
+    pub fn get_block_receipts(
+        &self,
+        id: BlockId,
+    ) -> impl Future<Item = Vec<(LocalizedReceipt, LocalizedTransaction)>, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::done(
+            chain_state
+                .resolve_block_number(id)
+                .and_then(|number| {
+                    chain_state
+                        .get_block_by_number(number)
+                        .ok_or_else(|| format_err!("block not found"))
+                })
+                .map(|block| {
+                    block
+                        .transactions
+                        .iter()
+                        .map(|txn| {
+                            let hash = txn.hash();
+                            (
+                                chain_state.receipts[&hash].clone(),
+                                chain_state.transactions[&hash].clone(),
+                            )
+                        })
+                        .collect()
+                }),
+        )
+    }
+
+    /// Retrieve the hash and block number of the transaction which created a
+    /// contract.
+    ///
+    /// `None` for accounts not created by a transaction of this chain, e.g.,
+    /// externally owned accounts and contracts of the genesis state.
+    pub fn get_contract_creation(&self, address: Address) -> Option<(H256, u64)> {
+        let chain_state = self.chain_state.read().unwrap();
+        chain_state
+            .contract_creations
+            .get(&address)
+            .map(|hash| (*hash, chain_state.receipts[hash].block_number))
+    }
+
+    /// Retrieve the reason why a mined Ethereum transaction failed, if it did.
+    pub fn get_txn_failure_reason(&self, hash: H256) -> Option<String> {
+        let chain_state = self.chain_state.read().unwrap();
+        chain_state.failure_reasons.get(&hash).cloned()
+    }
+
+    /// Retrieve a specific Ethereum transaction, identified by the block round and
+    /// transaction index within the block.
+    ///
+    /// `None` if there is no such block, or the index is out of range.
+    pub fn get_txn_by_number_and_index(
+        &self,
+        number: u64,
+        index: usize,
+    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::ok(
+            chain_state
+                .block_number_to_hash
+                .get(&number)
+                .and_then(|hash| chain_state.blocks.get(hash))
+                .and_then(|blk| blk.transactions.get(index))
+                .cloned(),
+        )
+    }
+
+    /// Retrieve a specific Ethereum transaction, identified by the block hash and
+    /// transaction index within the block.
+    ///
+    /// `None` if there is no such block, or the index is out of range.
+    pub fn get_txn_by_block_hash_and_index(
+        &self,
+        block_hash: H256,
+        index: usize,
+    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        future::ok(
+            chain_state
+                .blocks
+                .get(&block_hash)
+                .and_then(|blk| blk.transactions.get(index))
+                .cloned(),
+        )
+    }
+
+    /// Retrieve a specific Ethereum transaction, identified by a block identifier
+    /// and transaction index within the block.
+    pub fn get_txn(
+        &self,
+        id: BlockId,
+        index: usize,
+    ) -> impl Future<Item = Option<LocalizedTransaction>, Error = Error> {
+        let txn: BoxFuture<Option<LocalizedTransaction>> = match id {
+            BlockId::Hash(hash) => Box::new(self.get_txn_by_block_hash_and_index(hash, index)),
+            BlockId::Number(number) => Box::new(self.get_txn_by_number_and_index(number, index)),
+            BlockId::Latest => {
+                Box::new(self.get_txn_by_number_and_index(self.best_block_number(), index))
+            }
+            BlockId::Earliest => Box::new(self.get_txn_by_number_and_index(0, index)),
+        };
+
+        txn
+    }
+
+    /// Submit a raw Ethereum transaction to the chain.
+    ///
+    /// The execution result is `None` if the transaction was not mined right
+    /// away (see `submit_transaction`).
+    ///
+    /// Only legacy transactions are supported: the pinned ethcore predates
+    /// typed transaction envelopes (EIP-2718), so it cannot represent access
+    /// list (EIP-2930) or any other typed transactions. These are rejected with
+    /// an "unsupported transaction type" error.
+    pub fn send_raw_transaction(
+        &self,
+        raw: Vec<u8>,
+    ) -> impl Future<Item = (H256, Option<ExecutionResult>), Error = Error> {
+        future::done(
+            self.decode_raw_transaction(&raw)
+                .and_then(|txn| self.send_transaction(txn)),
+        )
+    }
+
+    /// Submit a signed Ethereum transaction to the chain.
+    ///
+    /// The execution result is `None` if the transaction was not mined right
+    /// away (see `submit_transaction`).
+    pub fn send_transaction(
+        &self,
+        txn: SignedTransaction,
+    ) -> Result<(H256, Option<ExecutionResult>), Error> {
+        {
+            let chain_state = self.chain_state.read().unwrap();
+            self.check_transaction(&chain_state, &txn)?;
+        }
+
+        self.submit_transaction(txn)
+    }
+
+    /// Submit raw transactions to be mined together, in order, in a new block.
+    ///
+    /// The block is mined right away in any mining mode, and only if every
+    /// transaction is valid and applies: otherwise the error identifies the
+    /// first failing transaction and nothing is mined. Nonces must follow the
+    /// latest state without gaps, and the pending buffer is left untouched.
+    pub fn send_raw_transactions(&self, raw_txns: Vec<Vec<u8>>) -> Fallible<Vec<H256>> {
+        let batch_error = |index, err: Error| TransactionError::BatchRejected {
+            index,
+            reason: err.to_string(),
+        };
+
+        let mut txns = Vec::with_capacity(raw_txns.len());
+        for (index, raw) in raw_txns.iter().enumerate() {
+            txns.push(
+                self.decode_raw_transaction(raw)
+                    .map_err(|err| batch_error(index, err))?,
+            );
+        }
+
+        let chain_state = self.chain_state.read().unwrap();
+        let state = chain_state.best_state()?;
+        let mut nonces = HashMap::new();
+        for (index, txn) in txns.iter().enumerate() {
+            self.check_transaction(&chain_state, txn)
+                .map_err(|err| batch_error(index, err))?;
+
+            let sender = txn.sender();
+            let nonce = match nonces.get(&sender) {
+                Some(nonce) => *nonce,
+                None => state.nonce(&sender)?,
+            };
+            if txn.nonce != nonce {
+                let err = if txn.nonce < nonce {
+                    TransactionError::NonceTooLow
+                } else {
+                    TransactionError::NonceTooHigh
+                };
+                return Err(batch_error(index, err.into()).into());
+            }
+            nonces.insert(sender, nonce + U256::one());
+        }
+
+        drop(chain_state);
+
+        let hashes = txns.iter().map(|txn| txn.hash()).collect();
+        let (results, _) = self.mine_block_concurrently(txns, true);
+        if let Some((index, Err(err))) = results
+            .into_iter()
+            .enumerate()
+            .find(|(_, result)| result.is_err())
+        {
+            return Err(batch_error(index, err).into());
+        }
+
+        Ok(hashes)
+    }
+
+    /// Decode a raw transaction, unless it exceeds the maximum transaction
+    /// size.
+    fn decode_raw_transaction(&self, raw: &[u8]) -> Fallible<SignedTransaction> {
+        if raw.len() > self.max_transaction_size {
+            return Err(TransactionError::Oversized {
+                size: raw.len(),
+                limit: self.max_transaction_size,
+            }
+            .into());
+        }
+
+        decode_raw_transaction(raw)
+    }
+
+    /// Check a transaction against the chain limits and the latest state.
+    fn check_transaction(&self, chain_state: &ChainState, txn: &SignedTransaction) -> Fallible<()> {
+        // Check that gas < block gas limit.
+        if txn.gas > self.block_gas_limit {
+            return Err(TransactionError::GasLimitExceeded.into());
+        }
+
+        // Check gas price.
+        if txn.gas_price < self.gas_price.into() {
+            return Err(TransactionError::Underpriced.into());
+        }
+
+        // Check that the intrinsic gas and the upfront cost are covered.
+        let schedule = self
+            .spec
+            .engine
+            .machine()
+            .schedule(chain_state.block_number + 1);
+        if txn.gas < txn.gas_required(&schedule).into() {
+            return Err(TransactionError::IntrinsicGasTooLow.into());
+        }
+
+        let cost = txn.gas.full_mul(txn.gas_price) + U512::from(txn.value);
+        let address = txn.sender();
+        let balance = chain_state.best_state()?.balance(&address)?;
+        if U512::from(balance) < cost {
+            return Err(TransactionError::InsufficientFunds {
+                address,
+                balance,
+                cost,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Submit a verified transaction.
+    ///
+    /// A transaction with the sender's next nonce is mined right away in auto
+    /// mining mode and added to the pending buffer otherwise, followed by any
+    /// queued transactions it unblocks. A transaction with a future nonce is
+    /// queued until the gap is filled. An execution result is only returned
+    /// if the transaction was mined.
+    ///
+    /// Transactions mined right away execute without holding the chain state
+    /// lock (see `mine_block_concurrently`).
+    fn submit_transaction(
+        &self,
+        txn: SignedTransaction,
+    ) -> Result<(H256, Option<ExecutionResult>), Error> {
+        let mut chain_state = self.chain_state.write().unwrap();
+        let sender = txn.sender();
+        let nonce = chain_state.next_nonce(&sender)?;
+
+        if txn.nonce < nonce {
+            return Err(TransactionError::NonceTooLow.into());
+        }
+        if txn.nonce > nonce {
+            let txn_hash = txn.hash();
+            if self.mining_log != MiningLog::Quiet {
+                info!(
+                    "Queued transaction {:?} with nonce {} (expected nonce {})",
+                    txn_hash, txn.nonce, nonce
+                );
+            }
+            chain_state
+                .queued
+                .entry(sender)
+                .or_insert_with(BTreeMap::new)
+                .insert(txn.nonce, txn);
+            return Ok((txn_hash, None));
+        }
+
+        let txn_hash = txn.hash();
+        if self.mining_mode() == MiningMode::Auto && self.instamine_delay == Duration::from_secs(0)
+        {
+            // Execute the transaction without blocking other submissions.
+            drop(chain_state);
+            let (mut results, mut chain_state) = self.mine_block_concurrently(vec![txn], false);
+            let (_txn_hash, result) = results
+                .pop()
+                .expect("must have a result for the transaction")?;
+            self.promote_queued(&mut chain_state, sender)?;
+            return Ok((txn_hash, Some(result)));
+        }
+        let result = self.include_transaction(&mut chain_state, txn)?;
+        self.promote_queued(&mut chain_state, sender)?;
+
+        Ok((txn_hash, result))
+    }
+
+    /// Mine the transaction in auto mining mode, otherwise add it to the
+    /// pending buffer.
+    ///
+    /// With an instamine delay, transactions also go through the pending
+    /// buffer in auto mining mode, until `delayed_instamine` mines them.
+    fn include_transaction(
+        &self,
+        chain_state: &mut ChainState,
+        txn: SignedTransaction,
+    ) -> Result<Option<ExecutionResult>, Error> {
+        match self.mining_mode() {
+            MiningMode::Auto if self.instamine_delay > Duration::from_secs(0) => {
+                chain_state.pending.push(txn);
+                let deadline = Instant::now() + self.instamine_delay;
+                let _ = self.instamine_requests.unbounded_send(deadline);
+                Ok(None)
+            }
+            MiningMode::Auto => {
+                let (_txn_hash, result) = self.mine_transaction(chain_state, txn)?;
+                Ok(Some(result))
+            }
+            MiningMode::Interval(_) | MiningMode::Manual => {
+                chain_state.pending.push(txn);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Include queued transactions of the sender for as long as they continue
+    /// its nonce sequence.
+    fn promote_queued(&self, chain_state: &mut ChainState, sender: Address) -> Fallible<()> {
+        loop {
+            let nonce = chain_state.next_nonce(&sender)?;
+            let txn = match chain_state.queued.get_mut(&sender) {
+                Some(queue) => match queue.remove(&nonce) {
+                    Some(txn) => txn,
+                    None => break,
+                },
+                None => break,
+            };
+
+            if let Err(err) = self.include_transaction(chain_state, txn) {
+                warn!("Dropping queued transaction with nonce {}: {}", nonce, err);
+                break;
+            }
+        }
+
+        // Drop the sender's queue once it is drained.
+        if chain_state
+            .queued
+            .get(&sender)
+            .map_or(false, |queue| queue.is_empty())
+        {
+            chain_state.queued.remove(&sender);
+        }
+
+        Ok(())
+    }
+
+    /// Mine the pending transactions once the instamine delay elapsed after
+    /// their submission, emulating network latency in auto mining mode.
+    ///
+    /// To be spawned once on a runtime. Transactions submitted within the
+    /// delay of each other may be mined into the same block.
+    pub fn delayed_instamine(blockchain: Arc<Self>) -> impl Future<Item = (), Error = ()> {
+        let deadlines = blockchain
+            .instamine_deadlines
+            .lock()
+            .unwrap()
+            .take()
+            .expect("delayed instamine must only be started once");
+
+        deadlines.for_each(move |deadline| {
+            let blockchain = blockchain.clone();
+            Delay::new(deadline)
+                .map_err(|err| error!("Instamine timer error: {:?}", err))
+                .map(move |()| {
+                    // Earlier blocks may have included the transaction.
+                    if blockchain.pending_transaction_count() > 0 {
+                        blockchain.mine_pending_block();
+                    }
+                })
+        })
+    }
+
+    /// Mine the pending transactions at a fixed interval while in interval
+    /// mining mode, following the changes of mining mode.
+    ///
+    /// To be spawned once on a runtime.
+    pub fn interval_mining(blockchain: Arc<Self>) -> impl Future<Item = (), Error = ()> {
+        let updates = blockchain
+            .mining_mode_receiver
+            .lock()
+            .unwrap()
+            .take()
+            .expect("interval mining must only be started once");
+        let interval = new_mining_interval(blockchain.mining_mode());
+
+        IntervalMining {
+            blockchain,
+            updates,
+            interval,
+        }
+    }
+
+    /// Number of transactions in the pending buffer.
+    pub fn pending_transaction_count(&self) -> usize {
+        let chain_state = self.chain_state.read().unwrap();
+        chain_state.pending.len()
+    }
+
+    /// Snapshot of the chain, for debugging.
+    ///
+    /// Covers the stored blocks and the accounts the chain knows about: the
+    /// genesis accounts, the senders and recipients of mined transactions, the
+    /// contracts they created, and the given `accounts`.
+    pub fn dump_state(&self, accounts: &[Address]) -> Fallible<StateDump> {
+        let chain_state = self.chain_state.read().unwrap();
+
+        let mut addresses = chain_state.genesis_accounts.clone();
+        addresses.extend(accounts);
+        for txn in chain_state.transactions.values() {
+            addresses.insert(txn.clone().sender());
+            if let Action::Call(to) = txn.action {
+                addresses.insert(to);
+            }
+        }
+        addresses.extend(
+            chain_state
+                .receipts
+                .values()
+                .filter_map(|receipt| receipt.contract_address),
+        );
+
+        let state = chain_state.best_state()?;
+        let accounts = addresses
+            .into_iter()
+            .map(|address| {
+                let account = AccountDump {
+                    balance: state.balance(&address)?,
+                    nonce: state.nonce(&address)?,
+                    code_size: state.code(&address)?.map_or(0, |code| code.len()),
+                };
+                Ok((address, account))
+            })
+            .collect::<Fallible<_>>()?;
+
+        // Blocks skipped by `evm_setBlockNumber` are not stored.
+        let blocks = chain_state
+            .block_number_to_hash
+            .values()
+            .map(|hash| {
+                let block = &chain_state.blocks[hash];
+                BlockDump {
+                    number: block.number,
+                    hash: block.hash,
+                    timestamp: block.timestamp,
+                    transaction_count: block.transactions.len(),
+                }
+            })
+            .collect();
+
+        Ok(StateDump {
+            best_block: chain_state.block_number,
+            blocks,
+            transaction_count: chain_state.transactions.len(),
+            pending_transaction_count: chain_state.pending.len(),
+            accounts,
+        })
+    }
+
+    /// Drop all pending and queued transactions, returning how many there were.
+    pub fn discard_pending_transactions(&self) -> usize {
+        let mut chain_state = self.chain_state.write().unwrap();
+        let queued: usize = chain_state.queued.values().map(|txns| txns.len()).sum();
+        let pending = chain_state.pending.len();
+        chain_state.pending.clear();
+        chain_state.queued.clear();
+        pending + queued
+    }
+
+    /// Drop a pending or queued transaction which has not been mined yet,
+    /// returning whether it was found.
+    ///
+    /// The sender's later pending transactions go back to the queue, so that
+    /// they are mined once a transaction with the freed nonce is submitted.
+    pub fn drop_transaction(&self, txn_hash: H256) -> bool {
+        let mut chain_state = self.chain_state.write().unwrap();
+
+        if let Some(index) = chain_state
+            .pending
+            .iter()
+            .position(|txn| txn.hash() == txn_hash)
+        {
+            let sender = chain_state.pending.remove(index).sender();
+            let (requeued, pending) = chain_state
+                .pending
+                .drain(..)
+                .enumerate()
+                .partition::<Vec<_>, _>(|(i, txn)| *i >= index && txn.sender() == sender);
+            chain_state.pending = pending.into_iter().map(|(_, txn)| txn).collect();
+            if !requeued.is_empty() {
+                let queue = chain_state
+                    .queued
+                    .entry(sender)
+                    .or_insert_with(BTreeMap::new);
+                for (_, txn) in requeued {
+                    queue.insert(txn.nonce, txn);
+                }
+            }
+            return true;
+        }
+
+        let queued = chain_state.queued.iter().find_map(|(sender, queue)| {
+            queue
+                .values()
+                .find(|txn| txn.hash() == txn_hash)
+                .map(|txn| (*sender, txn.nonce))
+        });
+        let (sender, nonce) = match queued {
+            Some(queued) => queued,
+            None => return false,
+        };
+        let queue = chain_state
+            .queued
+            .get_mut(&sender)
+            .expect("queue must exist");
+        queue.remove(&nonce);
+        if queue.is_empty() {
+            chain_state.queued.remove(&sender);
+        }
+        true
+    }
+
+    /// Mine a block containing the pending transactions, returning its number.
+    ///
+    /// Pending transactions which do not fit into the block gas limit are left
+    /// for the next block. A block is mined even if there are no pending
+    /// transactions.
+    pub fn mine_pending_block(&self) -> u64 {
+        let mut chain_state = self.chain_state.write().unwrap();
+        self.mine_pending(&mut chain_state);
+        chain_state.block_number
+    }
+
+    /// Mine `count` blocks, returning the number of the last one.
+    ///
+    /// Pending transactions are only sealed into the first block, as with
+    /// `mine_pending_block`, and the other blocks are empty. If an `interval`
+    /// is given, the timestamps of the blocks are that many seconds apart.
+    pub fn mine_blocks(&self, count: u64, interval: Option<u64>) -> Fallible<u64> {
+        if count == 0 {
+            return Err(format_err!("block count must be positive"));
+        }
+        if interval == Some(0) {
+            return Err(format_err!("block interval must be positive"));
+        }
+
+        let mut chain_state = self.chain_state.write().unwrap();
+        self.mine_pending(&mut chain_state);
+        for _ in 1..count {
+            if let Some(interval) = interval {
+                let parent = chain_state
+                    .get_block_by_number(chain_state.block_number)
+                    .expect("best block must exist");
+                chain_state.next_timestamp = Some(parent.timestamp().saturating_add(interval));
+            }
+            self.mine_block(&mut chain_state, vec![], true, false);
+        }
+
+        Ok(chain_state.block_number)
+    }
+
+    /// Mine a block containing the pending transactions which fit into the
+    /// block gas limit.
+    fn mine_pending(&self, chain_state: &mut ChainState) {
+        let mut gas = U256::zero();
+        let block_gas_limit = self.block_gas_limit;
+        let count = chain_state
+            .pending
+            .iter()
+            .take_while(|txn| {
+                gas = gas + txn.gas;
+                gas <= block_gas_limit
+            })
+            .count();
+        let txns: Vec<SignedTransaction> = chain_state.pending.drain(..count).collect();
+
+        for result in self.mine_block(chain_state, txns, true, false) {
+            if let Err(err) = result {
+                warn!("Dropping pending transaction: {}", err);
+            }
+        }
+    }
+
+    /// Mine a block containing the transaction.
+    fn mine_transaction(
+        &self,
+        chain_state: &mut ChainState,
+        txn: SignedTransaction,
+    ) -> Result<(H256, ExecutionResult), Error> {
+        self.mine_block(chain_state, vec![txn], false, false)
+            .pop()
+            .expect("must have a result for the transaction")
+    }
+
+    /// Mine a block containing the given transactions.
+    ///
+    /// Transactions are executed in order. Those which cannot be applied
+    /// (e.g., because of an invalid nonce) are left out of the block, and
+    /// their error is returned in place of an execution result. Unless
+    /// `allow_empty` is set, no block is mined if no transaction applies. If
+    /// `atomic` is set, no block is mined unless every transaction applies.
+    fn mine_block(
+        &self,
+        chain_state: &mut ChainState,
+        txns: Vec<SignedTransaction>,
+        allow_empty: bool,
+        atomic: bool,
+    ) -> Vec<Result<(H256, ExecutionResult), Error>> {
+        let executed = self.execute_block(self.block_template(chain_state), txns);
+        self.seal_block(chain_state, executed, allow_empty, atomic)
+    }
+
+    /// Mine a block containing the given transactions, as `mine_block` does
+    /// without allowing empty blocks, but only holding the chain state lock
+    /// to seal the block. The lock is returned along with the results.
+    ///
+    /// Should another block be mined or the best state change while the
+    /// transactions execute, they are executed again on top of the new best
+    /// block, so concurrent submissions never mine two blocks with the same
+    /// number.
+    fn mine_block_concurrently(
+        &self,
+        txns: Vec<SignedTransaction>,
+        atomic: bool,
+    ) -> (
+        Vec<Result<(H256, ExecutionResult), Error>>,
+        RwLockWriteGuard<ChainState>,
+    ) {
+        loop {
+            let template = self.block_template(&self.chain_state.read().unwrap());
+            let executed = self.execute_block(template, txns.clone());
+
+            let mut chain_state = self.chain_state.write().unwrap();
+            if chain_state.is_parent_of(&executed.template) {
+                let results = self.seal_block(&mut chain_state, executed, false, atomic);
+                return (results, chain_state);
+            }
+        }
+    }
+
+    /// Template of the block following the best block.
+    fn block_template(&self, chain_state: &ChainState) -> BlockTemplate {
+        chain_state.block_template(self.coinbase, self.block_gas_limit)
+    }
+
+    /// Execute the transactions of a block on a view of its parent's state.
+    ///
+    /// The state updates stay in the view until the block is sealed, so this
+    /// does not need the chain state.
+    fn execute_block(
+        &self,
+        template: BlockTemplate,
+        txns: Vec<SignedTransaction>,
+    ) -> ExecutedBlock {
+        execute_block(
+            &self.spec,
+            &self.km_client,
+            self.block_reward,
+            template,
+            txns,
+        )
+    }
+
+    /// Seal an executed block on top of the best block, which must be its
+    /// parent, returning the execution results of its transactions.
+    fn seal_block(
+        &self,
+        chain_state: &mut ChainState,
+        executed: ExecutedBlock,
+        allow_empty: bool,
+        atomic: bool,
+    ) -> Vec<Result<(H256, ExecutionResult), Error>> {
+        let ExecutedBlock {
+            template,
+            gas_used: block_gas_used,
+            results,
+            applied,
+        } = executed;
+        if (applied.is_empty() && !allow_empty) || (atomic && applied.len() < results.len()) {
+            return results;
+        }
+        let number = template.env_info.number;
+        let timestamp = template.env_info.timestamp;
+
+        // Commit the state updates as the state of the new block.
+        let version = chain_state.commit_state_version(&template.mkvs);
+        chain_state.block_state_versions.insert(number, version);
+
+        // Create a block.
+        let mut log_bloom = Bloom::default();
+        for (_, receipt, _, _) in &applied {
+            log_bloom.accrue_bloom(&receipt.log_bloom);
+        }
+        let mut block = EthereumBlock::new(
+            number,
+            template.parent_hash,
+            self.coinbase,
+            timestamp,
+            block_gas_used,
+            self.block_gas_limit,
+            log_bloom,
+        );
+        if !chain_state.reorgs.is_empty() {
+            block.hash = keccak(format!("{}/{}", number, chain_state.reorgs.len())).into();
+        }
+        let block_hash = block.hash();
+        chain_state.block_number = number;
+        chain_state.next_timestamp = None;
+
+        for (transaction_index, (txn, receipt, gas_used, failure_reason)) in
+            applied.into_iter().enumerate()
+        {
+            // Store the txn.
+            let txn_hash = txn.hash();
+            if let Some(failure_reason) = failure_reason {
+                chain_state.failure_reasons.insert(txn_hash, failure_reason);
+            }
+            let localized_txn = LocalizedTransaction {
+                signed: txn.clone().into(),
+                block_number: number,
+                block_hash,
+                transaction_index,
+                cached_sender: None,
+            };
+            block.transactions.push(localized_txn.clone());
+            chain_state.transactions.insert(txn_hash, localized_txn);
+
+            // Store the logs.
+            let first_log_index = block.logs.len();
+            let logs: Vec<LocalizedLogEntry> = receipt
+                .logs
+                .into_iter()
+                .enumerate()
+                .map(|(i, log)| LocalizedLogEntry {
+                    entry: log,
+                    block_hash: block_hash,
+                    block_number: number,
+                    transaction_hash: txn_hash,
+                    transaction_index,
+                    transaction_log_index: i,
+                    log_index: first_log_index + i,
+                })
+                .collect();
+            block.logs.extend(logs.clone());
+
+            // Store the receipt.
+            let localized_receipt = LocalizedReceipt {
+                transaction_hash: txn_hash,
+                transaction_index,
+                block_hash: block_hash,
+                block_number: number,
+                cumulative_gas_used: receipt.gas_used,
+                gas_used,
+                contract_address: match txn.action {
+                    Action::Call(_) => None,
+                    // The applied outcome does not report the created address,
+                    // so derive it as the executive does for a transaction (see
+                    // `Executive::transact`), with the spec's address scheme. The
+                    // addresses of contracts created by contracts, e.g., with
+                    // CREATE2, are not part of the receipt.
+                    Action::Create => Some(
+                        contract_address(
+                            self.spec.engine.create_address_scheme(number),
+                            &txn.sender(),
+                            &txn.nonce,
+                            &txn.data,
+                        )
+                        .0,
+                    ),
+                },
+                logs: logs,
+                log_bloom: receipt.log_bloom,
+                outcome: receipt.outcome,
+            };
+            if let (Some(address), TransactionOutcome::StatusCode(1)) = (
+                localized_receipt.contract_address,
+                &localized_receipt.outcome,
+            ) {
+                chain_state.contract_creations.insert(address, txn_hash);
+            }
+            chain_state.receipts.insert(txn_hash, localized_receipt);
+
+            if self.mining_log == MiningLog::Normal {
+                info!(
+                    "Mined block number {:?} containing transaction {:?}. Gas used: {:?}",
+                    number, txn_hash, gas_used
+                );
+            }
+        }
+        chain_state.index_logs(&block.logs);
+
+        if self.mining_log == MiningLog::Structured {
+            let record = MinedBlockRecord {
+                number,
+                hash: block_hash,
+                gas_used: block.gas_used,
+                transactions: block
+                    .transactions
+                    .iter()
+                    .map(|txn| MinedTransactionRecord {
+                        hash: txn.hash(),
+                        gas_used: chain_state.receipts[&txn.hash()].gas_used,
+                    })
+                    .collect(),
+            };
+            info!(
+                "{}",
+                serde_json::to_string(&record).expect("record serialization cannot fail")
+            );
+        }
+
+        // Store the block.
+        chain_state.blocks.insert(block_hash, block);
+        chain_state.block_number_to_hash.insert(number, block_hash);
+
+        results
+    }
+
+    /// Simulate a transaction against a given block.
+    ///
+    /// The transaction runs on the state after the block, in the environment
+    /// of the block following it: its number, its timestamp (the one the next
+    /// block would get, if the block is the best block) and the hashes of the
+    /// blocks before it. Against the pending block, it runs on the pending
+    /// state, in the environment of the pending block.
+    ///
+    /// The simulated transaction is executed in a dedicated thread pool to
+    /// avoid blocking I/O processing.
+    ///
+    /// Optional state overrides are applied to the simulation state before
+    /// the transaction runs. The simulation state is never committed, so the
+    /// overrides do not outlive the call.
+    ///
+    /// The call gas cap is the gas limit of the simulation block, so a single
+    /// call cannot tie up a simulator thread indefinitely. Transactions with
+    /// more gas than the cap are rejected.
+    ///
+    /// Like the nonce, the sender's balance is not checked: a sender which
+    /// cannot pay for the value and gas is credited the difference in the
+    /// simulation state, so calls from unfunded addresses succeed.
+    ///
+    /// The call also fails with "execution timed out" once the call timeout
+    /// elapses, whichever of the two trips first. The EVM cannot be
+    /// interrupted, so a timed out simulation keeps its thread until it runs
+    /// out of gas, but the caller no longer waits for it. It does not hold
+    /// the chain state lock meanwhile, as it executes on a snapshot of the
+    /// state.
+    ///
+    /// # Notes
+    ///
+    /// Confidential contracts are not supported, see
+    /// `simulate_confidential_transaction`.
+    pub fn simulate_transaction(
+        &self,
+        transaction: SignedTransaction,
+        id: impl Into<StateId>,
+        overrides: Option<StateOverride>,
+    ) -> impl Future<Item = Executed, Error = CallError> {
+        self.simulate(
+            transaction,
+            id.into(),
+            overrides,
+            false,
+            NoopTracer,
+            NoopVMTracer,
+        )
+    }
+
+    /// Simulate a transaction against a given block, like
+    /// `simulate_transaction`, with a confidential context.
+    ///
+    /// The call data of a transaction to a confidential contract must be
+    /// encrypted to the contract's key, and so is its output to the peer that
+    /// encrypted it.
+    pub fn simulate_confidential_transaction(
+        &self,
+        transaction: SignedTransaction,
+        id: impl Into<StateId>,
+    ) -> impl Future<Item = Executed, Error = CallError> {
+        self.simulate(transaction, id.into(), None, true, NoopTracer, NoopVMTracer)
+    }
+
+    /// Simulate a transaction against a given block, like
+    /// `simulate_transaction`, and collect the accounts and storage slots it
+    /// accesses.
+    ///
+    /// The access list excludes the sender, the recipient (or created
+    /// contract) and the precompiled contracts, unless storage slots of them
+    /// are accessed. A failed execution still returns the accesses made
+    /// before it failed.
+    pub fn create_access_list(
+        &self,
+        transaction: SignedTransaction,
+        id: impl Into<StateId>,
+    ) -> impl Future<Item = (AccessList, Executed<FlatTrace, AccessList>), Error = CallError> {
+        let id = id.into();
+        let sender = transaction.sender();
+        // The transaction runs in the block after the state's, as in
+        // `simulate`.
+        let state_number = match id {
+            StateId::Block(id) => self.chain_state.read().unwrap().resolve_block_number(id),
+            StateId::Pending => Ok(self.best_block_number()),
+        };
+        let number = match state_number {
+            Ok(number) => number + 1,
+            Err(err) => {
+                return Either::A(future::err(
+                    ExecutionError::Internal(err.to_string()).into(),
+                ));
+            }
+        };
+        let address = match transaction.action {
+            Action::Call(address) => address,
+            Action::Create => {
+                let nonce = match self.state(id).and_then(|state| Ok(state.nonce(&sender)?)) {
+                    Ok(nonce) => nonce,
+                    Err(err) => {
+                        return Either::A(future::err(
+                            ExecutionError::Internal(err.to_string()).into(),
+                        ));
+                    }
+                };
+                contract_address(
+                    self.spec.engine.create_address_scheme(number),
+                    &sender,
+                    &nonce,
+                    &transaction.data,
+                )
+                .0
+            }
+        };
+
+        let spec = self.spec.clone();
+        let tracer = AccessListTracer::new(address);
+        Either::B(
+            self.simulate(transaction, id, None, false, NoopTracer, tracer)
+                .map(move |mut executed| {
+                    let machine = spec.engine.machine();
+                    let mut access_list = executed.vm_trace.take().unwrap_or_default();
+                    access_list.retain(|account, slots| {
+                        let excluded = *account == sender
+                            || *account == address
+                            || machine.builtin(account, number).is_some();
+                        !excluded || !slots.is_empty()
+                    });
+                    (access_list, executed)
+                }),
+        )
+    }
+
+    fn simulate<T, V>(
+        &self,
+        transaction: SignedTransaction,
+        id: StateId,
+        overrides: Option<StateOverride>,
+        confidential: bool,
+        tracer: T,
+        vm_tracer: V,
+    ) -> impl Future<Item = Executed<T::Output, V::Output>, Error = CallError>
+    where
+        T: Tracer + 'static,
+        V: VMTracer + 'static,
+        T::Output: Send + 'static,
+        V::Output: Send + 'static,
+    {
+        let simulator_pool = self.simulator_pool.clone();
+        let chain_state = self.chain_state.clone();
+        let coinbase = self.coinbase;
+        let block_gas_limit = self.block_gas_limit;
+        let block_reward = self.block_reward;
+        let call_gas_cap = self.call_gas_cap;
+        let call_timeout = self.call_timeout;
+        let spec = self.spec.clone();
+        let km_client = self.km_client.clone();
+
+        // Execute simulation in a dedicated thread pool to avoid blocking
+        // I/O processing with simulations.
+        let simulation = simulator_pool.spawn_handle(future::lazy(move || {
+            if transaction.gas > call_gas_cap {
+                return Err(ExecutionError::Internal(format!(
+                    "gas {} exceeds the call gas cap {}",
+                    transaction.gas, call_gas_cap
+                ))
+                .into());
+            }
+
+            let chain_state = chain_state.read().unwrap();
+            let (number, mkvs) = match id {
+                StateId::Block(id) => {
+                    let number = chain_state
+                        .resolve_block_number(id)
+                        .map_err(|err| ExecutionError::Internal(err.to_string()))?;
+                    (number, chain_state.state_view(number))
+                }
+                // As `Blockchain::pending_state_view`, but in the pool.
+                StateId::Pending => {
+                    let template = chain_state.block_template(coinbase, block_gas_limit);
+                    let executed = execute_block(
+                        &spec,
+                        &km_client,
+                        block_reward,
+                        template,
+                        chain_state.pending.clone(),
+                    );
+                    (chain_state.block_number, executed.template.mkvs)
+                }
+            };
+            let timestamp = match chain_state.get_block_by_number(number + 1) {
+                Some(next) => next.timestamp,
+                None => chain_state.next_block_timestamp(),
+            };
+
+            let env_info = EnvInfo {
+                number: number + 1,
+                author: coinbase,
+                timestamp,
+                difficulty: Default::default(),
+                last_hashes: chain_state.last_hashes(number),
+                gas_used: Default::default(),
+                gas_limit: call_gas_cap,
+            };
+            let machine = spec.engine.machine();
+            // `transact_virtual` tops up the sender's balance to cover the
+            // value and gas payment.
+            let options = TransactOptions::new(tracer, vm_tracer)
+                .dont_check_nonce()
+                .save_output_from_contract();
+            let confidential_ctx = if confidential {
+                let block = chain_state
+                    .get_block_by_number(number)
+                    .expect("resolved block must exist");
+                Some(Box::new(ConfidentialCtx::new(block.hash, km_client))
+                    as Box<dyn EthConfidentialCtx>)
+            } else {
+                None
+            };
+            let mut state = State::from_existing(
+                Box::new(mkvs),
+                NullBackend,
+                chain_state.account_start_nonce,
+                Default::default(), /* factories */
+                confidential_ctx,
+            )
+            .expect("state initialization must succeed");
+            // Execute on the state snapshot without blocking mining.
+            drop(chain_state);
+
+            if let Some(overrides) = overrides {
+                apply_state_override(&mut state, &overrides)
+                    .map_err(|err| ExecutionError::Internal(err.to_string()))?;
+            }
+
+            Ok(Executive::new(&mut state, &env_info, machine)
+                .transact_virtual(&transaction, options)?)
+        }));
+
+        // Race the simulation against its timeout on the timer runtime, as
+        // the caller may not run one.
+        let timeout = Delay::new(Instant::now() + call_timeout);
+        let race = simulation.select2(timeout).then(|result| match result {
+            Ok(Either::A((executed, _))) => Ok(executed),
+            Err(Either::A((err, _))) => Err(err),
+            Ok(Either::B(_)) => Err(ExecutionError::Internal("execution timed out".into()).into()),
+            Err(Either::B((err, _))) => {
+                Err(ExecutionError::Internal(format!("simulation timer error: {}", err)).into())
+            }
+        });
+        oneshot::spawn(race, &self.simulator_timer.lock().unwrap().executor())
+    }
+
+    /// Estimates gas against a given block.
+    ///
+    /// Uses `simulate_transaction` internally.
+    ///
+    /// # Notes
+    ///
+    /// Confidential contracts are not supported.
+    pub fn estimate_gas(
+        &self,
+        transaction: SignedTransaction,
+        id: impl Into<StateId>,
+    ) -> impl Future<Item = U256, Error = CallError> {
+        self.simulate_transaction(transaction, id, None)
+            .inspect(|executed| match &executed.exception {
+                Some(VmError::Reverted) | Some(VmError::OutOfGas) => {
+                    eprintln!("vm error: {:?}", executed.exception.as_ref().unwrap());
+                }
+                _ => {}
+            })
+            .map(|executed| executed.gas_used + executed.refunded)
+    }
+
+    /// Looks up logs based on the given filter.
+    ///
+    /// The end of the range is clamped to the best block, and an inverted
+    /// range yields no logs. Fails if the resolved block range is wider than
+    /// the configured limit.
+    pub fn logs(
+        &self,
+        filter: Filter,
+    ) -> impl Future<Item = Vec<LocalizedLogEntry>, Error = Error> {
+        // Resolve starting and ending blocks.
+        let range = self.chain_state.read().unwrap().resolve_log_range(&filter);
+        let (from_block, to_block) = match range {
+            Ok(range) => range,
+            Err(err) => return Box::new(future::err(err)) as BoxFuture<_>,
+        };
+        if from_block > to_block {
+            return Box::new(future::ok(vec![])) as BoxFuture<_>;
+        }
+        if to_block - from_block + 1 > self.max_logs_block_range {
+            let err = format_err!(
+                "query returned more than {} blocks",
+                self.max_logs_block_range
+            );
+            return Box::new(future::err(err)) as BoxFuture<_>;
+        }
+
+        // Get logs.
+        let chain_state = self.chain_state.clone();
+        let chunks: BoxFuture<Vec<Vec<LocalizedLogEntry>>> = match filter.address {
+            // Use the address index to avoid scanning unrelated blocks.
+            Some(ref addresses) => {
+                let chain_state = chain_state.read().unwrap();
+                let mut logs = chain_state.indexed_logs(addresses, from_block, to_block);
+                logs.retain(|log| filter.matches(log));
+                Box::new(future::ok(vec![logs]))
+            }
+            // Scan chunks of the block range in parallel, off the I/O path.
+            None => Box::new(future::join_all(
+                (from_block..=to_block)
+                    .step_by(LOG_SCAN_CHUNK_SIZE as usize)
+                    .map(|start| {
+                        let end = cmp::min(start + LOG_SCAN_CHUNK_SIZE - 1, to_block);
+                        let chain_state = chain_state.clone();
+                        let filter = filter.clone();
+                        self.simulator_pool.spawn_handle(future::lazy(move || {
+                            let chain_state = chain_state.read().unwrap();
+                            Ok(chain_state.scan_logs(start, end, &filter))
+                        }))
+                    })
+                    .collect::<Vec<_>>(),
+            )),
+        };
+
+        Box::new(chunks.map(|chunks| {
+            let mut logs: Vec<LocalizedLogEntry> = chunks.into_iter().flatten().collect();
+            // Canonical log order.
+            logs.sort_by_key(|log| (log.block_number, log.transaction_index, log.log_index));
+            logs
+        }))
+    }
+}
+
+lazy_static! {
+    // Dummy-valued PoW-related block extras.
+    static ref BLOCK_EXTRA_INFO: BTreeMap<String, String> = {
+        let mut map = BTreeMap::new();
+        map.insert("mixHash".into(), format!("0x{:x}", H256::default()));
+        map.insert("nonce".into(), format!("0x{:x}", H64::default()));
+        map
+    };
+}
+
+/// Reasons for rejecting a submitted transaction.
+///
+/// The messages match those of Geth, which wallets know how to parse.
+#[derive(Debug, Fail)]
+pub enum TransactionError {
+    #[fail(display = "unsupported transaction type {:#x}", _0)]
+    UnsupportedType(u8),
+    #[fail(display = "transaction too large: {} bytes, limit {}", size, limit)]
+    Oversized { size: usize, limit: usize },
+    #[fail(display = "could not decode transaction")]
+    InvalidRlp,
+    #[fail(display = "invalid sender")]
+    InvalidSignature,
+    #[fail(display = "exceeds block gas limit")]
+    GasLimitExceeded,
+    #[fail(display = "transaction underpriced")]
+    Underpriced,
+    #[fail(display = "intrinsic gas too low")]
+    IntrinsicGasTooLow,
+    #[fail(
+        display = "insufficient funds for gas * price + value: address {:?} have {} want {}",
+        address, balance, cost
+    )]
+    InsufficientFunds {
+        address: Address,
+        balance: U256,
+        cost: U512,
+    },
+    #[fail(display = "nonce too low")]
+    NonceTooLow,
+    #[fail(display = "nonce too high")]
+    NonceTooHigh,
+    #[fail(
+        display = "transaction {} of the batch was rejected: {}",
+        index, reason
+    )]
+    BatchRejected { index: usize, reason: String },
+}
+
+/// Execute the transactions of a block on a view of its parent's state,
+/// crediting the block reward to the block's author (see
+/// `Blockchain::execute_block`).
+fn execute_block(
+    spec: &Spec,
+    km_client: &Arc<MockClient>,
+    block_reward: U256,
+    template: BlockTemplate,
+    txns: Vec<SignedTransaction>,
+) -> ExecutedBlock {
+    // Initialize Ethereum state access functions.
+    let mut state = State::from_existing(
+        Box::new(template.mkvs.clone()),
+        NullBackend,
+        template.account_start_nonce,
+        Default::default(), /* factories */
+        Some(Box::new(ConfidentialCtx::new(
+            template.parent_hash,
+            km_client.clone(),
+        ))),
+    )
+    .expect("state initialization must succeed");
+    let mut env_info = template.env_info.clone();
+
+    // Execute the transactions.
+    let mut results = Vec::with_capacity(txns.len());
+    let mut applied = vec![];
+    for txn in txns {
+        let machine = spec.engine.machine();
+        state.checkpoint();
+        let mut outcome = match state.apply(&env_info, machine, &txn, false, true) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                state.revert_to_checkpoint();
+                results.push(Err(format_err!("{}", err)));
+                continue;
+            }
+        };
+        // Only the traces of a failed transaction tell why it failed, so
+        // execute it again with tracing then.
+        if let TransactionOutcome::StatusCode(0) = outcome.receipt.outcome {
+            state.revert_to_checkpoint();
+            outcome = state
+                .apply(&env_info, machine, &txn, true, true)
+                .expect("transaction must apply again");
+        } else {
+            state.discard_checkpoint();
+        }
+
+        // The receipt reports the gas used by the block so far.
+        let cumulative_gas_used = outcome.receipt.gas_used;
+        let gas_used = cumulative_gas_used - env_info.gas_used;
+        env_info.gas_used = cumulative_gas_used;
+
+        let failure_reason = failure_reason(&outcome.trace).map(String::from);
+        let result = ExecutionResult {
+            cumulative_gas_used,
+            gas_used,
+            log_bloom: outcome.receipt.log_bloom,
+            logs: outcome.receipt.logs.clone(),
+            status_code: match outcome.receipt.outcome {
+                TransactionOutcome::StatusCode(code) => code,
+                _ => unreachable!("we always use EIP-658 semantics"),
+            },
+            failure_reason: failure_reason.clone(),
+            output: outcome.output,
+        };
+        results.push(Ok((txn.hash(), result)));
+        applied.push((txn, outcome.receipt, gas_used, failure_reason));
+    }
+
+    // Credit the block reward.
+    if !block_reward.is_zero() {
+        state
+            .add_balance(
+                &template.env_info.author,
+                &block_reward,
+                CleanupMode::NoEmpty,
+            )
+            .expect("block reward must be credited");
+    }
+
+    // Flush the state updates into the view.
+    state.commit().expect("state commit must succeed");
+
+    ExecutedBlock {
+        template,
+        gas_used: env_info.gas_used,
+        results,
+        applied,
+    }
+}
+
+/// Hash of a block skipped by `evm_setBlockNumber`: a fixed prefix followed
+/// by the block number, so the block can be synthesized given its hash.
+fn synthetic_block_hash(number: u64) -> H256 {
+    let mut hash = keccak("synthetic block");
+    hash[24..].copy_from_slice(&number.to_be_bytes());
+    hash
+}
+
+/// Number of the block skipped by `evm_setBlockNumber` with the given hash.
+fn synthetic_block_number(hash: &H256) -> Option<u64> {
+    if hash[..24] != keccak("synthetic block")[..24] {
+        return None;
+    }
+
+    let mut number = [0u8; 8];
+    number.copy_from_slice(&hash[24..]);
+    Some(u64::from_be_bytes(number))
+}
+
+/// Reason why a transaction failed, given the traces of its execution: the
+/// error of its top-level call or creation.
+fn failure_reason(traces: &[FlatTrace]) -> Option<&'static str> {
+    let error = match traces.first().map(|trace| &trace.result) {
+        Some(Res::FailedCall(error)) | Some(Res::FailedCreate(error)) => error,
+        _ => return None,
+    };
+
+    Some(match *error {
+        TraceError::Reverted => "reverted",
+        TraceError::OutOfGas => "outOfGas",
+        TraceError::BadInstruction => "badInstruction",
+        TraceError::BadJumpDestination => "badJumpDestination",
+        TraceError::StackUnderflow => "stackUnderflow",
+        TraceError::OutOfStack => "outOfStack",
+        _ => "failed",
+    })
+}
+
+/// Decode and verify the signature of a raw transaction.
+///
+/// A typed transaction starts with its type byte, a legacy one with an RLP
+/// list prefix. Only legacy transactions are supported.
+fn decode_raw_transaction(raw: &[u8]) -> Fallible<SignedTransaction> {
+    match raw.first() {
+        Some(&tx_type) if tx_type <= MAX_TRANSACTION_TYPE => {
+            return Err(TransactionError::UnsupportedType(tx_type).into());
+        }
+        _ => {}
+    }
+
+    let decoded: UnverifiedTransaction =
+        rlp::decode(raw).map_err(|_| TransactionError::InvalidRlp)?;
+    Ok(SignedTransaction::new(decoded).map_err(|_| TransactionError::InvalidSignature)?)
+}
+
+/// Snapshot of the chain (see `Blockchain::dump_state`).
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDump {
+    pub best_block: u64,
+    pub blocks: Vec<BlockDump>,
+    pub transaction_count: usize,
+    pub pending_transaction_count: usize,
+    pub accounts: BTreeMap<Address, AccountDump>,
+}
+
+/// Stored block of a chain snapshot.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockDump {
+    pub number: u64,
+    pub hash: H256,
+    pub timestamp: u64,
+    pub transaction_count: usize,
+}
+
+/// Account of a chain snapshot, in the latest state.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDump {
+    pub balance: U256,
+    pub nonce: U256,
+    pub code_size: usize,
+}
+
+/// Transaction execution result.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExecutionResult {
+    pub cumulative_gas_used: U256,
+    pub gas_used: U256,
+    pub log_bloom: Bloom,
+    pub logs: Vec<LogEntry>,
+    pub status_code: u8,
+    /// Why the transaction failed, e.g. `reverted` or `outOfGas`. The status
+    /// code is 0 in that case.
+    pub failure_reason: Option<String>,
+    #[serde(with = "serde_bytes")]
+    pub output: Vec<u8>,
+}
+
+/// A wrapper that exposes a simulated Ethereum block.
+#[derive(Clone, Debug)]
+pub struct EthereumBlock {
+    number: u64,
+    timestamp: u64,
+    hash: H256,
+    parent_hash: H256,
+    author: Address,
+    gas_used: U256,
+    gas_limit: U256,
+    /// Fraction of the gas limit used, as reported by `eth_feeHistory`.
+    gas_used_ratio: f64,
+    log_bloom: Bloom,
+    logs: Vec<LocalizedLogEntry>,
+    transactions: Vec<LocalizedTransaction>,
+    pending: bool,
+}
+
+impl EthereumBlock {
+    /// Create a new Ethereum block.
+    pub fn new(
+        number: u64,
+        parent_hash: H256,
+        author: Address,
+        timestamp: u64,
+        gas_used: U256,
+        gas_limit: U256,
+        log_bloom: Bloom,
+    ) -> Self {
+        // TODO: better blockhash
+        Self {
+            number,
+            parent_hash,
+            author,
+            timestamp,
+            logs: vec![],
+            transactions: vec![],
+            hash: keccak(number.to_string()).into(),
+            gas_used,
+            gas_limit,
+            gas_used_ratio: if gas_limit.is_zero() {
+                0.0
+            } else {
+                gas_used.low_u64() as f64 / gas_limit.low_u64() as f64
+            },
+            log_bloom,
+            pending: false,
+        }
+    }
+
+    /// Create a new pending Ethereum block, which has no hash.
+    pub fn new_pending(
+        number: u64,
+        parent_hash: H256,
+        author: Address,
+        timestamp: u64,
+        gas_limit: U256,
+    ) -> Self {
+        Self {
+            pending: true,
+            ..Self::new(
+                number,
+                parent_hash,
+                author,
+                timestamp,
+                U256::zero(),
+                gas_limit,
+                Default::default(),
+            )
+        }
+    }
+
+    /// Ethereum block number as an u64.
+    pub fn number_u64(&self) -> u64 {
+        self.number
+    }
+
+    /// Block timestamp (seconds since the epoch).
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Block hash.
+    pub fn hash(&self) -> H256 {
+        self.hash
+    }
+
+    /// Fraction of the gas limit used by the block, between 0 and 1.
+    pub fn gas_used_ratio(&self) -> f64 {
+        self.gas_used_ratio
+    }
+
+    /// Ethereum transactions contained in the block.
+    pub fn transactions(&self) -> Vec<LocalizedTransaction> {
+        self.transactions.clone()
+    }
+
+    /// Root of the trie of the RLP-encoded transactions, keyed by index.
+    fn transactions_root(&self) -> H256 {
+        triehash::ordered_trie_root(
+            self.transactions
+                .iter()
+                .map(|txn| rlp::encode(&txn.signed).to_vec()),
+        )
+    }
+
+    /// Retrieve the Ethereum block header.
+    ///
+    /// The state and receipts roots are left at their defaults, the roots of
+    /// empty tries, and the hash of the header is not `self.hash`.
+    pub fn header(&self) -> Header {
+        let mut header = Header::new();
+        header.set_parent_hash(self.parent_hash);
+        header.set_uncles_hash(KECCAK_EMPTY_LIST_RLP);
+        header.set_author(self.author);
+        header.set_transactions_root(self.transactions_root());
+        header.set_number(self.number);
+        header.set_gas_used(self.gas_used);
+        header.set_gas_limit(self.gas_limit);
+        header.set_log_bloom(self.log_bloom);
+        header.set_timestamp(self.timestamp);
+        header
+    }
+
+    /// RLP encoding of the full block, i.e., header, transactions and (no)
+    /// uncles. See `header` for how it differs from a real block.
+    pub fn rlp(&self) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&self.header());
+        stream.begin_list(self.transactions.len());
+        for txn in &self.transactions {
+            stream.append(&txn.signed);
+        }
+        stream.begin_list(0);
+        stream.out()
+    }
+
+    /// Retrieve an Ethereum block header with additional metadata.
+    pub fn rich_header(&self) -> EthRpcRichHeader {
+        EthRpcRichHeader {
+            inner: EthRpcHeader {
+                hash: match self.pending {
+                    true => None,
+                    false => Some(self.hash.into()),
+                },
+                size: None,
+                parent_hash: self.parent_hash.into(),
+                uncles_hash: KECCAK_EMPTY_LIST_RLP.into(), /* empty list */
+                author: self.author.into(),
+                miner: self.author.into(),
                 // TODO: state root
                 state_root: Default::default(),
-                transactions_root: Default::default(),
+                transactions_root: self.transactions_root().into(),
                 receipts_root: Default::default(),
                 number: Some(self.number.into()),
                 gas_used: self.gas_used.into(),
@@ -668,50 +2892,1949 @@ impl EthereumBlock {
         }
     }
 
-    /// Retrieve an Ethereum block with additional metadata.
-    pub fn rich_block(&self, include_txs: bool) -> EthRpcRichBlock {
-        let eip86_transition = genesis::SPEC.params().eip86_transition;
-        let rich_header = self.rich_header();
+    /// Retrieve an Ethereum block with additional metadata.
+    ///
+    /// Transactions are formatted according to the spec's `eip86_transition`.
+    pub fn rich_block(&self, include_txs: bool, eip86_transition: u64) -> EthRpcRichBlock {
+        let rich_header = self.rich_header();
+
+        EthRpcRichBlock {
+            inner: EthRpcBlock {
+                hash: rich_header.hash.clone(),
+                size: rich_header.size,
+                parent_hash: rich_header.parent_hash.clone(),
+                uncles_hash: rich_header.uncles_hash.clone(),
+                author: rich_header.author.clone(),
+                miner: rich_header.miner.clone(),
+                state_root: rich_header.state_root.clone(),
+                transactions_root: rich_header.transactions_root.clone(),
+                receipts_root: rich_header.receipts_root.clone(),
+                number: rich_header.number,
+                gas_used: rich_header.gas_used,
+                gas_limit: rich_header.gas_limit,
+                logs_bloom: Some(rich_header.logs_bloom.clone()),
+                timestamp: rich_header.timestamp,
+                difficulty: rich_header.difficulty,
+                total_difficulty: None,
+                seal_fields: vec![],
+                uncles: vec![],
+                transactions: match include_txs {
+                    true => EthRpcBlockTransactions::Full(
+                        self.transactions
+                            .clone()
+                            .into_iter()
+                            .map(|txn| EthRpcTransaction::from_localized(txn, eip86_transition))
+                            .collect(),
+                    ),
+                    false => EthRpcBlockTransactions::Hashes(
+                        self.transactions
+                            .clone()
+                            .into_iter()
+                            .map(|txn| txn.signed.hash().into())
+                            .collect(),
+                    ),
+                },
+                extra_data: Default::default(),
+            },
+            extra_info: rich_header.extra_info.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, thread};
+
+    use ethcore::{transaction::Transaction, vm::CreateContractAddress};
+
+    use super::*;
+    use crate::{
+        genesis,
+        test_support::{fake_txn, new_blockchain, test_config},
+    };
+
+    /// Wrap runtime bytecode in init code which returns it on deployment, i.e.
+    /// PUSH1 len, DUP1, PUSH1 11, PUSH1 0, CODECOPY, PUSH1 0, RETURN.
+    fn init_code(runtime: &[u8]) -> Vec<u8> {
+        let mut code = vec![
+            0x60, 0x00, 0x80, 0x60, 0x0b, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3,
+        ];
+        code[1] = runtime.len() as u8;
+        code.extend_from_slice(runtime);
+        code
+    }
+
+    /// Append a block containing the given logs to the chain.
+    fn push_block_with_logs(chain_state: &mut ChainState, entries: Vec<LogEntry>) {
+        let parent_hash = chain_state
+            .get_block_by_number(chain_state.block_number)
+            .unwrap()
+            .hash();
+        let number = chain_state.block_number + 1;
+        let mut log_bloom = Bloom::default();
+        for entry in &entries {
+            log_bloom.accrue_bloom(&entry.bloom());
+        }
+        let mut block = EthereumBlock::new(
+            number,
+            parent_hash,
+            Address::zero(),
+            0,
+            U256::zero(),
+            BLOCK_GAS_LIMIT.into(),
+            log_bloom,
+        );
+        let block_hash = block.hash();
+        block.logs = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| LocalizedLogEntry {
+                entry,
+                block_hash,
+                block_number: number,
+                transaction_hash: H256::from(number),
+                transaction_index: 0,
+                transaction_log_index: i,
+                log_index: i,
+            })
+            .collect();
+        chain_state.index_logs(&block.logs);
+        chain_state.blocks.insert(block_hash, block);
+        chain_state.block_number_to_hash.insert(number, block_hash);
+        chain_state.block_number = number;
+    }
+
+    fn log_from(address: Address) -> LogEntry {
+        LogEntry {
+            address,
+            topics: vec![],
+            data: vec![],
+        }
+    }
+
+    fn address_filter(addresses: Option<Vec<Address>>) -> Filter {
+        Filter {
+            from_block: BlockId::Earliest,
+            to_block: BlockId::Latest,
+            address: addresses,
+            topics: vec![None, None, None, None],
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_indexed_logs_skip_unrelated_blocks() {
+        let blockchain = new_blockchain(test_config());
+        let target = Address::from(1);
+        let other = Address::from(2);
+
+        // Only every 100th block contains a log from the target contract.
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            for i in 0..4000 {
+                let mut entries = vec![log_from(other)];
+                if i % 100 == 0 {
+                    entries.push(log_from(target));
+                }
+                push_block_with_logs(&mut chain_state, entries);
+            }
+
+            // Drop all blocks without a target log, so that the filtered path
+            // would panic if it touched any of them.
+            let unrelated: Vec<u64> = (1..=chain_state.block_number)
+                .filter(|number| (number - 1) % 100 != 0)
+                .collect();
+            for number in unrelated {
+                let hash = chain_state.block_number_to_hash[&number];
+                chain_state.blocks.remove(&hash);
+            }
+        }
+
+        let logs = blockchain
+            .logs(address_filter(Some(vec![target])))
+            .wait()
+            .unwrap();
+        assert_eq!(logs.len(), 40);
+        assert!(logs.iter().all(|log| log.entry.address == target));
+        assert!(logs
+            .windows(2)
+            .all(|w| w[0].block_number < w[1].block_number));
+    }
+
+    #[test]
+    fn test_logs_filtered_by_multiple_addresses() {
+        let blockchain = new_blockchain(test_config());
+        let contracts: Vec<Address> = (1..=3).map(Address::from).collect();
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            for _ in 0..3 {
+                // Each contract logs in every block, in reverse address order.
+                let entries = contracts.iter().rev().cloned().map(log_from).collect();
+                push_block_with_logs(&mut chain_state, entries);
+            }
+        }
+
+        let logs = blockchain
+            .logs(address_filter(Some(vec![contracts[2], contracts[0]])))
+            .wait()
+            .unwrap();
+        let found: Vec<(u64, usize, Address)> = logs
+            .iter()
+            .map(|log| (log.block_number, log.log_index, log.entry.address))
+            .collect();
+        let expected: Vec<(u64, usize, Address)> = (1..=3)
+            .flat_map(|number| vec![(number, 0, contracts[2]), (number, 2, contracts[0])])
+            .collect();
+        assert_eq!(found, expected);
+
+        // Topics still apply to the selected addresses.
+        let mut filter = address_filter(Some(vec![contracts[2], contracts[0]]));
+        filter.topics = vec![Some(vec![H256::from(1)]), None, None, None];
+        assert!(blockchain.logs(filter).wait().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_logs_sorted_by_log_index() {
+        let blockchain = new_blockchain(test_config());
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            push_block_with_logs(&mut chain_state, vec![]);
+
+            // Two transactions in the same block, each emitting a log, stored
+            // out of order.
+            let block_hash = chain_state.block_number_to_hash[&1];
+            let block = chain_state.blocks.get_mut(&block_hash).unwrap();
+            block.logs = (0..2)
+                .rev()
+                .map(|i| LocalizedLogEntry {
+                    entry: log_from(Address::from(i as u64)),
+                    block_hash,
+                    block_number: 1,
+                    transaction_hash: H256::from(i as u64),
+                    transaction_index: i,
+                    transaction_log_index: 0,
+                    log_index: i,
+                })
+                .collect();
+        }
+
+        let logs = blockchain.logs(address_filter(None)).wait().unwrap();
+        assert_eq!(
+            logs.iter().map(|log| log.log_index).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert_eq!(
+            logs.iter()
+                .map(|log| log.transaction_index)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_pending_block() {
+        let blockchain = new_blockchain(test_config());
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            push_block_with_logs(&mut chain_state, vec![]);
+        }
+
+        let latest = blockchain.get_latest_block().wait().unwrap();
+        let pending = blockchain.get_pending_block().wait().unwrap();
+        assert_eq!(pending.number_u64(), blockchain.best_block_number() + 1);
+        assert_eq!(pending.parent_hash, latest.hash());
+        assert!(pending.transactions().is_empty());
+        assert!(pending.rich_header().hash.is_none());
+        assert!(latest.rich_header().hash.is_some());
+    }
+
+    #[test]
+    fn test_block_reward_credited_to_coinbase() {
+        let coinbase = Address::from(0x99);
+        let blockchain = new_blockchain(BlockchainConfig {
+            coinbase,
+            block_reward: 1000.into(),
+            ..test_config()
+        });
+
+        let sender = Address::from(1);
+        for nonce in 0..2 {
+            let txn = fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+            blockchain.submit_transaction(txn).unwrap();
+        }
+
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.balance(&coinbase).unwrap(), 2000.into());
+
+        let header = blockchain.get_latest_block().wait().unwrap().rich_header();
+        assert_eq!(header.author, coinbase.into());
+        assert_eq!(header.miner, coinbase.into());
+    }
+
+    #[test]
+    fn test_create_receipt_contract_address() {
+        let blockchain = new_blockchain(test_config());
+
+        // SLOAD(0) and return it.
+        let runtime = vec![
+            0x60, 0x00, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+        ];
+        let txn = fake_txn(Address::from(1), 0, Action::Create, init_code(&runtime));
+        let (hash, result) = blockchain.submit_transaction(txn).unwrap();
+        assert_eq!(result.unwrap().status_code, 1);
+
+        let receipt = blockchain
+            .get_txn_receipt_by_hash(hash)
+            .wait()
+            .unwrap()
+            .unwrap();
+        let address = receipt.contract_address.unwrap();
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(*state.code(&address).unwrap().unwrap(), runtime);
+    }
+
+    #[test]
+    fn test_create_receipt_contract_address_scheme() {
+        // With EIP-86, created addresses depend on the code rather than the
+        // sender's nonce.
+        let spec_json = include_str!("../resources/genesis.json").replace(
+            r#""eip86Transition": "0xffffffffffffffff""#,
+            r#""eip86Transition": "0x0""#,
+        );
+        let spec = Spec::load(Cursor::new(spec_json)).unwrap();
+        let blockchain = Blockchain::new(test_config(), spec, Arc::new(MockClient::new())).unwrap();
+
+        let sender = Address::from(1);
+        let code = init_code(&[0x00]);
+        let txn = fake_txn(sender, 0, Action::Create, code.clone());
+        let (hash, _) = blockchain.submit_transaction(txn).unwrap();
+        let receipt = blockchain
+            .get_txn_receipt_by_hash(hash)
+            .wait()
+            .unwrap()
+            .unwrap();
+        let address = receipt.contract_address.unwrap();
+        let (nonce_address, _) = contract_address(
+            CreateContractAddress::FromSenderAndNonce,
+            &sender,
+            &U256::zero(),
+            &code,
+        );
+        assert_ne!(address, nonce_address);
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(*state.code(&address).unwrap().unwrap(), vec![0x00]);
+    }
+
+    #[test]
+    fn test_account_start_nonce() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            account_start_nonce: Some(5.into()),
+            ..test_config()
+        });
+        let sender = Address::from(1);
+        assert_eq!(blockchain.pending_nonce(&sender).unwrap(), 5.into());
+
+        let code = init_code(&[0x00]);
+        let txn = fake_txn(sender, 0, Action::Create, code.clone());
+        assert!(blockchain.submit_transaction(txn).is_err());
+
+        // The first deployment of a fresh account uses the start nonce.
+        let txn = fake_txn(sender, 5, Action::Create, code.clone());
+        let (hash, _) = blockchain.submit_transaction(txn).unwrap();
+        let receipt = blockchain
+            .get_txn_receipt_by_hash(hash)
+            .wait()
+            .unwrap()
+            .unwrap();
+        let (expected, _) = contract_address(
+            blockchain.spec().engine.create_address_scheme(1),
+            &sender,
+            &5.into(),
+            &code,
+        );
+        assert_eq!(receipt.contract_address, Some(expected));
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(*state.code(&expected).unwrap().unwrap(), vec![0x00]);
+        assert_eq!(state.nonce(&sender).unwrap(), 6.into());
+    }
+
+    #[test]
+    fn test_genesis_alloc() {
+        let new_blockchain_with_alloc = |alloc: StateOverride| {
+            Blockchain::new(
+                BlockchainConfig {
+                    genesis_alloc: alloc,
+                    ..test_config()
+                },
+                genesis::oasis_spec(),
+                Arc::new(MockClient::new()),
+            )
+        };
+        let address = Address::from(1);
+        let mut storage = HashMap::new();
+        storage.insert(H256::from(1), H256::from(2));
+        let mut alloc = StateOverride::new();
+        alloc.insert(
+            address,
+            AccountOverride {
+                balance: Some(100.into()),
+                nonce: Some(3.into()),
+                code: Some(vec![0x00]),
+                state_diff: Some(storage),
+                ..Default::default()
+            },
+        );
+
+        let blockchain = new_blockchain_with_alloc(alloc.clone()).unwrap();
+        assert_eq!(blockchain.best_block_number(), 0);
+        let state = blockchain.state(BlockId::Number(0)).unwrap();
+        assert_eq!(state.balance(&address).unwrap(), 100.into());
+        assert_eq!(state.nonce(&address).unwrap(), 3.into());
+        assert_eq!(*state.code(&address).unwrap().unwrap(), vec![0x00]);
+        assert_eq!(
+            state.storage_at(&address, &H256::from(1)).unwrap(),
+            H256::from(2)
+        );
+
+        // Entries which cannot be applied fail initialization.
+        alloc.get_mut(&address).unwrap().state = Some(HashMap::new());
+        assert!(new_blockchain_with_alloc(alloc).is_err());
+    }
+
+    #[test]
+    fn test_genesis_not_reapplied_to_existing_store() {
+        let spec = genesis::oasis_spec();
+        let address = Address::from(1);
+        let alloc_balance = |balance: u64| {
+            let mut alloc = StateOverride::new();
+            alloc.insert(
+                address,
+                AccountOverride {
+                    balance: Some(balance.into()),
+                    ..Default::default()
+                },
+            );
+            alloc
+        };
+
+        let store = MemoryMKVS::new();
+        let mut chain_state = ChainState::with_store(
+            store.clone(),
+            &spec,
+            Default::default(),
+            U256::zero(),
+            &alloc_balance(100),
+        )
+        .unwrap();
+        // Later state versions are not part of the genesis state.
+        chain_state
+            .update_best_state(None, |state| state.set_balance(&address, &1.into()))
+            .unwrap();
+        assert_eq!(
+            chain_state.best_state().unwrap().balance(&address).unwrap(),
+            1.into()
+        );
+
+        // Loading the store keeps its genesis state, ignoring the allocation.
+        let chain_state = ChainState::with_store(
+            store.clone(),
+            &spec,
+            Default::default(),
+            U256::zero(),
+            &alloc_balance(200),
+        )
+        .unwrap();
+        assert_eq!(chain_state.block_number, 0);
+        let state = chain_state.state_at(0).unwrap();
+        assert_eq!(state.balance(&address).unwrap(), 100.into());
+        assert_eq!(
+            chain_state.best_state().unwrap().balance(&address).unwrap(),
+            100.into()
+        );
+
+        // A store of another genesis spec is rejected.
+        store.set_metadata(GENESIS_ROOT_KEY, &H256::from(1));
+        let err = ChainState::with_store(
+            store,
+            &spec,
+            Default::default(),
+            U256::zero(),
+            &Default::default(),
+        )
+        .err()
+        .unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("store was initialized with genesis state root"));
+
+        // So is a non-empty store without genesis state.
+        let mut store = MemoryMKVS::new();
+        store.insert(b"key", b"value");
+        store.commit(0);
+        let err = ChainState::with_store(
+            store,
+            &spec,
+            Default::default(),
+            U256::zero(),
+            &Default::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "store is not empty, but has no genesis state"
+        );
+    }
+
+    #[test]
+    fn test_create_access_list() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+        let contract = Address::from(0x1234);
+
+        // BALANCE(0x99), SLOAD(5) and return it.
+        let runtime = vec![
+            0x60, 0x99, 0x31, 0x50, 0x60, 0x05, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00,
+            0xf3,
+        ];
+        blockchain.set_code(contract, runtime).unwrap();
+        let txn = fake_txn(sender, 0, Action::Call(contract), vec![]);
+        let (access_list, executed) = blockchain
+            .create_access_list(txn, BlockId::Latest)
+            .wait()
+            .unwrap();
+        assert!(executed.exception.is_none());
+        assert!(executed.gas_used > 21_000.into());
+        let expected: AccessList = vec![
+            (contract, vec![H256::from(5)].into_iter().collect()),
+            (Address::from(0x99), BTreeSet::new()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(access_list, expected);
+
+        // Accesses made before a revert are still reported.
+        // SLOAD(7), REVERT(0, 0).
+        let runtime = vec![0x60, 0x07, 0x54, 0x50, 0x60, 0x00, 0x60, 0x00, 0xfd];
+        blockchain.set_code(contract, runtime).unwrap();
+        let txn = fake_txn(sender, 0, Action::Call(contract), vec![]);
+        let (access_list, executed) = blockchain
+            .create_access_list(txn, BlockId::Latest)
+            .wait()
+            .unwrap();
+        match executed.exception {
+            Some(VmError::Reverted) => {}
+            ref exception => panic!("unexpected exception {:?}", exception),
+        }
+        let slots: Vec<H256> = access_list[&contract].iter().cloned().collect();
+        assert_eq!(slots, vec![H256::from(7)]);
+    }
+
+    #[test]
+    fn test_simulator_threads() {
+        let new_blockchain_with_threads = |simulator_threads| {
+            Blockchain::new(
+                BlockchainConfig {
+                    simulator_threads,
+                    ..test_config()
+                },
+                genesis::oasis_spec(),
+                Arc::new(MockClient::new()),
+            )
+        };
+        assert!(new_blockchain_with_threads(0).is_err());
+
+        // Simulations beyond the pool size wait for a thread.
+        let blockchain = new_blockchain_with_threads(2).unwrap();
+        let simulations: Vec<_> = (0..8)
+            .map(|i| {
+                let txn = fake_txn(
+                    Address::from(i + 1),
+                    0,
+                    Action::Call(Address::from(2)),
+                    vec![],
+                );
+                blockchain.simulate_transaction(txn, BlockId::Latest, None)
+            })
+            .collect();
+        let results = future::join_all(simulations).wait().unwrap();
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|executed| executed.exception.is_none()));
+    }
+
+    #[test]
+    fn test_simulate_state_override() {
+        let blockchain = new_blockchain(test_config());
+        let contract = Address::from(0x1234);
+
+        // SLOAD(0) and return it.
+        let runtime = vec![
+            0x60, 0x00, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+        ];
+        let mut storage = HashMap::new();
+        storage.insert(H256::zero(), H256::from(42));
+        let mut overrides = StateOverride::new();
+        overrides.insert(
+            contract,
+            AccountOverride {
+                code: Some(runtime),
+                state_diff: Some(storage),
+                ..Default::default()
+            },
+        );
+
+        let txn = fake_txn(Address::from(1), 0, Action::Call(contract), vec![]);
+        let executed = blockchain
+            .simulate_transaction(txn.clone(), BlockId::Latest, Some(overrides))
+            .wait()
+            .unwrap();
+        assert_eq!(executed.output, H256::from(42).to_vec());
+
+        // Overrides must not persist after the call.
+        let executed = blockchain
+            .simulate_transaction(txn, BlockId::Latest, None)
+            .wait()
+            .unwrap();
+        assert!(executed.output.is_empty());
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.code(&contract).unwrap(), None);
+    }
+
+    #[test]
+    fn test_simulate_full_state_override() {
+        let blockchain = new_blockchain(test_config());
+        let contract = Address::from(0x1234);
+
+        // SLOAD(0) and return it.
+        let runtime = vec![
+            0x60, 0x00, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+        ];
+        blockchain.set_code(contract, runtime).unwrap();
+        blockchain
+            .set_storage(contract, H256::zero(), H256::from(42))
+            .unwrap();
+
+        // `state` replaces the entire storage, so slot 0 reads zero.
+        let mut storage = HashMap::new();
+        storage.insert(H256::from(1), H256::from(7));
+        let mut overrides = StateOverride::new();
+        overrides.insert(
+            contract,
+            AccountOverride {
+                state: Some(storage.clone()),
+                ..Default::default()
+            },
+        );
+        let txn = fake_txn(Address::from(1), 0, Action::Call(contract), vec![]);
+        let executed = blockchain
+            .simulate_transaction(txn.clone(), BlockId::Latest, Some(overrides))
+            .wait()
+            .unwrap();
+        assert_eq!(executed.output, H256::zero().to_vec());
+
+        // `state` and `stateDiff` cannot be combined.
+        let mut overrides = StateOverride::new();
+        overrides.insert(
+            contract,
+            AccountOverride {
+                state: Some(storage.clone()),
+                state_diff: Some(storage),
+                ..Default::default()
+            },
+        );
+        let err = blockchain
+            .simulate_transaction(txn, BlockId::Latest, Some(overrides))
+            .wait()
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("both state and stateDiff"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_simulate_balance_override() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+        let contract = Address::from(0x1234);
+
+        // BALANCE(CALLER) and return it.
+        let runtime = vec![0x33, 0x31, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+        let mut overrides = StateOverride::new();
+        overrides.insert(
+            contract,
+            AccountOverride {
+                code: Some(runtime),
+                ..Default::default()
+            },
+        );
+        overrides.insert(
+            sender,
+            AccountOverride {
+                balance: Some(1_000.into()),
+                ..Default::default()
+            },
+        );
+
+        let txn = fake_txn(sender, 0, Action::Call(contract), vec![]);
+        let executed = blockchain
+            .simulate_transaction(txn, BlockId::Latest, Some(overrides))
+            .wait()
+            .unwrap();
+        assert_eq!(executed.output, H256::from(1_000).to_vec());
+    }
+
+    #[test]
+    fn test_simulate_from_unfunded_sender() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+        let contract = Address::from(0x1234);
+
+        // BALANCE(ADDRESS) and return it.
+        let runtime = vec![0x30, 0x31, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+        blockchain.set_code(contract, runtime).unwrap();
+
+        let txn = Transaction {
+            nonce: U256::zero(),
+            gas_price: 1_000.into(),
+            gas: 1_000_000.into(),
+            action: Action::Call(contract),
+            value: 1_000.into(),
+            data: vec![],
+        }
+        .fake_sign(sender);
+        let executed = blockchain
+            .simulate_transaction(txn, BlockId::Latest, None)
+            .wait()
+            .unwrap();
+        assert!(executed.exception.is_none());
+        assert_eq!(executed.output, H256::from(1_000).to_vec());
+
+        // The transfer is only reflected in the simulation state.
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.balance(&sender).unwrap(), U256::zero());
+        assert_eq!(state.balance(&contract).unwrap(), U256::zero());
+    }
+
+    #[test]
+    fn test_block_timestamps_strictly_increasing() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+
+        let mut timestamps = vec![];
+        for nonce in 0..3 {
+            let txn = fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+            blockchain.submit_transaction(txn).unwrap();
+            let block = blockchain.get_latest_block().wait().unwrap();
+            timestamps.push(block.timestamp());
+        }
+
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_set_next_block_timestamp() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+        let forced = util::get_timestamp() + 1_000;
+
+        blockchain.set_next_block_timestamp(forced).unwrap();
+        let pending = blockchain.get_pending_block().wait().unwrap();
+        assert_eq!(pending.timestamp(), forced);
+
+        let txn = fake_txn(sender, 0, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(txn).unwrap();
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.timestamp(), forced);
+
+        // Timestamps cannot go backwards.
+        assert!(blockchain.set_next_block_timestamp(forced).is_err());
+
+        // The forced timestamp only applies to a single block.
+        let txn = fake_txn(sender, 1, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(txn).unwrap();
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.timestamp(), forced + 1);
+
+        // Timestamps near the end of time do not overflow.
+        let max = u64::max_value();
+        assert!(blockchain.set_next_block_timestamp(max).is_err());
+        blockchain.set_next_block_timestamp(max - 1).unwrap();
+        assert_eq!(blockchain.mine_blocks(3, Some(10)).unwrap(), 5);
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.timestamp(), max);
+    }
+
+    #[test]
+    fn test_deterministic_block_time() {
+        let block_time = BlockTime::Deterministic {
+            genesis_timestamp: 1_000,
+            step: 1,
+        };
+        let mine = || {
+            let blockchain = new_blockchain(BlockchainConfig {
+                mining_mode: MiningMode::Manual,
+                block_time,
+                ..test_config()
+            });
+            let txn = fake_txn(Address::from(1), 0, Action::Call(Address::from(2)), vec![]);
+            blockchain.submit_transaction(txn).unwrap();
+            assert_eq!(blockchain.mine_blocks(5, None).unwrap(), 5);
+            (0..=5)
+                .map(|number| {
+                    let block = blockchain
+                        .get_block_by_number(number)
+                        .wait()
+                        .unwrap()
+                        .unwrap();
+                    (block.timestamp(), block.rlp())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Block hashes only depend on block numbers, so compare the encoded
+        // blocks, whose headers include the timestamps.
+        let blocks = mine();
+        assert_eq!(blocks, mine());
+        let timestamps: Vec<u64> = blocks.iter().map(|(timestamp, _)| *timestamp).collect();
+        assert_eq!(timestamps, vec![1_000, 1_001, 1_002, 1_003, 1_004, 1_005]);
+
+        // A forced timestamp still applies, and later blocks step from it.
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            block_time,
+            ..test_config()
+        });
+        blockchain.set_next_block_timestamp(2_000).unwrap();
+        assert_eq!(blockchain.mine_blocks(2, None).unwrap(), 2);
+        let latest = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(latest.timestamp(), 2_001);
+
+        let config = BlockchainConfig {
+            block_time: BlockTime::Deterministic {
+                genesis_timestamp: 0,
+                step: 0,
+            },
+            ..test_config()
+        };
+        assert!(
+            Blockchain::new(config, genesis::oasis_spec(), Arc::new(MockClient::new())).is_err()
+        );
+    }
+
+    #[test]
+    fn test_future_nonce_queued_until_gap_filled() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+        let call = |nonce| fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+
+        // Submit out of order: nonces 2 and 1 are queued.
+        let (hash2, result) = blockchain.submit_transaction(call(2)).unwrap();
+        assert!(result.is_none());
+        let (hash1, result) = blockchain.submit_transaction(call(1)).unwrap();
+        assert!(result.is_none());
+        assert_eq!(blockchain.best_block_number(), 0);
+
+        // Filling the gap mines the queued transactions in nonce order.
+        let (hash0, result) = blockchain.submit_transaction(call(0)).unwrap();
+        assert_eq!(result.unwrap().status_code, 1);
+        assert_eq!(blockchain.best_block_number(), 3);
+        for (number, hash) in vec![hash0, hash1, hash2].into_iter().enumerate() {
+            let receipt = blockchain
+                .get_txn_receipt_by_hash(hash)
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert_eq!(receipt.block_number, number as u64 + 1);
+        }
+        assert!(blockchain.chain_state.read().unwrap().queued.is_empty());
+
+        // Stale nonces are rejected.
+        let err = blockchain.submit_transaction(call(1)).unwrap_err();
+        assert_eq!(err.to_string(), "nonce too low");
+    }
+
+    #[test]
+    fn test_switch_to_auto_mines_pending() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Interval(Duration::from_secs(3_600)),
+            ..test_config()
+        });
+        let sender = Address::from(1);
+        let call = |nonce| fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+
+        // Interval mining buffers the transactions until the next tick.
+        let (hash0, result) = blockchain.submit_transaction(call(0)).unwrap();
+        assert!(result.is_none());
+        let (hash1, _) = blockchain.submit_transaction(call(1)).unwrap();
+        assert_eq!(blockchain.pending_transaction_count(), 2);
+        assert_eq!(blockchain.best_block_number(), 0);
+
+        // Switching to auto mining mines them right away.
+        blockchain.set_mining_mode(MiningMode::Auto);
+        assert_eq!(blockchain.pending_transaction_count(), 0);
+        assert_eq!(blockchain.best_block_number(), 1);
+        for hash in vec![hash0, hash1] {
+            let receipt = blockchain
+                .get_txn_receipt_by_hash(hash)
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert_eq!(receipt.block_number, 1);
+        }
+
+        // Later transactions are mined as they are submitted.
+        let (_hash, result) = blockchain.submit_transaction(call(2)).unwrap();
+        assert_eq!(result.unwrap().status_code, 1);
+        assert_eq!(blockchain.best_block_number(), 2);
+    }
+
+    #[test]
+    fn test_manual_mining_seals_pending_transactions() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let sender = Address::from(1);
 
-        EthRpcRichBlock {
-            inner: EthRpcBlock {
-                hash: rich_header.hash.clone(),
-                size: rich_header.size,
-                parent_hash: rich_header.parent_hash.clone(),
-                uncles_hash: rich_header.uncles_hash.clone(),
-                author: rich_header.author.clone(),
-                miner: rich_header.miner.clone(),
-                state_root: rich_header.state_root.clone(),
-                transactions_root: rich_header.transactions_root.clone(),
-                receipts_root: rich_header.receipts_root.clone(),
-                number: rich_header.number,
-                gas_used: rich_header.gas_used,
-                gas_limit: rich_header.gas_limit,
-                logs_bloom: Some(rich_header.logs_bloom.clone()),
-                timestamp: rich_header.timestamp,
-                difficulty: rich_header.difficulty,
-                total_difficulty: None,
-                seal_fields: vec![],
-                uncles: vec![],
-                transactions: match include_txs {
-                    true => EthRpcBlockTransactions::Full(
-                        self.transactions
-                            .clone()
-                            .into_iter()
-                            .map(|txn| EthRpcTransaction::from_localized(txn, eip86_transition))
-                            .collect(),
-                    ),
-                    false => EthRpcBlockTransactions::Hashes(
-                        self.transactions
-                            .clone()
-                            .into_iter()
-                            .map(|txn| txn.signed.hash().into())
-                            .collect(),
-                    ),
-                },
-                extra_data: Default::default(),
+        // Log emitter: LOG1 with the first calldata word as topic.
+        let runtime = vec![0x60, 0x00, 0x35, 0x60, 0x00, 0x60, 0x00, 0xa1, 0x00];
+        let (deploy_hash, result) = blockchain
+            .submit_transaction(fake_txn(sender, 0, Action::Create, init_code(&runtime)))
+            .unwrap();
+        assert!(result.is_none());
+        assert_eq!(blockchain.best_block_number(), 0);
+        assert_eq!(blockchain.mine_pending_block(), 1);
+        let contract = blockchain
+            .get_txn_receipt_by_hash(deploy_hash)
+            .wait()
+            .unwrap()
+            .unwrap()
+            .contract_address
+            .unwrap();
+
+        let mut hashes = vec![];
+        for nonce in 1..4 {
+            let data = H256::from(nonce).to_vec();
+            let txn = fake_txn(sender, nonce, Action::Call(contract), data);
+            let (hash, result) = blockchain.submit_transaction(txn).unwrap();
+            assert!(result.is_none());
+            hashes.push(hash);
+        }
+        let pending = blockchain.get_pending_block().wait().unwrap();
+        assert_eq!(pending.transactions().len(), 3);
+        assert_eq!(blockchain.best_block_number(), 1);
+
+        // All pending transactions are sealed into a single block.
+        assert_eq!(blockchain.mine_pending_block(), 2);
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.transactions().len(), 3);
+
+        let mut cumulative_gas_used = U256::zero();
+        for (index, hash) in hashes.into_iter().enumerate() {
+            let receipt = blockchain
+                .get_txn_receipt_by_hash(hash)
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert_eq!(receipt.block_number, 2);
+            assert_eq!(receipt.transaction_index, index);
+            assert_eq!(receipt.logs[0].log_index, index);
+            cumulative_gas_used = cumulative_gas_used + receipt.gas_used;
+            assert_eq!(receipt.cumulative_gas_used, cumulative_gas_used);
+            assert!(block.log_bloom.contains_bloom(&receipt.log_bloom));
+        }
+        assert_eq!(block.gas_used, cumulative_gas_used);
+
+        // Mining without pending transactions produces an empty block.
+        assert_eq!(blockchain.mine_pending_block(), 3);
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert!(block.transactions().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_submissions_mine_distinct_blocks() {
+        let blockchain = Arc::new(new_blockchain(test_config()));
+        let threads: Vec<_> = (1..=8)
+            .map(|sender| {
+                let blockchain = blockchain.clone();
+                thread::spawn(move || {
+                    let txn = fake_txn(
+                        Address::from(sender),
+                        0,
+                        Action::Call(Address::from(0x1234)),
+                        vec![],
+                    );
+                    blockchain.submit_transaction(txn).unwrap().0
+                })
+            })
+            .collect();
+        let hashes: Vec<H256> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+
+        assert_eq!(blockchain.best_block_number(), 8);
+        let mut numbers: Vec<u64> = hashes
+            .into_iter()
+            .map(|hash| {
+                blockchain
+                    .get_txn_receipt_by_hash(hash)
+                    .wait()
+                    .unwrap()
+                    .unwrap()
+                    .block_number
+            })
+            .collect();
+        numbers.sort();
+        assert_eq!(numbers, (1..=8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_concurrent_submissions_with_same_nonce() {
+        let blockchain = Arc::new(new_blockchain(test_config()));
+        let threads: Vec<_> = (0..4)
+            .map(|data| {
+                let blockchain = blockchain.clone();
+                thread::spawn(move || {
+                    let txn = fake_txn(
+                        Address::from(1),
+                        0,
+                        Action::Call(Address::from(0x1234)),
+                        vec![data],
+                    );
+                    blockchain.submit_transaction(txn).is_ok()
+                })
+            })
+            .collect();
+        let mined = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .filter(|mined| *mined)
+            .count();
+
+        // Only one of the transactions applies.
+        assert_eq!(mined, 1);
+        assert_eq!(blockchain.best_block_number(), 1);
+    }
+
+    #[test]
+    fn test_delayed_instamine() {
+        let blockchain = Arc::new(new_blockchain(BlockchainConfig {
+            instamine_delay: Duration::from_millis(50),
+            ..test_config()
+        }));
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.spawn(Blockchain::delayed_instamine(blockchain.clone()));
+
+        let txn = fake_txn(Address::from(1), 0, Action::Call(Address::from(2)), vec![]);
+        let (hash, result) = blockchain.submit_transaction(txn).unwrap();
+        assert!(result.is_none());
+        assert_eq!(blockchain.pending_transaction_count(), 1);
+        assert_eq!(blockchain.best_block_number(), 0);
+
+        for _ in 0..40 {
+            if blockchain.best_block_number() > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(blockchain.best_block_number(), 1);
+        assert_eq!(blockchain.pending_transaction_count(), 0);
+        let receipt = blockchain
+            .get_txn_receipt_by_hash(hash)
+            .wait()
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.block_number, 1);
+        runtime.shutdown_now().wait().unwrap();
+    }
+
+    #[test]
+    fn test_block_bloom_aggregates_receipts() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let sender = Address::from(1);
+
+        // Log emitter: LOG1 with the first calldata word as topic.
+        let contract = Address::from(0x1234);
+        let runtime = vec![0x60, 0x00, 0x35, 0x60, 0x00, 0x60, 0x00, 0xa1, 0x00];
+        blockchain.set_code(contract, runtime).unwrap();
+
+        let topics = vec![H256::from(1), H256::from(2)];
+        let mut hashes = vec![];
+        for (nonce, topic) in topics.iter().enumerate() {
+            let txn = fake_txn(sender, nonce as u64, Action::Call(contract), topic.to_vec());
+            hashes.push(blockchain.submit_transaction(txn).unwrap().0);
+        }
+        assert_eq!(blockchain.mine_pending_block(), 1);
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.transactions().len(), 2);
+
+        let topic_bloom = |topic: H256| {
+            LogEntry {
+                address: contract,
+                topics: vec![topic],
+                data: vec![],
+            }
+            .bloom()
+        };
+        for (hash, topic) in hashes.into_iter().zip(topics.iter()) {
+            // The block bloom matches the topics of both transactions, while
+            // each receipt keeps its own bloom.
+            assert!(block.log_bloom.contains_bloom(&topic_bloom(*topic)));
+            let receipt = blockchain
+                .get_txn_receipt_by_hash(hash)
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert_eq!(receipt.log_bloom, topic_bloom(*topic));
+            assert_ne!(receipt.log_bloom, block.log_bloom);
+        }
+        assert!(!block.log_bloom.contains_bloom(&topic_bloom(H256::from(3))));
+
+        // Bloom pre-filtering finds the logs of either transaction.
+        for topic in topics {
+            let logs = blockchain
+                .logs(Filter {
+                    from_block: BlockId::Latest,
+                    to_block: BlockId::Latest,
+                    address: None,
+                    topics: vec![Some(vec![topic]), None, None, None],
+                    limit: None,
+                })
+                .wait()
+                .unwrap();
+            assert_eq!(logs.len(), 1);
+            assert_eq!(logs[0].entry.topics, vec![topic]);
+        }
+    }
+
+    #[test]
+    fn test_block_rlp_round_trip() {
+        let blockchain = new_blockchain(test_config());
+        for nonce in 0..2 {
+            let txn = fake_txn(
+                Address::from(1),
+                nonce,
+                Action::Call(Address::from(2)),
+                vec![],
+            );
+            blockchain.submit_transaction(txn).unwrap();
+        }
+        let block = blockchain.get_block_by_number(2).wait().unwrap().unwrap();
+
+        let raw = block.rlp();
+        let rlp = rlp::Rlp::new(&raw);
+        assert_eq!(rlp.item_count().unwrap(), 3);
+        assert_eq!(rlp.at(1).unwrap().item_count().unwrap(), 1);
+        assert_eq!(rlp.at(2).unwrap().item_count().unwrap(), 0);
+
+        let header: Header = rlp.val_at(0).unwrap();
+        let rich_header = block.rich_header();
+        assert_eq!(
+            *header.parent_hash(),
+            rich_header.parent_hash.clone().into()
+        );
+        assert_eq!(
+            *header.uncles_hash(),
+            rich_header.uncles_hash.clone().into()
+        );
+        assert_eq!(*header.author(), rich_header.author.clone().into());
+        assert_eq!(
+            *header.transactions_root(),
+            rich_header.transactions_root.clone().into()
+        );
+        assert_ne!(*header.transactions_root(), H256::zero());
+        assert_eq!(
+            U256::from(header.number()),
+            rich_header.number.unwrap().into()
+        );
+        assert_eq!(*header.gas_used(), rich_header.gas_used.into());
+        assert_eq!(*header.gas_limit(), rich_header.gas_limit.into());
+        assert_eq!(*header.log_bloom(), rich_header.logs_bloom.clone().into());
+        assert_eq!(U256::from(header.timestamp()), rich_header.timestamp.into());
+    }
+
+    #[test]
+    fn test_cumulative_gas_used_across_block() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let hashes: Vec<H256> = (0..3)
+            .map(|nonce| {
+                let data = vec![0xff; nonce as usize * 32];
+                let txn = fake_txn(
+                    Address::from(1),
+                    nonce,
+                    Action::Call(Address::from(2)),
+                    data,
+                );
+                blockchain.submit_transaction(txn).unwrap().0
+            })
+            .collect();
+        blockchain.mine_pending_block();
+
+        let mut previous = U256::zero();
+        for hash in hashes {
+            let receipt = blockchain
+                .get_txn_receipt_by_hash(hash)
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert!(receipt.cumulative_gas_used > previous);
+            assert_eq!(receipt.cumulative_gas_used - previous, receipt.gas_used);
+            previous = receipt.cumulative_gas_used;
+        }
+        let header = blockchain.get_latest_block().wait().unwrap().rich_header();
+        assert_eq!(previous, header.gas_used.into());
+    }
+
+    #[test]
+    fn test_typed_transaction_unsupported() {
+        let blockchain = new_blockchain(test_config());
+        // An (empty) EIP-2930 access list transaction.
+        let raw = vec![0x01, 0xc0];
+        let err = blockchain.send_raw_transaction(raw).wait().unwrap_err();
+        assert_eq!(err.to_string(), "unsupported transaction type 0x1");
+
+        let err = blockchain
+            .send_raw_transaction(vec![0xc0])
+            .wait()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "could not decode transaction");
+    }
+
+    #[test]
+    fn test_max_transaction_size() {
+        let blockchain = new_blockchain(test_config());
+        let raw_txn = |nonce: u64, size: usize| {
+            rlp::encode(&*fake_txn(
+                Address::from(1),
+                nonce,
+                Action::Call(Address::from(2)),
+                vec![0; size],
+            ))
+            .to_vec()
+        };
+        // Size the data so the raw transaction is exactly at the limit.
+        let overhead = raw_txn(0, MAX_TRANSACTION_SIZE).len() - MAX_TRANSACTION_SIZE;
+        let data_size = MAX_TRANSACTION_SIZE - overhead;
+        let raw = raw_txn(0, data_size);
+        assert_eq!(raw.len(), MAX_TRANSACTION_SIZE);
+        blockchain.send_raw_transaction(raw).wait().unwrap();
+
+        let raw = raw_txn(1, data_size + 1);
+        let err = blockchain.send_raw_transaction(raw).wait().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "transaction too large: {} bytes, limit {}",
+                MAX_TRANSACTION_SIZE + 1,
+                MAX_TRANSACTION_SIZE
+            )
+        );
+        assert_eq!(blockchain.best_block_number(), 1);
+    }
+
+    #[test]
+    fn test_insufficient_funds_rejected() {
+        let blockchain = new_blockchain(test_config());
+        let raw = rlp::encode(
+            &*Transaction {
+                nonce: U256::zero(),
+                gas_price: 1.into(),
+                gas: 21_000.into(),
+                action: Action::Call(Address::from(2)),
+                value: 5.into(),
+                data: vec![],
+            }
+            .fake_sign(Address::from(1)),
+        )
+        .to_vec();
+        let sender = decode_raw_transaction(&raw).unwrap().sender();
+        blockchain.set_balance(sender, 21_004.into()).unwrap();
+
+        let err = blockchain.send_raw_transaction(raw).wait().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "insufficient funds for gas * price + value: address {:?} have 21004 want 21005",
+                sender
+            )
+        );
+        assert_eq!(blockchain.best_block_number(), 0);
+    }
+
+    #[test]
+    fn test_drop_transaction() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let first = fake_txn(Address::from(1), 0, Action::Call(Address::from(3)), vec![]);
+        let second = fake_txn(Address::from(2), 0, Action::Call(Address::from(3)), vec![]);
+        let (first_hash, _) = blockchain.submit_transaction(first).unwrap();
+        let (second_hash, _) = blockchain.submit_transaction(second).unwrap();
+
+        assert!(blockchain.drop_transaction(first_hash));
+        assert!(!blockchain.drop_transaction(first_hash));
+        assert_eq!(blockchain.mine_pending_block(), 1);
+        let block = blockchain.get_latest_block().wait().unwrap();
+        let hashes: Vec<H256> = block.transactions().iter().map(|txn| txn.hash()).collect();
+        assert_eq!(hashes, vec![second_hash]);
+
+        // Mined transactions cannot be dropped.
+        assert!(!blockchain.drop_transaction(second_hash));
+    }
+
+    #[test]
+    fn test_drop_transaction_frees_nonce() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let sender = Address::from(1);
+        let call = |nonce, to| fake_txn(sender, nonce, Action::Call(Address::from(to)), vec![]);
+        let (dropped, _) = blockchain.submit_transaction(call(0, 2)).unwrap();
+        blockchain.submit_transaction(call(1, 2)).unwrap();
+        let (queued, _) = blockchain.submit_transaction(call(3, 2)).unwrap();
+
+        // The later pending transaction waits for a replacement.
+        assert!(blockchain.drop_transaction(dropped));
+        assert_eq!(blockchain.pending_transaction_count(), 0);
+        assert!(blockchain.drop_transaction(queued));
+
+        let (replacement, _) = blockchain.submit_transaction(call(0, 3)).unwrap();
+        assert_eq!(blockchain.pending_transaction_count(), 2);
+        blockchain.mine_pending_block();
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.transactions().len(), 2);
+        assert_eq!(block.transactions()[0].hash(), replacement);
+    }
+
+    #[test]
+    fn test_discard_pending_transactions() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let sender = Address::from(1);
+        for nonce in &[0, 1, 3] {
+            let txn = fake_txn(sender, *nonce, Action::Call(Address::from(2)), vec![]);
+            blockchain.submit_transaction(txn).unwrap();
+        }
+        assert_eq!(blockchain.pending_transaction_count(), 2);
+
+        // Both pending and queued transactions are dropped.
+        assert_eq!(blockchain.discard_pending_transactions(), 3);
+        assert_eq!(blockchain.pending_transaction_count(), 0);
+        assert_eq!(blockchain.mine_pending_block(), 1);
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert!(block.transactions().is_empty());
+    }
+
+    #[test]
+    fn test_set_balance_and_nonce() {
+        let blockchain = new_blockchain(test_config());
+        let address = Address::from(1);
+        let txn = fake_txn(address, 0, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(txn).unwrap();
+
+        blockchain.set_balance(address, 1_000.into()).unwrap();
+        blockchain.set_nonce(address, 6.into()).unwrap();
+        blockchain.set_nonce(address, 5.into()).unwrap();
+        assert!(blockchain
+            .set_nonce(address, U256::from(u64::max_value()) + U256::one())
+            .is_err());
+
+        // Only the latest state is changed, and no block is mined.
+        assert_eq!(blockchain.best_block_number(), 1);
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.balance(&address).unwrap(), 1_000.into());
+        assert_eq!(state.nonce(&address).unwrap(), 5.into());
+        let state = blockchain.state(BlockId::Number(0)).unwrap();
+        assert_eq!(state.balance(&address).unwrap(), U256::zero());
+        assert_eq!(state.nonce(&address).unwrap(), U256::zero());
+
+        // Later blocks build on the changed state.
+        let txn = fake_txn(address, 5, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(txn).unwrap();
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.balance(&address).unwrap(), 1_000.into());
+        assert_eq!(state.nonce(&address).unwrap(), 6.into());
+        let state = blockchain.state(BlockId::Number(1)).unwrap();
+        assert_eq!(state.nonce(&address).unwrap(), 5.into());
+    }
+
+    #[test]
+    fn test_mine_blocks() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        for nonce in 0..2 {
+            let txn = fake_txn(
+                Address::from(1),
+                nonce,
+                Action::Call(Address::from(2)),
+                vec![],
+            );
+            blockchain.submit_transaction(txn).unwrap();
+        }
+
+        assert_eq!(blockchain.mine_blocks(5, Some(60)).unwrap(), 5);
+        let blocks: Vec<EthereumBlock> = (1..=5)
+            .map(|number| {
+                blockchain
+                    .get_block_by_number(number)
+                    .wait()
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+        // Pending transactions only go into the first block.
+        assert_eq!(blocks[0].transactions().len(), 2);
+        for pair in blocks.windows(2) {
+            assert!(pair[1].transactions().is_empty());
+            assert_eq!(pair[1].timestamp(), pair[0].timestamp() + 60);
+        }
+
+        assert!(blockchain.mine_blocks(0, None).is_err());
+        assert!(blockchain.mine_blocks(2, Some(0)).is_err());
+        assert_eq!(blockchain.mine_blocks(2, None).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_set_block_number() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+        let txn = fake_txn(sender, 0, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(txn).unwrap();
+        let block1 = blockchain.get_latest_block().wait().unwrap();
+
+        assert_eq!(blockchain.set_block_number(1_000).unwrap(), 1_000);
+        assert_eq!(blockchain.best_block_number(), 1_000);
+        assert!(blockchain.set_block_number(999).is_err());
+
+        // Skipped blocks are empty, chained, and can be looked up by hash.
+        let block = blockchain.get_block_by_number(500).wait().unwrap().unwrap();
+        let parent = blockchain.get_block_by_number(499).wait().unwrap().unwrap();
+        assert!(block.transactions().is_empty());
+        assert_eq!(block.parent_hash, parent.hash());
+        assert_eq!(block.timestamp(), block1.timestamp() + 499);
+        let by_hash = blockchain.get_block_by_hash(block.hash()).wait().unwrap();
+        assert_eq!(by_hash.unwrap().number_u64(), 500);
+        let state = blockchain.state(BlockId::Number(500)).unwrap();
+        assert_eq!(state.nonce(&sender).unwrap(), 1.into());
+
+        // NUMBER and BLOCKHASH(1000), i.e., PUSH2 1000, BLOCKHASH.
+        let contract = Address::from(0x1234);
+        let tip = blockchain.get_latest_block().wait().unwrap();
+        for (opcode, expected) in vec![
+            (vec![0x43], H256::from(1_001)),
+            (vec![0x61, 0x03, 0xe8, 0x40], tip.hash()),
+        ] {
+            let mut runtime = opcode;
+            runtime.extend_from_slice(&[0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3]);
+            blockchain.set_code(contract, runtime).unwrap();
+            let txn = fake_txn(sender, 1, Action::Call(contract), vec![]);
+            let executed = blockchain
+                .simulate_transaction(txn, BlockId::Latest, None)
+                .wait()
+                .unwrap();
+            assert_eq!(executed.output, expected.to_vec());
+        }
+
+        // Mining continues from the new height.
+        let txn = fake_txn(sender, 1, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(txn).unwrap();
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.number_u64(), 1_001);
+        assert_eq!(block.parent_hash, tip.hash());
+    }
+
+    #[test]
+    fn test_set_block_number_limit() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            block_time: BlockTime::Deterministic {
+                genesis_timestamp: 0,
+                step: 1,
             },
-            extra_info: rich_header.extra_info.clone(),
+            ..test_config()
+        });
+        let max = u64::max_value();
+        assert!(blockchain.set_block_number(max).is_err());
+        assert!(blockchain.set_block_number(max - 1).is_err());
+        assert_eq!(blockchain.set_block_number(max - 2).unwrap(), max - 2);
+
+        // The next block still gets a number and a timestamp.
+        let txn = fake_txn(Address::from(1), 0, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(txn).unwrap();
+        let block = blockchain.get_latest_block().wait().unwrap();
+        assert_eq!(block.number_u64(), max - 1);
+        assert_eq!(block.timestamp(), max - 1);
+
+        // Timestamps of the skipped blocks must not overflow either.
+        let blockchain = new_blockchain(BlockchainConfig {
+            block_time: BlockTime::Deterministic {
+                genesis_timestamp: max - 10,
+                step: 1,
+            },
+            ..test_config()
+        });
+        assert!(blockchain.set_block_number(10).is_err());
+        assert_eq!(blockchain.set_block_number(9).unwrap(), 9);
+    }
+
+    #[test]
+    fn test_reorg() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+        let call = |nonce| fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+        let mut txn_hashes = vec![];
+        for nonce in 0..3 {
+            let (txn_hash, _) = blockchain.submit_transaction(call(nonce)).unwrap();
+            txn_hashes.push(txn_hash);
+        }
+        let blocks: Vec<EthereumBlock> = (1..=3)
+            .map(|number| {
+                blockchain
+                    .get_block_by_number(number)
+                    .wait()
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+
+        assert!(blockchain.reorg(0, 0).is_err());
+        assert!(blockchain.reorg(4, 0).is_err());
+        assert_eq!(blockchain.reorg(2, 2).unwrap(), 3);
+
+        // The discarded blocks and their transactions are gone.
+        for (block, txn_hash) in blocks[1..].iter().zip(&txn_hashes[1..]) {
+            let by_hash = blockchain.get_block_by_hash(block.hash()).wait().unwrap();
+            assert!(by_hash.is_none());
+            assert!(blockchain
+                .get_txn_by_hash(*txn_hash)
+                .wait()
+                .unwrap()
+                .is_none());
+            let receipt = blockchain.get_txn_receipt_by_hash(*txn_hash).wait();
+            assert!(receipt.unwrap().is_none());
+        }
+        assert!(blockchain
+            .get_txn_by_hash(txn_hashes[0])
+            .wait()
+            .unwrap()
+            .is_some());
+
+        // The replacement blocks are empty and chained to the kept block.
+        let replacement = blockchain.get_block_by_number(2).wait().unwrap().unwrap();
+        assert_ne!(replacement.hash(), blocks[1].hash());
+        assert_eq!(replacement.parent_hash, blocks[0].hash());
+        assert!(replacement.transactions().is_empty());
+        let head = blockchain.get_latest_block().wait().unwrap();
+        assert_ne!(head.hash(), blocks[2].hash());
+
+        // The state is rolled back, so the discarded transactions can be
+        // submitted again.
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.nonce(&sender).unwrap(), 1.into());
+        blockchain.submit_transaction(call(1)).unwrap();
+        assert_eq!(blockchain.best_block_number(), 4);
+    }
+
+    #[test]
+    fn test_reorg_requeues_pending() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let sender = Address::from(1);
+        let call = |nonce| fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+        blockchain.submit_transaction(call(0)).unwrap();
+        blockchain.mine_pending_block();
+        let (hash1, _) = blockchain.submit_transaction(call(1)).unwrap();
+        assert_eq!(blockchain.pending_transaction_count(), 1);
+
+        // Discarding the first transaction leaves a nonce gap, so the pending
+        // transaction is queued until it is filled.
+        blockchain.reorg(1, 0).unwrap();
+        assert_eq!(blockchain.pending_transaction_count(), 0);
+        assert_eq!(blockchain.pending_nonce(&sender).unwrap(), 0.into());
+        let (hash0, _) = blockchain.submit_transaction(call(0)).unwrap();
+        assert_eq!(blockchain.pending_transaction_count(), 2);
+
+        assert_eq!(blockchain.mine_pending_block(), 1);
+        for (index, hash) in vec![hash0, hash1].into_iter().enumerate() {
+            let receipt = blockchain
+                .get_txn_receipt_by_hash(hash)
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert_eq!(receipt.block_number, 1);
+            assert_eq!(receipt.transaction_index, index);
+        }
+    }
+
+    #[test]
+    fn test_set_code() {
+        let blockchain = new_blockchain(test_config());
+        let contract = Address::from(0x1234);
+
+        // Return 42.
+        let runtime = vec![0x60, 0x2a, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+        blockchain.set_code(contract, runtime.clone()).unwrap();
+
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(*state.code(&contract).unwrap().unwrap(), runtime);
+        assert_eq!(state.code_hash(&contract).unwrap(), keccak(&runtime));
+
+        let txn = fake_txn(Address::from(1), 0, Action::Call(contract), vec![]);
+        let executed = blockchain
+            .simulate_transaction(txn, BlockId::Latest, None)
+            .wait()
+            .unwrap();
+        assert_eq!(executed.output, H256::from(42).to_vec());
+    }
+
+    #[test]
+    fn test_set_nonce_limits() {
+        let blockchain = new_blockchain(test_config());
+        let account = Address::from(1);
+        let max_nonce = U256::from(u64::max_value());
+        blockchain.set_nonce(account, max_nonce).unwrap();
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.nonce(&account).unwrap(), max_nonce);
+
+        // Contract nonces are stepped, so they keep their storage.
+        let contract = Address::from(0x1234);
+        blockchain.set_code(contract, vec![0x00]).unwrap();
+        blockchain
+            .set_storage(contract, H256::from(1), H256::from(42))
+            .unwrap();
+        blockchain.set_nonce(contract, 3.into()).unwrap();
+        assert!(blockchain.set_nonce(contract, 2.into()).is_err());
+        assert!(blockchain.set_nonce(contract, max_nonce).is_err());
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(state.nonce(&contract).unwrap(), 3.into());
+        assert_eq!(
+            state.storage_at(&contract, &H256::from(1)).unwrap(),
+            H256::from(42)
+        );
+    }
+
+    #[test]
+    fn test_simulate_timeout() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            call_timeout: Duration::from_millis(100),
+            ..test_config()
+        });
+        let contract = Address::from(0x1234);
+
+        // Loop until out of gas: JUMPDEST, PUSH1 0, JUMP.
+        blockchain
+            .set_code(contract, vec![0x5b, 0x60, 0x00, 0x56])
+            .unwrap();
+
+        let txn = Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas: CALL_GAS_CAP.into(),
+            action: Action::Call(contract),
+            value: U256::zero(),
+            data: vec![],
+        }
+        .fake_sign(Address::from(1));
+        let err = blockchain
+            .simulate_transaction(txn, BlockId::Latest, None)
+            .wait()
+            .unwrap_err();
+        assert!(err.to_string().contains("execution timed out"), "{}", err);
+
+        // The simulation still runs, but does not block mining.
+        assert!(blockchain.chain_state.try_write().is_ok());
+        blockchain.mine_pending_block();
+    }
+
+    #[test]
+    fn test_simulate_at_historical_block() {
+        let blockchain = new_blockchain(test_config());
+        // Return NUMBER and TIMESTAMP, respectively.
+        let number_contract = Address::from(0x1234);
+        let timestamp_contract = Address::from(0x1235);
+        for (contract, opcode) in vec![(number_contract, 0x43), (timestamp_contract, 0x42)] {
+            let runtime = vec![opcode, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+            blockchain.set_code(contract, runtime).unwrap();
+        }
+        blockchain.mine_blocks(3, None).unwrap();
+
+        let simulate = |contract, number| {
+            let txn = fake_txn(Address::from(1), 0, Action::Call(contract), vec![]);
+            blockchain
+                .simulate_transaction(txn, BlockId::Number(number), None)
+                .wait()
+                .map(|executed| U256::from(&executed.output[..]))
+        };
+        // The environment is the one of the block after the requested one.
+        assert_eq!(simulate(number_contract, 1).unwrap(), 2.into());
+        assert_eq!(simulate(number_contract, 3).unwrap(), 4.into());
+        let block2 = blockchain.get_block_by_number(2).wait().unwrap().unwrap();
+        assert_eq!(
+            simulate(timestamp_contract, 1).unwrap(),
+            block2.timestamp().into()
+        );
+        assert!(simulate(number_contract, 4).is_err());
+    }
+
+    #[test]
+    fn test_set_storage() {
+        let blockchain = new_blockchain(test_config());
+        let contract = Address::from(0x1234);
+
+        // Return SLOAD(1).
+        let runtime = vec![
+            0x60, 0x01, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+        ];
+        blockchain.set_code(contract, runtime).unwrap();
+        blockchain
+            .set_storage(contract, H256::from(1), H256::from(42))
+            .unwrap();
+
+        let state = blockchain.state(BlockId::Latest).unwrap();
+        assert_eq!(
+            state.storage_at(&contract, &H256::from(1)).unwrap(),
+            H256::from(42)
+        );
+        let state = blockchain.state(BlockId::Number(0)).unwrap();
+        assert_eq!(
+            state.storage_at(&contract, &H256::from(1)).unwrap(),
+            H256::zero()
+        );
+
+        let txn = fake_txn(Address::from(1), 0, Action::Call(contract), vec![]);
+        let executed = blockchain
+            .simulate_transaction(txn, BlockId::Latest, None)
+            .wait()
+            .unwrap();
+        assert_eq!(executed.output, H256::from(42).to_vec());
+    }
+
+    #[test]
+    fn test_send_raw_transactions_in_one_block() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode: MiningMode::Manual,
+            ..test_config()
+        });
+        let raw_txn = |nonce| {
+            let txn = fake_txn(
+                Address::from(1),
+                nonce,
+                Action::Call(Address::from(2)),
+                vec![],
+            );
+            rlp::encode(&*txn).to_vec()
+        };
+
+        let hashes = blockchain
+            .send_raw_transactions((0..3).map(raw_txn).collect())
+            .unwrap();
+        assert_eq!(blockchain.best_block_number(), 1);
+        let block = blockchain.get_latest_block().wait().unwrap();
+        let mined: Vec<H256> = block.transactions().iter().map(|txn| txn.hash()).collect();
+        assert_eq!(mined, hashes);
+        assert_eq!(blockchain.pending_transaction_count(), 0);
+
+        // A nonce gap rejects the whole batch.
+        let err = blockchain
+            .send_raw_transactions(vec![raw_txn(3), raw_txn(5)])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "transaction 1 of the batch was rejected: nonce too high"
+        );
+        let err = blockchain
+            .send_raw_transactions(vec![raw_txn(3), vec![0xc0]])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "transaction 1 of the batch was rejected: could not decode transaction"
+        );
+        assert_eq!(blockchain.best_block_number(), 1);
+    }
+
+    #[test]
+    fn test_historical_state() {
+        let blockchain = new_blockchain(test_config());
+        let sender = Address::from(1);
+
+        // SSTORE(0, first calldata word).
+        let runtime = vec![0x60, 0x00, 0x35, 0x60, 0x00, 0x55, 0x00];
+        let (hash, _) = blockchain
+            .submit_transaction(fake_txn(sender, 0, Action::Create, init_code(&runtime)))
+            .unwrap();
+        let contract = blockchain
+            .get_txn_receipt_by_hash(hash)
+            .wait()
+            .unwrap()
+            .unwrap()
+            .contract_address
+            .unwrap();
+        for nonce in 1..3 {
+            let data = H256::from(nonce).to_vec();
+            let txn = fake_txn(sender, nonce, Action::Call(contract), data);
+            blockchain.submit_transaction(txn).unwrap();
+        }
+
+        let storage_at = |id| {
+            blockchain
+                .state(id)
+                .unwrap()
+                .storage_at(&contract, &H256::zero())
+                .unwrap()
+        };
+        assert_eq!(storage_at(BlockId::Number(1)), H256::zero());
+        assert_eq!(storage_at(BlockId::Number(2)), H256::from(1));
+        assert_eq!(storage_at(BlockId::Number(3)), H256::from(2));
+        assert_eq!(storage_at(BlockId::Latest), H256::from(2));
+        assert_eq!(
+            blockchain
+                .state(BlockId::Earliest)
+                .unwrap()
+                .code(&contract)
+                .unwrap(),
+            None
+        );
+        assert!(blockchain.state(BlockId::Number(4)).is_err());
+    }
+
+    #[test]
+    fn test_log_scan_across_chunks_uses_bloom() {
+        let blockchain = new_blockchain(test_config());
+        let topic = H256::from(0x42);
+        let log_with_topic = |address| LogEntry {
+            address,
+            topics: vec![topic],
+            data: vec![],
+        };
+
+        // Matching logs spread over multiple scan chunks.
+        let matching = vec![1, 1500, 2000, 2500];
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            for number in 1..=2500 {
+                let entries = if matching.contains(&number) {
+                    vec![log_with_topic(Address::from(number))]
+                } else {
+                    vec![log_from(Address::from(number))]
+                };
+                push_block_with_logs(&mut chain_state, entries);
+            }
+
+            // A block whose bloom rules out the topic is not scanned.
+            let hash = chain_state.block_number_to_hash[&2000];
+            chain_state.blocks.get_mut(&hash).unwrap().log_bloom = Bloom::default();
+        }
+
+        let mut filter = address_filter(None);
+        filter.topics = vec![Some(vec![topic]), None, None, None];
+        let logs = blockchain.logs(filter).wait().unwrap();
+        let numbers: Vec<u64> = logs.iter().map(|log| log.block_number).collect();
+        assert_eq!(numbers, vec![1, 1500, 2500]);
+    }
+
+    #[test]
+    fn test_logs_block_range_limit() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            max_logs_block_range: 5,
+            ..test_config()
+        });
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            for _ in 0..10 {
+                push_block_with_logs(&mut chain_state, vec![log_from(Address::zero())]);
+            }
+        }
+
+        let err = blockchain.logs(address_filter(None)).wait().unwrap_err();
+        assert_eq!(err.to_string(), "query returned more than 5 blocks");
+
+        // Blocks 5 to 10 are one too many, 6 to 10 are within the limit.
+        let mut filter = address_filter(None);
+        filter.from_block = BlockId::Number(5);
+        assert!(blockchain.logs(filter).wait().is_err());
+        let mut filter = address_filter(None);
+        filter.from_block = BlockId::Number(6);
+        let logs = blockchain.logs(filter).wait().unwrap();
+        assert_eq!(logs.len(), 5);
+    }
+
+    #[test]
+    fn test_unfiltered_logs_scan_all_blocks() {
+        let blockchain = new_blockchain(test_config());
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            for i in 0..10 {
+                push_block_with_logs(&mut chain_state, vec![log_from(Address::from(i % 2))]);
+            }
+        }
+
+        let logs = blockchain.logs(address_filter(None)).wait().unwrap();
+        assert_eq!(logs.len(), 10);
+    }
+
+    #[test]
+    fn test_logs_range_tags_and_numbers() {
+        let blockchain = new_blockchain(test_config());
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            for i in 0..3 {
+                push_block_with_logs(&mut chain_state, vec![log_from(Address::from(i))]);
+            }
+        }
+        let range = |from_block, to_block| {
+            let mut filter = address_filter(None);
+            filter.from_block = from_block;
+            filter.to_block = to_block;
+            let logs = blockchain.logs(filter).wait().unwrap();
+            logs.iter().map(|log| log.block_number).collect::<Vec<_>>()
+        };
+
+        assert_eq!(range(BlockId::Earliest, BlockId::Latest), vec![1, 2, 3]);
+        assert_eq!(range(BlockId::Number(2), BlockId::Number(2)), vec![2]);
+        assert_eq!(range(BlockId::Number(1), BlockId::Latest), vec![1, 2, 3]);
+
+        // The end of the range is clamped to the best block.
+        assert_eq!(range(BlockId::Number(2), BlockId::Number(100)), vec![2, 3]);
+        assert_eq!(range(BlockId::Number(10), BlockId::Latest), vec![]);
+    }
+
+    #[test]
+    fn test_logs_inverted_range() {
+        let blockchain = new_blockchain(BlockchainConfig {
+            max_logs_block_range: 1,
+            ..test_config()
+        });
+        {
+            let mut chain_state = blockchain.chain_state.write().unwrap();
+            for _ in 0..3 {
+                push_block_with_logs(&mut chain_state, vec![log_from(Address::zero())]);
+            }
         }
+
+        let mut filter = address_filter(None);
+        filter.from_block = BlockId::Latest;
+        filter.to_block = BlockId::Earliest;
+        assert!(blockchain.logs(filter).wait().unwrap().is_empty());
+
+        let mut filter = address_filter(Some(vec![Address::zero()]));
+        filter.from_block = BlockId::Number(3);
+        filter.to_block = BlockId::Number(1);
+        assert!(blockchain.logs(filter).wait().unwrap().is_empty());
     }
 }
@@ -259,12 +259,15 @@ impl EthConfidentialCtx for ConfidentialCtx {
         nonce.copy_from_slice(&data[nonce_offset..]);
         let ciphertext = &data[..nonce_offset];
 
-        Ok(self
-            .d2
+        self.d2
             .as_ref()
             .expect("Should always have a Deoxys-II instance to decrypt storage")
             .open(&nonce, ciphertext.to_vec(), vec![])
-            .unwrap())
+            .map_err(|_| {
+                Error::Confidential(
+                    "failed to decrypt storage value: corrupt ciphertext or wrong key".to_string(),
+                )
+            })
     }
 
     fn peer(&self) -> Option<Vec<u8>> {
@@ -359,6 +362,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_decrypt_corrupt_storage_value() {
+        let contract_key = ContractKey::new(
+            PublicKey::default(),
+            PrivateKey::default(),
+            StateKey::default(),
+            vec![],
+        );
+        let mut ctx = ConfidentialCtx {
+            peer_public_key: None,
+            contract: Some((Address::default(), contract_key)),
+            next_nonce: None,
+            prev_block_hash: H256::default(),
+            next_storage_nonce: Some(Nonce::new([0; NONCE_SIZE])),
+            d2: Some(DeoxysII::new(&[0; KEY_SIZE])),
+            key_manager: Arc::new(MockClient::new()),
+            activated: true,
+        };
+
+        let value = H256::from(42).to_vec();
+        let mut ciphertext = ctx.encrypt_storage_value(value.clone()).unwrap();
+        assert_eq!(
+            ctx.decrypt_storage_value(ciphertext.clone()).unwrap(),
+            value
+        );
+
+        // Flip a bit of the ciphertext, keeping its size.
+        ciphertext[0] ^= 1;
+        let res = ctx.decrypt_storage_value(ciphertext);
+
+        assert_eq!(
+            &format!("{}", res.err().unwrap()),
+            "Confidential error: failed to decrypt storage value: corrupt ciphertext or wrong key"
+        );
+    }
+
     #[test]
     fn test_decrypt_tx_data_after_deactivate() {
         let peer_public_key = PublicKey::default();
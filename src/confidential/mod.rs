@@ -1,7 +1,7 @@
 //! Handling of confidential execution.
 
 mod confidential_ctx;
-mod crypto;
+pub mod crypto;
 
 // Re-exports.
 pub use self::confidential_ctx::ConfidentialCtx;
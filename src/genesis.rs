@@ -1,14 +1,142 @@
 //! Genesis state.
-use std::io::Cursor;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Cursor,
+};
 
 use ethcore::spec::Spec;
-use lazy_static::lazy_static;
+use failure::{format_err, Fallible};
+use parity_rpc::v1::types::{Bytes, H160 as RpcH160, H256 as RpcH256, U256 as RpcU256};
+use serde_json;
 
-lazy_static! {
-    /// Genesis spec.
-    pub static ref SPEC: Spec = {
-        let spec_json = include_str!("../resources/genesis.json");
+use crate::blockchain::{AccountOverride, StateOverride};
 
-        Spec::load(Cursor::new(spec_json)).expect("must have a valid genesis spec")
-    };
+/// Name of the built-in Oasis chain spec.
+pub const OASIS_SPEC: &str = "oasis";
+
+/// Built-in Oasis chain spec.
+pub fn oasis_spec() -> Spec {
+    let spec_json = include_str!("../resources/genesis.json");
+
+    Spec::load(Cursor::new(spec_json)).expect("must have a valid genesis spec")
+}
+
+/// Load a chain spec, given the name of a built-in spec or the path to a spec
+/// JSON file.
+///
+/// The spec determines the genesis state and the hardfork transitions, and so
+/// the gas schedule of each block.
+pub fn load_spec(selector: &str) -> Fallible<Spec> {
+    match selector {
+        OASIS_SPEC => Ok(oasis_spec()),
+        path => {
+            let file = File::open(path)
+                .map_err(|err| format_err!("failed to open chain spec {}: {}", path, err))?;
+            Spec::load(file).map_err(|err| format_err!("invalid chain spec {}: {}", path, err))
+        }
+    }
+}
+
+/// Account of a genesis allocation file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AllocAccount {
+    balance: Option<RpcU256>,
+    nonce: Option<RpcU256>,
+    code: Option<Bytes>,
+    storage: Option<BTreeMap<RpcH256, RpcH256>>,
+}
+
+/// Load a genesis allocation from a JSON file.
+///
+/// As in Geth's `alloc`, the file maps addresses to their balance, nonce, code
+/// and storage, all optional. Addresses, quantities and data are hex-encoded
+/// with a `0x` prefix.
+pub fn load_alloc(path: &str) -> Fallible<StateOverride> {
+    let json = fs::read_to_string(path)
+        .map_err(|err| format_err!("failed to open genesis allocation {}: {}", path, err))?;
+    parse_alloc(&json).map_err(|err| format_err!("invalid genesis allocation {}: {}", path, err))
+}
+
+fn parse_alloc(json: &str) -> Fallible<StateOverride> {
+    let alloc: BTreeMap<RpcH160, AllocAccount> = serde_json::from_str(json)?;
+
+    Ok(alloc
+        .into_iter()
+        .map(|(address, account)| {
+            let storage = account.storage.map(|slots| {
+                slots
+                    .into_iter()
+                    .map(|(key, value)| (key.into(), value.into()))
+                    .collect()
+            });
+            let account = AccountOverride {
+                balance: account.balance.map(Into::into),
+                nonce: account.nonce.map(Into::into),
+                code: account.code.map(Into::into),
+                state: None,
+                state_diff: storage,
+            };
+            (address.into(), account)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use ethereum_types::{Address, H256};
+
+    use super::*;
+
+    #[test]
+    fn test_load_spec() {
+        let spec = load_spec(OASIS_SPEC).unwrap();
+        assert_eq!(spec.name, "Oasis");
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/genesis.json");
+        assert_eq!(load_spec(path).unwrap().name, "Oasis");
+
+        assert!(load_spec("no-such-spec.json").is_err());
+    }
+
+    #[test]
+    fn test_parse_alloc() {
+        let alloc = parse_alloc(
+            r#"{
+                "0x0000000000000000000000000000000000000001": {
+                    "balance": "0x64",
+                    "nonce": "0x2",
+                    "code": "0x00",
+                    "storage": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000001":
+                            "0x0000000000000000000000000000000000000000000000000000000000000002"
+                    }
+                },
+                "0x0000000000000000000000000000000000000002": {}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(alloc.len(), 2);
+        let account = &alloc[&Address::from(1)];
+        assert_eq!(account.balance, Some(100.into()));
+        assert_eq!(account.nonce, Some(2.into()));
+        assert_eq!(account.code, Some(vec![0x00]));
+        assert_eq!(
+            account.state_diff.as_ref().unwrap()[&H256::from(1)],
+            H256::from(2)
+        );
+
+        // Malformed entries are rejected rather than skipped.
+        assert!(parse_alloc(r#"{ "0x01": {} }"#).is_err());
+        assert!(parse_alloc(
+            r#"{ "0x0000000000000000000000000000000000000001": { "balance": "100" } }"#
+        )
+        .is_err());
+        assert!(parse_alloc(
+            r#"{ "0x0000000000000000000000000000000000000001": { "wei": "0x1" } }"#
+        )
+        .is_err());
+        assert!(load_alloc("no-such-alloc.json").is_err());
+    }
 }
@@ -0,0 +1,109 @@
+//! Development accounts derived from a BIP-39 mnemonic.
+use std::fmt::Write;
+
+use bip39::{Language, Mnemonic, Seed};
+use ethereum_types::U256;
+use ethkey::{
+    extended::{Derivation, ExtendedKeyPair},
+    KeyPair,
+};
+use failure::{format_err, Fallible};
+
+use crate::util;
+
+/// Mnemonic of the well-known development accounts.
+pub const DEFAULT_MNEMONIC: &str =
+    "range drive remove bleak mule satisfy mandate east lion minimum unfold ready";
+/// Default number of development accounts.
+pub const DEFAULT_ACCOUNT_COUNT: usize = 10;
+/// Default balance of the development accounts the genesis spec does not fund (in DEV).
+pub const DEFAULT_ACCOUNT_BALANCE: u64 = 100;
+
+/// Derivation path of the accounts, followed by the account index.
+const BASE_PATH: &str = "m/44'/60'/0'/0";
+/// Offset of hardened child indices.
+const HARDENED: u32 = 0x8000_0000;
+
+/// Derive the keypairs of the first `count` accounts of the HD wallet of the
+/// given mnemonic, along `m/44'/60'/0'/0/{account_index}` like Ganache.
+///
+/// Fails if the mnemonic is not a valid BIP-39 English phrase.
+pub fn derive_accounts(mnemonic: &str, count: usize) -> Fallible<Vec<KeyPair>> {
+    let mnemonic = Mnemonic::from_phrase(mnemonic, Language::English)
+        .map_err(|err| format_err!("invalid mnemonic: {}", err))?;
+    let seed = Seed::new(&mnemonic, "");
+    let root = ExtendedKeyPair::with_seed(seed.as_bytes())
+        .map_err(|err| format_err!("failed to derive HD wallet: {:?}", err))?;
+    let base = [44 | HARDENED, 60 | HARDENED, HARDENED, 0]
+        .iter()
+        .try_fold(root, |key, &index| derive(&key, index))?;
+
+    (0..count as u32)
+        .map(|index| {
+            let key = derive(&base, index)?;
+            KeyPair::from_secret(key.secret().as_raw().clone())
+                .map_err(|err| format_err!("invalid derived key: {}", err))
+        })
+        .collect()
+}
+
+fn derive(key: &ExtendedKeyPair, index: u32) -> Fallible<ExtendedKeyPair> {
+    key.derive(Derivation::from(index))
+        .map_err(|err| format_err!("failed to derive child key {}: {:?}", index, err))
+}
+
+/// Describe the accounts, their private keys and the HD wallet for the
+/// startup log.
+pub fn describe_accounts(keys: &[KeyPair], balances: &[U256], mnemonic: &str) -> String {
+    let mut description = String::new();
+    writeln!(description, "Accounts").unwrap();
+    writeln!(description, "==================").unwrap();
+    for (index, (key, balance)) in keys.iter().zip(balances).enumerate() {
+        let dev = balance / util::dev_to_wei(1);
+        writeln!(description, "({}) {:?} ({} DEV)", index, key.address(), dev).unwrap();
+    }
+    writeln!(description).unwrap();
+    writeln!(description, "Private Keys").unwrap();
+    writeln!(description, "==================").unwrap();
+    for (index, key) in keys.iter().enumerate() {
+        writeln!(description, "({}) {:?}", index, **key.secret()).unwrap();
+    }
+    writeln!(description).unwrap();
+    writeln!(description, "HD Wallet").unwrap();
+    writeln!(description, "==================").unwrap();
+    writeln!(description, "Mnemonic:      {}", mnemonic).unwrap();
+    write!(
+        description,
+        "Base HD Path:  {}/{{account_index}}",
+        BASE_PATH
+    )
+    .unwrap();
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use ethereum_types::Address;
+
+    use super::*;
+
+    #[test]
+    fn test_default_mnemonic_accounts() {
+        let keys = derive_accounts(DEFAULT_MNEMONIC, DEFAULT_ACCOUNT_COUNT).unwrap();
+        assert_eq!(keys.len(), DEFAULT_ACCOUNT_COUNT);
+        let first: Address = "b8b3666d8fea887d97ab54f571b8e5020c5c8b58".parse().unwrap();
+        let last: Address = "07b23940821ea777b9a26e3c8dc3027648236bbf".parse().unwrap();
+        assert_eq!(keys[0].address(), first);
+        assert_eq!(keys[9].address(), last);
+
+        // Derivation is deterministic.
+        let again = derive_accounts(DEFAULT_MNEMONIC, 1).unwrap();
+        assert_eq!(again[0].secret(), keys[0].secret());
+    }
+
+    #[test]
+    fn test_invalid_mnemonic() {
+        let err = derive_accounts("range drive remove bleak mule", 1).unwrap_err();
+        assert!(err.to_string().starts_with("invalid mnemonic"), "{}", err);
+    }
+}
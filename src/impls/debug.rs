@@ -0,0 +1,102 @@
+//! Debug rpc implementation.
+use std::sync::Arc;
+
+use ethcore::account_provider::AccountProvider;
+use failure::format_err;
+use futures::prelude::*;
+use jsonrpc_core::{BoxFuture, Result};
+use parity_rpc::v1::{helpers::errors, types::Bytes};
+
+use crate::{
+    blockchain::{Blockchain, StateDump},
+    traits::Debug,
+    util::jsonrpc_error,
+};
+
+/// Debug rpc implementation.
+pub struct DebugClient {
+    blockchain: Arc<Blockchain>,
+    accounts: Arc<AccountProvider>,
+}
+
+impl DebugClient {
+    /// Creates new DebugClient.
+    pub fn new(blockchain: Arc<Blockchain>, accounts: Arc<AccountProvider>) -> Self {
+        DebugClient {
+            blockchain,
+            accounts,
+        }
+    }
+}
+
+impl Debug for DebugClient {
+    fn block_rlp(&self, number: u64) -> BoxFuture<Bytes> {
+        Box::new(
+            self.blockchain
+                .get_block_by_number(number)
+                .and_then(|block| block.ok_or_else(|| format_err!("block not found")))
+                .map(|block| block.rlp().into())
+                .map_err(jsonrpc_error),
+        )
+    }
+
+    fn dump_state(&self) -> Result<StateDump> {
+        let accounts = self
+            .accounts
+            .accounts()
+            .map_err(|err| errors::account("Could not fetch accounts.", err))?;
+        self.blockchain.dump_state(&accounts).map_err(jsonrpc_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethcore::transaction::Action;
+    use ethereum_types::Address;
+    use serde_json::{self, Value};
+
+    use super::*;
+    use crate::test_support::{fake_txn, new_blockchain, test_config};
+
+    fn new_client() -> DebugClient {
+        let blockchain = new_blockchain(test_config());
+        DebugClient::new(
+            Arc::new(blockchain),
+            Arc::new(AccountProvider::transient_provider()),
+        )
+    }
+
+    #[test]
+    fn test_block_rlp_unknown_block() {
+        let client = new_client();
+
+        assert!(client.block_rlp(0).wait().is_ok());
+        let err = client.block_rlp(1).wait().unwrap_err();
+        assert_eq!(err.message, "block not found");
+    }
+
+    #[test]
+    fn test_dump_state() {
+        let client = new_client();
+        let managed = client.accounts.new_account("secret").unwrap();
+        client.blockchain.set_balance(managed, 7.into()).unwrap();
+        let txn = fake_txn(Address::from(1), 0, Action::Call(Address::from(2)), vec![]);
+        client.blockchain.send_transaction(txn).unwrap();
+
+        let dump = serde_json::to_value(client.dump_state().unwrap()).unwrap();
+        assert_eq!(dump["bestBlock"], Value::from(1));
+        assert_eq!(dump["transactionCount"], Value::from(1));
+        assert_eq!(dump["pendingTransactionCount"], Value::from(0));
+        let blocks = dump["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1]["number"], Value::from(1));
+        assert_eq!(blocks[1]["transactionCount"], Value::from(1));
+
+        let accounts = &dump["accounts"];
+        assert_eq!(accounts[format!("{:#x}", managed)]["balance"], "0x7");
+        assert_eq!(accounts[format!("{:#x}", Address::from(1))]["nonce"], "0x1");
+        assert!(accounts[format!("{:#x}", Address::from(2))].is_object());
+        // Funded genesis account.
+        assert!(accounts["0xb8b3666d8fea887d97ab54f571b8e5020c5c8b58"].is_object());
+    }
+}
@@ -16,47 +16,139 @@
 
 //! Eth rpc implementation.
 
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
-use ethcore::{filter::Filter as EthcoreFilter, ids::BlockId};
+use ethcore::{
+    account_provider::AccountProvider,
+    filter::Filter as EthcoreFilter,
+    ids::BlockId,
+    receipt::LocalizedReceipt,
+    transaction::{Action, LocalizedTransaction, SignedTransaction},
+};
 use ethereum_types::{Address, H256, U256};
 use failure::Error;
 use jsonrpc_core::{
     futures::{future, Future},
-    BoxFuture, Result,
+    BoxFuture, Params, Result, Value,
 };
 use jsonrpc_macros::Trailing;
 use parity_rpc::v1::{
-    helpers::{errors, fake_sign},
+    helpers::{errors, fake_sign, limit_logs},
     metadata::Metadata,
     traits::Eth,
     types::{
         BlockNumber, Bytes, CallRequest, Filter, Index, Log as RpcLog, Receipt as RpcReceipt,
         RichBlock, Transaction as RpcTransaction, Work, H160 as RpcH160, H256 as RpcH256,
-        H64 as RpcH64, U256 as RpcU256,
+        H64 as RpcH64, U256 as RpcU256, U64 as RpcU64,
     },
 };
 
 use crate::{
-    blockchain::Blockchain,
-    genesis,
-    util::{block_number_to_id, execution_error, jsonrpc_error},
+    blockchain::{AccountOverride, Blockchain, EthereumBlock, MiningMode, StateOverride},
+    traits::EthRaw,
+    util::{
+        block_number_to_id, block_number_to_state_id, execution_error, jsonrpc_error,
+        transaction_error,
+    },
 };
 
-// short for "try_boxfuture"
-// unwrap a result, returning a BoxFuture<_, Err> on failure.
-macro_rules! try_bf {
-    ($res:expr) => {
-        match $res {
-            Ok(val) => val,
-            Err(e) => return Box::new(::jsonrpc_core::futures::future::err(e.into())),
-        }
-    };
-}
-
 /// Eth rpc implementation.
+#[derive(Clone)]
 pub struct EthClient {
     blockchain: Arc<Blockchain>,
+    accounts: Arc<AccountProvider>,
+}
+
+/// Account state override accepted as the third `eth_call` parameter.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct RpcAccountOverride {
+    balance: Option<RpcU256>,
+    nonce: Option<RpcU256>,
+    code: Option<Bytes>,
+    /// Replaces all storage of the account.
+    state: Option<BTreeMap<RpcH256, RpcH256>>,
+    /// Merges into the existing storage of the account.
+    state_diff: Option<BTreeMap<RpcH256, RpcH256>>,
+}
+
+impl From<RpcAccountOverride> for AccountOverride {
+    fn from(value: RpcAccountOverride) -> AccountOverride {
+        let slots = |slots: BTreeMap<RpcH256, RpcH256>| -> HashMap<H256, H256> {
+            slots
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect()
+        };
+
+        AccountOverride {
+            balance: value.balance.map(Into::into),
+            nonce: value.nonce.map(Into::into),
+            code: value.code.map(Into::into),
+            state: value.state.map(slots),
+            state_diff: value.state_diff.map(slots),
+        }
+    }
+}
+
+/// Result of `eth_createAccessList`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcAccessListResult {
+    access_list: Vec<RpcAccessListItem>,
+    gas_used: RpcU256,
+    /// Why the execution failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Access list entry, as in EIP-2930 transactions.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcAccessListItem {
+    address: RpcH160,
+    storage_keys: Vec<RpcH256>,
+}
+
+/// Transaction receipt along with the sender, recipient, type and effective
+/// gas price of the transaction, which the parity `Receipt` lacks.
+///
+/// Only legacy transactions are supported, so the type is always 0 and the
+/// effective gas price is the transaction's gas price.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcTransactionReceipt {
+    #[serde(flatten)]
+    receipt: RpcReceipt,
+    /// Sender of the transaction.
+    from: RpcH160,
+    /// Recipient of the transaction, null for contract creations.
+    to: Option<RpcH160>,
+    /// EIP-2718 type of the transaction. Only legacy transactions are
+    /// supported, so it is always 0.
+    #[serde(rename = "type")]
+    transaction_type: RpcU64,
+    /// Gas price actually charged. Only legacy transactions are supported, so
+    /// this is the transaction's gas price.
+    effective_gas_price: RpcU256,
+}
+
+impl RpcTransactionReceipt {
+    fn new(receipt: LocalizedReceipt, mut txn: LocalizedTransaction) -> Self {
+        RpcTransactionReceipt {
+            receipt: receipt.into(),
+            from: txn.sender().into(),
+            to: match txn.action {
+                Action::Call(to) => Some(to.into()),
+                Action::Create => None,
+            },
+            transaction_type: RpcU64::from(0),
+            effective_gas_price: txn.gas_price.into(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -79,8 +171,202 @@ impl From<BlockNumber> for BlockNumberOrId {
 
 impl EthClient {
     /// Creates new EthClient.
-    pub fn new(blockchain: Arc<Blockchain>) -> Self {
-        EthClient { blockchain }
+    pub fn new(blockchain: Arc<Blockchain>, accounts: Arc<AccountProvider>) -> Self {
+        EthClient {
+            blockchain,
+            accounts,
+        }
+    }
+
+    /// Retrieve a block given an RPC block number, resolving "pending" to
+    /// the block that would be mined next.
+    fn block(
+        &self,
+        num: BlockNumber,
+    ) -> Box<dyn Future<Item = Option<EthereumBlock>, Error = Error> + Send> {
+        match num {
+            BlockNumber::Pending => Box::new(self.blockchain.get_pending_block().map(Some)),
+            num => Box::new(self.blockchain.get_block(block_number_to_id(num))),
+        }
+    }
+
+    /// Retrieve a transaction given an RPC block number and its index within
+    /// the block.
+    fn txn(
+        &self,
+        num: BlockNumber,
+        index: Index,
+    ) -> Box<dyn Future<Item = Option<LocalizedTransaction>, Error = Error> + Send> {
+        match num {
+            BlockNumber::Pending => Box::new(self.block(num).map(move |blk| {
+                blk.and_then(|blk| blk.transactions().get(index.value()).cloned())
+            })),
+            num => Box::new(
+                self.blockchain
+                    .get_txn(block_number_to_id(num), index.value()),
+            ),
+        }
+    }
+
+    /// `eth_call` accepting an optional state override set as the third
+    /// parameter.
+    ///
+    /// The parity `Eth` trait only supports two parameters, so this is
+    /// registered as a raw method superseding its `eth_call`. Within an
+    /// account override, `state` replaces all storage of the account while
+    /// `stateDiff` only overwrites the given slots.
+    pub fn call_with_overrides(&self, meta: Metadata, params: Params) -> BoxFuture<Value> {
+        let values: Vec<Value> = try_bf!(params.parse());
+        let (request, num, overrides) = match values.len() {
+            1 => {
+                let (request,): (CallRequest,) = try_bf!(Params::Array(values).parse());
+                (request, BlockNumber::default(), None)
+            }
+            2 => {
+                let (request, num): (CallRequest, BlockNumber) =
+                    try_bf!(Params::Array(values).parse());
+                (request, num, None)
+            }
+            3 => {
+                let (request, num, overrides): (
+                    CallRequest,
+                    BlockNumber,
+                    BTreeMap<RpcH160, RpcAccountOverride>,
+                ) = try_bf!(Params::Array(values).parse());
+                let overrides: StateOverride = overrides
+                    .into_iter()
+                    .map(|(address, account)| (address.into(), account.into()))
+                    .collect();
+                (request, num, Some(overrides))
+            }
+            _ => {
+                return Box::new(future::err(errors::invalid_params(
+                    "eth_call",
+                    "expected 1 to 3 parameters",
+                )));
+            }
+        };
+
+        Box::new(
+            self.call_at(meta, request, num, overrides)
+                .map(|bytes| serde_json::to_value(bytes).expect("bytes serialization cannot fail")),
+        )
+    }
+
+    /// `eth_getTransactionReceipt` including the `effectiveGasPrice`, the
+    /// transaction `type` and its `from` and `to` addresses.
+    ///
+    /// Registered as a raw method superseding the parity `Eth` one, whose
+    /// receipt type cannot carry the extra field.
+    pub fn transaction_receipt_with_gas_price(&self, params: Params) -> BoxFuture<Value> {
+        let (hash,): (RpcH256,) = try_bf!(params.parse());
+        let hash: H256 = hash.into();
+
+        Box::new(
+            self.blockchain
+                .get_txn_receipt_by_hash(hash)
+                .join(self.blockchain.get_txn_by_hash(hash))
+                .map_err(jsonrpc_error)
+                .map(|(receipt, txn)| {
+                    let receipt = match (receipt, txn) {
+                        (Some(receipt), Some(txn)) => {
+                            Some(RpcTransactionReceipt::new(receipt, txn))
+                        }
+                        _ => None,
+                    };
+                    serde_json::to_value(receipt).expect("receipt serialization cannot fail")
+                }),
+        )
+    }
+
+    /// `eth_getBlockReceipts`, returning the receipts of all transactions of
+    /// a block, by number or hash, in transaction index order.
+    ///
+    /// Receipts include the same extra fields as those of
+    /// `eth_getTransactionReceipt`.
+    pub fn block_receipts(&self, params: Params) -> BoxFuture<Value> {
+        let id = match params.clone().parse::<(BlockNumber,)>() {
+            Ok((num,)) => block_number_to_id(num),
+            Err(_) => {
+                let (hash,): (RpcH256,) = try_bf!(params.parse());
+                BlockId::Hash(hash.into())
+            }
+        };
+
+        Box::new(
+            self.blockchain
+                .get_block_receipts(id)
+                .map_err(jsonrpc_error)
+                .map(|receipts| {
+                    let receipts: Vec<_> = receipts
+                        .into_iter()
+                        .map(|(receipt, txn)| RpcTransactionReceipt::new(receipt, txn))
+                        .collect();
+                    serde_json::to_value(receipts).expect("receipt serialization cannot fail")
+                }),
+        )
+    }
+
+    /// `eth_createAccessList`, returning the accounts and storage slots a
+    /// call accesses, and the gas it uses.
+    ///
+    /// A call which fails still returns the accesses made before it failed,
+    /// along with the reason why it failed.
+    pub fn create_access_list(&self, meta: Metadata, params: Params) -> BoxFuture<Value> {
+        let (request, num) = match params.clone().parse::<(CallRequest,)>() {
+            Ok((request,)) => (request, BlockNumber::default()),
+            Err(_) => try_bf!(params.parse::<(CallRequest, BlockNumber)>()),
+        };
+        let signed = try_bf!(self.sign_call(&meta, request));
+
+        Box::new(
+            self.blockchain
+                .create_access_list(signed, block_number_to_state_id(num))
+                .map_err(errors::call)
+                .map(|(access_list, executed)| {
+                    let result = RpcAccessListResult {
+                        access_list: access_list
+                            .into_iter()
+                            .map(|(address, slots)| RpcAccessListItem {
+                                address: address.into(),
+                                storage_keys: slots.into_iter().map(Into::into).collect(),
+                            })
+                            .collect(),
+                        gas_used: executed.gas_used.into(),
+                        error: executed.exception.map(|err| err.to_string()),
+                    };
+                    serde_json::to_value(result).expect("access list serialization cannot fail")
+                }),
+        )
+    }
+
+    /// Fake-sign a call request, defaulting its gas to the call gas cap.
+    fn sign_call(&self, meta: &Metadata, mut request: CallRequest) -> Result<SignedTransaction> {
+        if request.gas.is_none() {
+            request.gas = Some(self.blockchain.call_gas_cap().into());
+        }
+        fake_sign::sign_call(request.into(), meta.is_dapp())
+    }
+
+    fn call_at(
+        &self,
+        meta: Metadata,
+        request: CallRequest,
+        num: BlockNumber,
+        overrides: Option<StateOverride>,
+    ) -> BoxFuture<Bytes> {
+        let signed = try_bf!(self.sign_call(&meta, request));
+
+        Box::new(
+            self.blockchain
+                .simulate_transaction(signed, block_number_to_state_id(num), overrides)
+                .map_err(errors::call)
+                .and_then(|executed| match executed.exception {
+                    Some(ref exception) => Err(errors::vm(exception, &executed.output)),
+                    None => Ok(executed),
+                })
+                .map(|executed| executed.output.into()),
+        )
     }
 }
 
@@ -89,19 +375,21 @@ impl Eth for EthClient {
 
     fn protocol_version(&self) -> Result<String> {
         // Ethereum wire protocol version: https://github.com/ethereum/wiki/wiki/Ethereum-Wire-Protocol#fast-synchronization-pv63
-        Ok(format!("{}", 63))
+        Ok(format!("{:#x}", 63))
     }
 
     fn syncing(&self) -> Result<bool> {
+        // The simulator is the only node, so it is always synced.
         Ok(false)
     }
 
     fn author(&self, _meta: Metadata) -> Result<RpcH160> {
-        Ok(Default::default())
+        Ok(self.blockchain.coinbase().into())
     }
 
     fn is_mining(&self) -> Result<bool> {
-        Ok(true)
+        // Blocks are only produced on their own in auto and interval mining mode.
+        Ok(self.blockchain.mining_mode() != MiningMode::Manual)
     }
 
     fn hashrate(&self) -> Result<RpcU256> {
@@ -113,7 +401,10 @@ impl Eth for EthClient {
     }
 
     fn accounts(&self, _meta: Metadata) -> Result<Vec<RpcH160>> {
-        Ok(vec![])
+        self.accounts
+            .accounts()
+            .map(|accounts| accounts.into_iter().map(Into::into).collect())
+            .map_err(|err| errors::account("Could not fetch accounts.", err))
     }
 
     fn block_number(&self) -> BoxFuture<RpcU256> {
@@ -126,7 +417,7 @@ impl Eth for EthClient {
         let address = address.into();
         let num = num.unwrap_or_default();
 
-        let state = match self.blockchain.state(block_number_to_id(num)) {
+        let state = match self.blockchain.state(block_number_to_state_id(num)) {
             Ok(state) => state,
             Err(err) => return Box::new(future::err(jsonrpc_error(err))),
         };
@@ -149,7 +440,7 @@ impl Eth for EthClient {
         let pos: U256 = RpcU256::into(pos);
         let num = num.unwrap_or_default();
 
-        let state = match self.blockchain.state(block_number_to_id(num)) {
+        let state = match self.blockchain.state(block_number_to_state_id(num)) {
             Ok(state) => state,
             Err(err) => return Box::new(future::err(jsonrpc_error(err))),
         };
@@ -170,7 +461,17 @@ impl Eth for EthClient {
         let address: Address = RpcH160::into(address);
         let num = num.unwrap_or_default();
 
-        let state = match self.blockchain.state(block_number_to_id(num)) {
+        // The pending nonce accounts for transactions awaiting a block.
+        if let BlockNumber::Pending = num {
+            return Box::new(future::done(
+                self.blockchain
+                    .pending_nonce(&address)
+                    .map_err(jsonrpc_error)
+                    .map(Into::into),
+            ));
+        }
+
+        let state = match self.blockchain.state(block_number_to_state_id(num)) {
             Ok(state) => state,
             Err(err) => return Box::new(future::err(jsonrpc_error(err))),
         };
@@ -193,14 +494,8 @@ impl Eth for EthClient {
     }
 
     fn block_transaction_count_by_number(&self, num: BlockNumber) -> BoxFuture<Option<RpcU256>> {
-        // We don't track pending transactions.
-        if let BlockNumber::Pending = num {
-            return Box::new(future::ok(Some(0.into())));
-        }
-
         Box::new(
-            self.blockchain
-                .get_block(block_number_to_id(num))
+            self.block(num)
                 .and_then(|blk| future::ok(blk.map(|blk| blk.transactions().len().into())))
                 .map_err(jsonrpc_error),
         )
@@ -220,11 +515,13 @@ impl Eth for EthClient {
         let address: Address = RpcH160::into(address);
         let num = num.unwrap_or_default();
 
-        let state = match self.blockchain.state(block_number_to_id(num)) {
+        let state = match self.blockchain.state(block_number_to_state_id(num)) {
             Ok(state) => state,
             Err(err) => return Box::new(future::err(jsonrpc_error(err))),
         };
 
+        // The code of a confidential contract is stored as deployed, i.e. it may
+        // be the confidential deployment wrapper rather than plain runtime code.
         Box::new(future::done(
             state
                 .code(&address)
@@ -234,13 +531,17 @@ impl Eth for EthClient {
     }
 
     fn block_by_hash(&self, hash: RpcH256, include_txs: bool) -> BoxFuture<Option<RichBlock>> {
+        let eip86_transition = self.blockchain.spec().params().eip86_transition;
+
         Box::new(
             self.blockchain
                 .get_block_by_hash(hash.into())
                 .and_then(
                     move |blk| -> Box<dyn Future<Item = _, Error = Error> + Send> {
                         match blk {
-                            Some(blk) => Box::new(future::ok(Some(blk.rich_block(include_txs)))),
+                            Some(blk) => Box::new(future::ok(Some(
+                                blk.rich_block(include_txs, eip86_transition),
+                            ))),
                             None => Box::new(future::ok(None)),
                         }
                     },
@@ -250,13 +551,16 @@ impl Eth for EthClient {
     }
 
     fn block_by_number(&self, num: BlockNumber, include_txs: bool) -> BoxFuture<Option<RichBlock>> {
+        let eip86_transition = self.blockchain.spec().params().eip86_transition;
+
         Box::new(
-            self.blockchain
-                .get_block(block_number_to_id(num))
+            self.block(num)
                 .and_then(
                     move |blk| -> Box<dyn Future<Item = _, Error = Error> + Send> {
                         match blk {
-                            Some(blk) => Box::new(future::ok(Some(blk.rich_block(include_txs)))),
+                            Some(blk) => Box::new(future::ok(Some(
+                                blk.rich_block(include_txs, eip86_transition),
+                            ))),
                             None => Box::new(future::ok(None)),
                         }
                     },
@@ -267,7 +571,7 @@ impl Eth for EthClient {
 
     fn transaction_by_hash(&self, hash: RpcH256) -> BoxFuture<Option<RpcTransaction>> {
         let hash = hash.into();
-        let eip86_transition = genesis::SPEC.params().eip86_transition;
+        let eip86_transition = self.blockchain.spec().params().eip86_transition;
 
         Box::new(
             self.blockchain
@@ -286,11 +590,11 @@ impl Eth for EthClient {
         index: Index,
     ) -> BoxFuture<Option<RpcTransaction>> {
         let hash = hash.into();
-        let eip86_transition = genesis::SPEC.params().eip86_transition;
+        let eip86_transition = self.blockchain.spec().params().eip86_transition;
 
         Box::new(
             self.blockchain
-                .get_txn_by_block_hash_and_index(hash, index.value() as u32)
+                .get_txn_by_block_hash_and_index(hash, index.value())
                 .and_then(move |txn| {
                     txn.map(|txn| Ok(RpcTransaction::from_localized(txn, eip86_transition)))
                         .transpose()
@@ -304,16 +608,10 @@ impl Eth for EthClient {
         num: BlockNumber,
         index: Index,
     ) -> BoxFuture<Option<RpcTransaction>> {
-        // We don't have pending transactions.
-        if let BlockNumber::Pending = num {
-            return Box::new(future::ok(None));
-        }
-
-        let eip86_transition = genesis::SPEC.params().eip86_transition;
+        let eip86_transition = self.blockchain.spec().params().eip86_transition;
 
         Box::new(
-            self.blockchain
-                .get_txn(block_number_to_id(num), index.value() as u32)
+            self.txn(num, index)
                 .and_then(move |txn| {
                     txn.map(|txn| Ok(RpcTransaction::from_localized(txn, eip86_transition)))
                         .transpose()
@@ -358,13 +656,15 @@ impl Eth for EthClient {
 
     fn logs(&self, filter: Filter) -> BoxFuture<Vec<RpcLog>> {
         let filter: EthcoreFilter = filter.into();
+        let limit = filter.limit;
 
         Box::new(
             self.blockchain
                 .clone()
                 .logs(filter)
                 .map_err(jsonrpc_error)
-                .map(|logs| logs.into_iter().map(Into::into).collect()),
+                .map(|logs| logs.into_iter().map(Into::into).collect())
+                .map(move |logs| limit_logs(logs, limit)),
         )
     }
 
@@ -385,7 +685,7 @@ impl Eth for EthClient {
             self.blockchain
                 .send_raw_transaction(raw.into())
                 .map(|(hash, _result)| hash.into())
-                .map_err(execution_error),
+                .map_err(transaction_error),
         )
     }
 
@@ -399,20 +699,7 @@ impl Eth for EthClient {
         request: CallRequest,
         num: Trailing<BlockNumber>,
     ) -> BoxFuture<Bytes> {
-        let num = num.unwrap_or_default();
-
-        let signed = try_bf!(fake_sign::sign_call(request.into(), meta.is_dapp()));
-
-        Box::new(
-            self.blockchain
-                .simulate_transaction(signed, block_number_to_id(num))
-                .map_err(errors::call)
-                .and_then(|executed| match executed.exception {
-                    Some(ref exception) => Err(errors::vm(exception, &executed.output)),
-                    None => Ok(executed),
-                })
-                .map(|executed| executed.output.into()),
-        )
+        self.call_at(meta, request, num.unwrap_or_default(), None)
     }
 
     fn estimate_gas(
@@ -423,11 +710,11 @@ impl Eth for EthClient {
     ) -> BoxFuture<RpcU256> {
         let num = num.unwrap_or_default();
 
-        let signed = try_bf!(fake_sign::sign_call(request.into(), meta.is_dapp()));
+        let signed = try_bf!(self.sign_call(&meta, request));
 
         Box::new(
             self.blockchain
-                .estimate_gas(signed, block_number_to_id(num))
+                .estimate_gas(signed, block_number_to_state_id(num))
                 .map_err(execution_error)
                 .map(Into::into),
         )
@@ -451,3 +738,601 @@ impl Eth for EthClient {
         ))
     }
 }
+
+/// RLP of the signed transaction, as originally submitted.
+fn raw_transaction(txn: LocalizedTransaction) -> Bytes {
+    rlp::encode(&txn.signed).to_vec().into()
+}
+
+impl EthRaw for EthClient {
+    fn raw_transaction_by_hash(&self, hash: RpcH256) -> BoxFuture<Option<Bytes>> {
+        Box::new(
+            self.blockchain
+                .get_txn_by_hash(hash.into())
+                .map(|txn| txn.map(raw_transaction))
+                .map_err(jsonrpc_error),
+        )
+    }
+
+    fn raw_transaction_by_block_hash_and_index(
+        &self,
+        hash: RpcH256,
+        index: Index,
+    ) -> BoxFuture<Option<Bytes>> {
+        Box::new(
+            self.blockchain
+                .get_txn_by_block_hash_and_index(hash.into(), index.value())
+                .map(|txn| txn.map(raw_transaction))
+                .map_err(jsonrpc_error),
+        )
+    }
+
+    fn raw_transaction_by_block_number_and_index(
+        &self,
+        num: BlockNumber,
+        index: Index,
+    ) -> BoxFuture<Option<Bytes>> {
+        Box::new(
+            self.txn(num, index)
+                .map(|txn| txn.map(raw_transaction))
+                .map_err(jsonrpc_error),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethcore::transaction::{Action, Transaction, UnverifiedTransaction};
+    use jsonrpc_core::ErrorCode;
+
+    use super::*;
+    use crate::{
+        blockchain::BlockchainConfig,
+        test_support::{fake_txn, new_blockchain, test_config},
+    };
+
+    fn new_client(mining_mode: MiningMode) -> EthClient {
+        let blockchain = new_blockchain(BlockchainConfig {
+            mining_mode,
+            ..test_config()
+        });
+        EthClient::new(
+            Arc::new(blockchain),
+            Arc::new(AccountProvider::transient_provider()),
+        )
+    }
+
+    #[test]
+    fn test_syncing() {
+        let client = new_client(MiningMode::Auto);
+        assert_eq!(
+            serde_json::to_value(client.syncing().unwrap()).unwrap(),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_mining_reflects_mining_mode() {
+        assert!(new_client(MiningMode::Auto).is_mining().unwrap());
+        assert!(new_client(MiningMode::Interval(Duration::from_secs(1)))
+            .is_mining()
+            .unwrap());
+        assert!(!new_client(MiningMode::Manual).is_mining().unwrap());
+    }
+
+    #[test]
+    fn test_protocol_version_and_hashrate() {
+        let client = new_client(MiningMode::Auto);
+        assert_eq!(client.protocol_version().unwrap(), "0x3f");
+        assert_eq!(
+            serde_json::to_value(client.hashrate().unwrap()).unwrap(),
+            Value::String("0x0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_raw_transaction() {
+        let client = new_client(MiningMode::Auto);
+        let txn = fake_txn(
+            Address::from(1),
+            0,
+            Action::Call(Address::from(2)),
+            vec![1, 2, 3],
+        );
+        let (hash, _) = client.blockchain.send_transaction(txn.clone()).unwrap();
+        let index = || -> Index { serde_json::from_str("\"0x0\"").unwrap() };
+
+        let raws = vec![
+            client.raw_transaction_by_hash(hash.into()),
+            client.raw_transaction_by_block_hash_and_index(
+                client
+                    .blockchain
+                    .get_latest_block()
+                    .wait()
+                    .unwrap()
+                    .hash()
+                    .into(),
+                index(),
+            ),
+            client.raw_transaction_by_block_number_and_index(BlockNumber::Num(1), index()),
+        ];
+        for raw in raws {
+            let raw = raw.wait().unwrap().unwrap().into_vec();
+            let decoded: UnverifiedTransaction = rlp::decode(&raw).unwrap();
+            assert_eq!(decoded, *txn);
+        }
+
+        assert!(client
+            .raw_transaction_by_hash(H256::from(1).into())
+            .wait()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_transaction_by_index_not_found() {
+        let client = new_client(MiningMode::Auto);
+        let txn = fake_txn(Address::from(1), 0, Action::Call(Address::from(2)), vec![]);
+        client.blockchain.send_transaction(txn).unwrap();
+        let block_hash: RpcH256 = client
+            .blockchain
+            .get_latest_block()
+            .wait()
+            .unwrap()
+            .hash()
+            .into();
+        let index =
+            |index: u64| -> Index { serde_json::from_str(&format!("\"{:#x}\"", index)).unwrap() };
+
+        let by_number = |num, index| {
+            client
+                .transaction_by_block_number_and_index(num, index)
+                .wait()
+                .unwrap()
+        };
+        let by_hash = |hash, index| {
+            client
+                .transaction_by_block_hash_and_index(hash, index)
+                .wait()
+                .unwrap()
+        };
+        assert!(by_number(BlockNumber::Num(1), index(0)).is_some());
+        assert!(by_hash(block_hash.clone(), index(0)).is_some());
+
+        // Indices beyond the block's transactions, including ones which do not
+        // fit 32 bits, are not found.
+        for value in vec![1, 1 << 32] {
+            assert!(by_number(BlockNumber::Num(1), index(value)).is_none());
+            assert!(by_hash(block_hash.clone(), index(value)).is_none());
+            assert!(client
+                .raw_transaction_by_block_number_and_index(BlockNumber::Num(1), index(value))
+                .wait()
+                .unwrap()
+                .is_none());
+        }
+        // Neither are unknown blocks.
+        assert!(by_number(BlockNumber::Num(5), index(0)).is_none());
+        assert!(by_number(BlockNumber::Pending, index(0)).is_none());
+        assert!(by_hash(RpcH256::from(1), index(0)).is_none());
+
+        // Only malformed inputs are errors.
+        assert!(serde_json::from_str::<Index>("\"0xzz\"").is_err());
+    }
+
+    #[test]
+    fn test_receipt_effective_gas_price() {
+        let client = new_client(MiningMode::Auto);
+        let sender: Address = "1cca28600d7491365520b31b466f88647b9839ec".parse().unwrap();
+        let txn = Transaction {
+            nonce: U256::zero(),
+            gas_price: 2_000_000_000.into(),
+            gas: 1_000_000.into(),
+            action: Action::Call(Address::from(2)),
+            value: U256::zero(),
+            data: vec![],
+        }
+        .fake_sign(sender);
+        let (hash, _) = client.blockchain.send_transaction(txn).unwrap();
+
+        let params = Params::Array(vec![serde_json::to_value(RpcH256::from(hash)).unwrap()]);
+        let receipt = client
+            .transaction_receipt_with_gas_price(params)
+            .wait()
+            .unwrap();
+        assert_eq!(receipt["effectiveGasPrice"], "0x77359400");
+        assert_eq!(receipt["transactionHash"], format!("{:#x}", hash));
+
+        let params = Params::Array(vec![serde_json::to_value(RpcH256::from(1)).unwrap()]);
+        let receipt = client
+            .transaction_receipt_with_gas_price(params)
+            .wait()
+            .unwrap();
+        assert_eq!(receipt, Value::Null);
+    }
+
+    #[test]
+    fn test_block_receipts() {
+        let client = new_client(MiningMode::Manual);
+        let sender = Address::from(1);
+        let mut hashes = vec![];
+        for nonce in 0..3 {
+            let txn = fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+            hashes.push(client.blockchain.send_transaction(txn).unwrap().0);
+        }
+        client.blockchain.mine_pending_block();
+
+        let params = Params::Array(vec![Value::from("0x1")]);
+        let receipts = client.block_receipts(params).wait().unwrap();
+        let receipts = receipts.as_array().unwrap();
+        assert_eq!(receipts.len(), 3);
+        let mut cumulative_gas_used = 0;
+        for (index, receipt) in receipts.iter().enumerate() {
+            assert_eq!(receipt["transactionHash"], format!("{:#x}", hashes[index]));
+            assert_eq!(receipt["transactionIndex"], format!("{:#x}", index));
+            let gas = receipt["cumulativeGasUsed"].as_str().unwrap();
+            let gas = u64::from_str_radix(&gas[2..], 16).unwrap();
+            assert!(gas > cumulative_gas_used);
+            cumulative_gas_used = gas;
+        }
+
+        // By hash.
+        let block = client.blockchain.get_latest_block().wait().unwrap();
+        let params = Params::Array(vec![
+            serde_json::to_value(RpcH256::from(block.hash())).unwrap()
+        ]);
+        assert_eq!(
+            client
+                .block_receipts(params)
+                .wait()
+                .unwrap()
+                .as_array()
+                .unwrap(),
+            receipts
+        );
+
+        // Empty and unknown blocks.
+        let params = Params::Array(vec![Value::from("0x0")]);
+        assert_eq!(
+            client.block_receipts(params).wait().unwrap(),
+            Value::Array(vec![])
+        );
+        let params = Params::Array(vec![Value::from("0x5")]);
+        assert!(client.block_receipts(params).wait().is_err());
+    }
+
+    #[test]
+    fn test_full_block_transactions_carry_block_context() {
+        let client = new_client(MiningMode::Manual);
+        for nonce in 0..3 {
+            let txn = fake_txn(
+                Address::from(1),
+                nonce,
+                Action::Call(Address::from(2)),
+                vec![],
+            );
+            client.blockchain.send_transaction(txn).unwrap();
+        }
+        client.blockchain.mine_pending_block();
+
+        let block = client
+            .block_by_number(BlockNumber::Num(1), true)
+            .wait()
+            .unwrap()
+            .unwrap();
+        let block = serde_json::to_value(block).unwrap();
+        let transactions = block["transactions"].as_array().unwrap();
+        assert_eq!(transactions.len(), 3);
+        for (index, txn) in transactions.iter().enumerate() {
+            assert_eq!(
+                txn["transactionIndex"],
+                Value::from(format!("0x{:x}", index))
+            );
+            assert_eq!(txn["blockHash"], block["hash"]);
+            assert_eq!(txn["blockNumber"], Value::from("0x1"));
+        }
+
+        // The block hash round-trips.
+        let hash = serde_json::from_value(block["hash"].clone()).unwrap();
+        let by_hash = client.block_by_hash(hash, true).wait().unwrap().unwrap();
+        assert_eq!(serde_json::to_value(by_hash).unwrap(), block);
+    }
+
+    #[test]
+    fn test_code_at() {
+        let client = new_client(MiningMode::Auto);
+        // Init code returning the runtime code STOP.
+        let init_code = vec![
+            0x60, 0x01, 0x80, 0x60, 0x0b, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3, 0x00,
+        ];
+        let txn = fake_txn(Address::from(1), 0, Action::Create, init_code);
+        let (hash, _) = client.blockchain.send_transaction(txn).unwrap();
+        let address = client
+            .blockchain
+            .get_txn_receipt_by_hash(hash)
+            .wait()
+            .unwrap()
+            .unwrap()
+            .contract_address
+            .unwrap();
+
+        let code = |num| client.code_at(address.into(), Some(num).into()).wait();
+        assert!(code(BlockNumber::Num(0)).unwrap().into_vec().is_empty());
+        assert_eq!(code(BlockNumber::Latest).unwrap().into_vec(), vec![0x00]);
+        // Externally owned accounts have no code.
+        assert!(client
+            .code_at(Address::from(1).into(), None.into())
+            .wait()
+            .unwrap()
+            .into_vec()
+            .is_empty());
+        // Blocks above the head are not found.
+        assert!(code(BlockNumber::Num(2)).is_err());
+    }
+
+    #[test]
+    fn test_call_gas_cap() {
+        let client = new_client(MiningMode::Auto);
+        let request = |gas: &str| -> CallRequest {
+            let json = format!(
+                r#"{{"to": "0x0000000000000000000000000000000000000002"{}}}"#,
+                gas
+            );
+            serde_json::from_str(&json).unwrap()
+        };
+        let call = |gas| {
+            client
+                .call(Default::default(), request(gas), None.into())
+                .wait()
+        };
+        let estimate_gas = |gas| {
+            client
+                .estimate_gas(Default::default(), request(gas), None.into())
+                .wait()
+        };
+
+        // Calls without gas get the cap.
+        assert!(call("").is_ok());
+        assert!(estimate_gas("").is_ok());
+        assert!(call(r#", "gas": "0x2faf080""#).is_ok());
+        // Calls above the cap are rejected.
+        assert!(call(r#", "gas": "0x2faf081""#).is_err());
+        assert!(estimate_gas(r#", "gas": "0x2faf081""#).is_err());
+    }
+
+    #[test]
+    fn test_receipt_fields() {
+        let client = new_client(MiningMode::Auto);
+        let sender = Address::from(1);
+        let send = |nonce: u64, action| {
+            let txn = fake_txn(sender, nonce, action, vec![0x00]);
+            client.blockchain.send_transaction(txn).unwrap().0
+        };
+        let receipt = |hash: H256| {
+            let params = Params::Array(vec![serde_json::to_value(RpcH256::from(hash)).unwrap()]);
+            client
+                .transaction_receipt_with_gas_price(params)
+                .wait()
+                .unwrap()
+        };
+
+        let creation = receipt(send(0, Action::Create));
+        let contract = creation["contractAddress"].clone();
+        assert!(contract.is_string());
+        assert_eq!(creation["from"], format!("{:#x}", sender));
+        assert_eq!(creation["to"], Value::Null);
+        assert!(creation.as_object().unwrap().contains_key("to"));
+        assert_eq!(creation["type"], "0x0");
+        assert_eq!(creation["effectiveGasPrice"], "0x0");
+
+        let callee = Address::from(2);
+        let call = receipt(send(1, Action::Call(callee)));
+        assert_eq!(call["from"], format!("{:#x}", sender));
+        assert_eq!(call["to"], format!("{:#x}", callee));
+        assert_eq!(call["contractAddress"], Value::Null);
+        assert!(call.as_object().unwrap().contains_key("contractAddress"));
+        assert_eq!(call["type"], "0x0");
+        assert_eq!(call["effectiveGasPrice"], "0x0");
+
+        // Both receipts have the same shape.
+        let keys = |receipt: &Value| {
+            let mut keys: Vec<String> = receipt.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(&creation), keys(&call));
+    }
+
+    #[test]
+    fn test_create_access_list() {
+        let client = new_client(MiningMode::Auto);
+        let contract = Address::from(0x1234);
+        // SLOAD(1), then REVERT(0, 0) if the call carries data.
+        let runtime = vec![
+            0x60, 0x01, 0x54, 0x50, 0x36, 0x60, 0x0d, 0x57, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5b,
+            0x60, 0x00, 0x60, 0x00, 0xfd,
+        ];
+        client.blockchain.set_code(contract, runtime).unwrap();
+        let create_access_list = |data: &str| {
+            let request = format!(r#"{{"to": "{:#x}", "data": "{}"}}"#, contract, data);
+            let params = Params::Array(vec![
+                serde_json::from_str(&request).unwrap(),
+                Value::from("latest"),
+            ]);
+            client
+                .create_access_list(Default::default(), params)
+                .wait()
+                .unwrap()
+        };
+
+        let result = create_access_list("0x");
+        assert_eq!(
+            result["accessList"][0]["address"],
+            format!("{:#x}", contract)
+        );
+        assert_eq!(
+            result["accessList"][0]["storageKeys"][0],
+            format!("{:#x}", H256::from(1))
+        );
+        assert!(result["gasUsed"].as_str().unwrap().starts_with("0x"));
+        assert!(result.get("error").is_none());
+
+        let result = create_access_list("0x01");
+        assert_eq!(
+            result["accessList"][0]["address"],
+            format!("{:#x}", contract)
+        );
+        assert!(result["error"].is_string());
+    }
+
+    #[test]
+    fn test_block_number_only_counts_mined_blocks() {
+        for mining_mode in vec![MiningMode::Auto, MiningMode::Manual] {
+            let client = new_client(mining_mode);
+            let sender = Address::from(1);
+            let recipient = Address::from(2);
+            client.blockchain.set_balance(sender, 10.into()).unwrap();
+            let txn = Transaction {
+                nonce: U256::zero(),
+                gas_price: U256::zero(),
+                gas: 1_000_000.into(),
+                action: Action::Call(recipient),
+                value: 3.into(),
+                data: vec![],
+            }
+            .fake_sign(sender);
+            let block_number = || client.block_number().wait().unwrap();
+            let balance =
+                |address: Address| client.balance(address.into(), None.into()).wait().unwrap();
+
+            assert_eq!(block_number(), RpcU256::from(0));
+            client.blockchain.send_transaction(txn).unwrap();
+            if mining_mode == MiningMode::Manual {
+                // Pending transactions are not reported until mined.
+                assert_eq!(block_number(), RpcU256::from(0));
+                assert_eq!(balance(recipient), RpcU256::from(0));
+                assert!(client
+                    .block_by_number(BlockNumber::Num(1), false)
+                    .wait()
+                    .unwrap()
+                    .is_none());
+                client.blockchain.mine_pending_block();
+            }
+
+            assert_eq!(block_number(), RpcU256::from(1));
+            assert!(client
+                .block_by_number(BlockNumber::Num(1), false)
+                .wait()
+                .unwrap()
+                .is_some());
+            assert_eq!(balance(sender), RpcU256::from(7));
+            assert_eq!(balance(recipient), RpcU256::from(3));
+            assert_eq!(client.gas_price().unwrap(), RpcU256::from(0));
+        }
+    }
+
+    #[test]
+    fn test_pending_transaction_count() {
+        let client = new_client(MiningMode::Manual);
+        let sender = Address::from(1);
+        for nonce in 0..2 {
+            let txn = fake_txn(sender, nonce, Action::Call(Address::from(2)), vec![]);
+            client.blockchain.send_transaction(txn).unwrap();
+        }
+
+        let count = |num| {
+            client
+                .transaction_count(sender.into(), Some(num).into())
+                .wait()
+                .unwrap()
+        };
+        assert_eq!(count(BlockNumber::Latest), RpcU256::from(0));
+        assert_eq!(count(BlockNumber::Pending), RpcU256::from(2));
+        // Never transacted.
+        assert_eq!(
+            client
+                .transaction_count(Address::from(3).into(), Some(BlockNumber::Pending).into())
+                .wait()
+                .unwrap(),
+            RpcU256::from(0)
+        );
+
+        client.blockchain.mine_pending_block();
+        assert_eq!(count(BlockNumber::Latest), RpcU256::from(2));
+        assert_eq!(count(BlockNumber::Earliest), RpcU256::from(0));
+    }
+
+    #[test]
+    fn test_pending_balance() {
+        let client = new_client(MiningMode::Manual);
+        let sender = Address::from(1);
+        let recipient = Address::from(2);
+        client
+            .blockchain
+            .set_balance(sender, U256::from(10))
+            .unwrap();
+        let txn = Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas: 1_000_000.into(),
+            action: Action::Call(recipient),
+            value: U256::from(3),
+            data: vec![],
+        }
+        .fake_sign(sender);
+        client.blockchain.send_transaction(txn).unwrap();
+
+        let balance = |num| {
+            client
+                .balance(recipient.into(), Some(num).into())
+                .wait()
+                .unwrap()
+        };
+        assert_eq!(balance(BlockNumber::Latest), RpcU256::from(0));
+        assert_eq!(balance(BlockNumber::Pending), RpcU256::from(3));
+
+        client.blockchain.mine_pending_block();
+        assert_eq!(balance(BlockNumber::Latest), RpcU256::from(3));
+    }
+
+    #[test]
+    fn test_send_raw_transaction_errors() {
+        let blockchain = new_blockchain(Default::default());
+        let client = EthClient::new(
+            Arc::new(blockchain),
+            Arc::new(AccountProvider::transient_provider()),
+        );
+        let txn = Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas: 1_000_000.into(),
+            action: Action::Call(Address::from(2)),
+            value: U256::zero(),
+            data: vec![],
+        };
+
+        // Unsigned, so only the gas price check fails.
+        let raw = rlp::encode(&*txn.clone().fake_sign(Address::from(1))).to_vec();
+        let err = client.send_raw_transaction(raw.into()).wait().unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(-32000));
+        assert_eq!(err.message, "transaction underpriced");
+
+        // A signature from which no sender can be recovered.
+        let mut stream = rlp::RlpStream::new_list(9);
+        stream
+            .append(&txn.nonce)
+            .append(&txn.gas_price)
+            .append(&txn.gas)
+            .append(&Address::from(2))
+            .append(&txn.value)
+            .append(&txn.data)
+            .append(&27u8)
+            .append(&U256::one())
+            .append(&U256::one());
+        let raw = stream.out();
+        let err = client.send_raw_transaction(raw.into()).wait().unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(-32000));
+        assert_eq!(err.message, "invalid sender");
+    }
+}
@@ -39,16 +39,24 @@ use parity_rpc::v1::{
     traits::EthPubSub,
     types::{pubsub, TransactionOutcome},
 };
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use tokio::spawn;
 
-use crate::{blockchain::Blockchain, pubsub::Listener};
+use crate::{
+    blockchain::Blockchain,
+    pubsub::{Broker, Listener, ListenerId},
+};
 
 type PubSubClient = Sink<pubsub::Result>;
 
 /// Eth PubSub implementation.
+///
+/// The notification handler is registered with the broker while there are
+/// subscriptions.
 pub struct EthPubSubClient {
     handler: Arc<ChainNotificationHandler>,
+    broker: Arc<Broker>,
+    listener: Mutex<Option<ListenerId>>,
     heads_subscribers: Arc<RwLock<Subscribers<PubSubClient>>>,
     logs_subscribers: Arc<RwLock<Subscribers<(PubSubClient, EthFilter)>>>,
     tx_subscribers: Arc<RwLock<Subscribers<(PubSubClient, EthTxFilter)>>>,
@@ -56,7 +64,7 @@ pub struct EthPubSubClient {
 
 impl EthPubSubClient {
     /// Creates new `EthPubSubClient`.
-    pub fn new(blockchain: Arc<Blockchain>) -> Self {
+    pub fn new(blockchain: Arc<Blockchain>, broker: Arc<Broker>) -> Self {
         let heads_subscribers = Arc::new(RwLock::new(Subscribers::default()));
         let logs_subscribers = Arc::new(RwLock::new(Subscribers::default()));
         let tx_subscribers = Arc::new(RwLock::new(Subscribers::default()));
@@ -68,6 +76,8 @@ impl EthPubSubClient {
                 logs_subscribers: logs_subscribers.clone(),
                 tx_subscribers: tx_subscribers.clone(),
             }),
+            broker,
+            listener: Mutex::new(None),
             heads_subscribers,
             logs_subscribers,
             tx_subscribers,
@@ -78,6 +88,27 @@ impl EthPubSubClient {
     pub fn handler(&self) -> Weak<ChainNotificationHandler> {
         Arc::downgrade(&self.handler)
     }
+
+    /// Register the notification handler with the broker, unless it is.
+    fn register_handler(&self) {
+        let mut listener = self.listener.lock();
+        if listener.is_none() {
+            *listener = Some(self.broker.add_listener(self.handler()));
+        }
+    }
+
+    /// Deregister the notification handler if there are no subscriptions left.
+    fn deregister_idle_handler(&self) {
+        let mut listener = self.listener.lock();
+        let idle = self.heads_subscribers.read().is_empty()
+            && self.logs_subscribers.read().is_empty()
+            && self.tx_subscribers.read().is_empty();
+        if idle {
+            if let Some(id) = listener.take() {
+                self.broker.remove_listener(id);
+            }
+        }
+    }
 }
 
 /// PubSub Notification handler.
@@ -129,6 +160,10 @@ impl ChainNotificationHandler {
         );
     }
 
+    /// Push the logs of the new blocks matching each subscriber's filter.
+    ///
+    /// Every subscription is served independently, and its logs are sent in
+    /// canonical order.
     fn notify_logs(&self, from_block: u64, to_block: u64) {
         for &(ref subscriber, ref filter) in self.logs_subscribers.read().values() {
             let mut filter = filter.clone();
@@ -141,13 +176,15 @@ impl ChainNotificationHandler {
             spawn(
                 self.blockchain
                     .logs(filter)
-                    .map(move |logs| {
-                        for log in logs {
-                            Self::notify(&subscriber, pubsub::Result::Log(log.into()));
-                        }
-                    })
-                    .map_err(move |err| {
-                        error!("Failed to fetch logs: {:?}", err);
+                    .map_err(|err| error!("Failed to fetch logs: {:?}", err))
+                    .and_then(move |logs| {
+                        let logs = logs
+                            .into_iter()
+                            .map(|log| Ok(pubsub::Result::Log(log.into())));
+                        subscriber
+                            .send_all(stream::iter_ok(logs))
+                            .map(|_| ())
+                            .map_err(|err| warn!("Unable to send notification: {:?}", err))
                     }),
             );
         }
@@ -192,6 +229,7 @@ impl EthPubSub for EthPubSubClient {
         let error = match (kind, params.into()) {
             (pubsub::Kind::NewHeads, None) => {
                 self.heads_subscribers.write().push(subscriber);
+                self.register_handler();
                 return;
             }
             (pubsub::Kind::NewHeads, _) => {
@@ -201,11 +239,13 @@ impl EthPubSub for EthPubSubClient {
                 self.logs_subscribers
                     .write()
                     .push(subscriber, filter.into());
+                self.register_handler();
                 return;
             }
             (pubsub::Kind::Logs, _) => errors::invalid_params("logs", "Expected a filter object."),
             (pubsub::Kind::CompletedTransaction, Some(pubsub::Params::Transaction(filter))) => {
                 self.tx_subscribers.write().push(subscriber, filter.into());
+                self.register_handler();
                 return;
             }
             // we don't track pending transactions currently
@@ -220,7 +260,111 @@ impl EthPubSub for EthPubSubClient {
         let res = self.heads_subscribers.write().remove(&id).is_some();
         let res2 = self.logs_subscribers.write().remove(&id).is_some();
         let res3 = self.tx_subscribers.write().remove(&id).is_some();
+        self.deregister_idle_handler();
 
         Ok(res || res2 || res3)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethcore::transaction::Action;
+    use ethereum_types::Address;
+    use futures::{future, sync::mpsc};
+    use jsonrpc_core::{MetaIoHandler, Value};
+    use jsonrpc_pubsub::Session;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::test_support::{fake_txn, new_blockchain, test_config};
+
+    fn subscribe(io: &MetaIoHandler<Metadata>, metadata: &Metadata, address: Address) -> String {
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","method":"eth_subscribe","params":["logs",{}],"id":1}}"#,
+            format!(r#"{{"address":"{:?}"}}"#, address)
+        );
+        let response = io.handle_request_sync(&request, metadata.clone()).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        response["result"].as_str().unwrap().to_string()
+    }
+
+    fn unsubscribe(io: &MetaIoHandler<Metadata>, metadata: &Metadata, id: &str) {
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","method":"eth_unsubscribe","params":["{}"],"id":1}}"#,
+            id
+        );
+        let response = io.handle_request_sync(&request, metadata.clone()).unwrap();
+        assert_eq!(response, r#"{"jsonrpc":"2.0","result":true,"id":1}"#);
+    }
+
+    #[test]
+    fn test_logs_subscriptions() {
+        let blockchain = Arc::new(new_blockchain(test_config()));
+
+        // Contracts emitting an empty LOG0 on every call, mined in blocks 1 and 2.
+        let runtime = vec![0x60, 0x00, 0x60, 0x00, 0xa0, 0x00];
+        let contracts = [Address::from(0x10), Address::from(0x20)];
+        for (nonce, contract) in contracts.iter().enumerate() {
+            blockchain.set_code(*contract, runtime.clone()).unwrap();
+            let txn = fake_txn(
+                Address::from(1),
+                nonce as u64,
+                Action::Call(*contract),
+                vec![],
+            );
+            blockchain.send_transaction(txn).unwrap();
+        }
+
+        let broker = Arc::new(Broker::new(blockchain.clone()));
+        let client = EthPubSubClient::new(blockchain, broker.clone());
+        let handler = client.handler().upgrade().unwrap();
+        let mut io = MetaIoHandler::default();
+        io.extend_with(client.to_delegate());
+
+        // One session per contract, each subscribed to the logs of its contract.
+        let mut sessions: Vec<_> = contracts
+            .iter()
+            .map(|contract| {
+                let (sender, receiver) = mpsc::channel(8);
+                let mut metadata = Metadata::default();
+                metadata.session = Some(Arc::new(Session::new(sender)));
+                let id = subscribe(&io, &metadata, *contract);
+                (metadata, id, receiver)
+            })
+            .collect();
+        assert_eq!(broker.listener_count(), 1);
+
+        let runtime = Runtime::new().unwrap();
+        let notifier = handler.clone();
+        runtime.executor().spawn(future::lazy(move || {
+            notifier.notify_blocks(1, 2);
+            Ok(())
+        }));
+
+        for (index, (_, id, receiver)) in sessions.iter_mut().enumerate() {
+            let notification = receiver.by_ref().wait().next().unwrap().unwrap();
+            let notification: Value = serde_json::from_str(&notification).unwrap();
+            let log = &notification["params"]["result"];
+            assert_eq!(
+                notification["params"]["subscription"],
+                Value::String(id.clone())
+            );
+            assert_eq!(
+                log["address"],
+                Value::String(format!("{:?}", contracts[index]))
+            );
+            assert_eq!(
+                log["blockNumber"],
+                Value::String(format!("0x{:x}", index + 1))
+            );
+        }
+
+        // Unsubscribing frees the filters, and then the handler is deregistered.
+        for (metadata, id, _) in &sessions {
+            assert_eq!(broker.listener_count(), 1);
+            unsubscribe(&io, metadata, id);
+        }
+        assert!(handler.logs_subscribers.read().is_empty());
+        assert_eq!(broker.listener_count(), 0);
+    }
+}
@@ -1,4 +1,15 @@
-use jsonrpc_core::{futures::future, BoxFuture};
+use std::sync::Arc;
+
+use ethcore::{
+    account_provider::AccountProvider,
+    ids::BlockId,
+    transaction::{Action, SignedTransaction, Transaction},
+};
+use ethereum_types::Address;
+use jsonrpc_core::{
+    futures::{future, Future},
+    BoxFuture,
+};
 use parity_rpc::v1::{
     helpers::errors,
     metadata::Metadata,
@@ -9,11 +20,27 @@ use parity_rpc::v1::{
     },
 };
 
-pub struct EthSigningClient {}
+use crate::{
+    blockchain::Blockchain,
+    util::{execution_error, jsonrpc_error, transaction_error},
+};
+
+/// Eth signing rpc implementation.
+///
+/// Only accounts managed by the simulator (see the personal namespace) can
+/// be used for signing.
+pub struct EthSigningClient {
+    blockchain: Arc<Blockchain>,
+    accounts: Arc<AccountProvider>,
+}
 
 impl EthSigningClient {
-    pub fn new() -> EthSigningClient {
-        EthSigningClient {}
+    /// Creates new EthSigningClient.
+    pub fn new(blockchain: Arc<Blockchain>, accounts: Arc<AccountProvider>) -> EthSigningClient {
+        EthSigningClient {
+            blockchain,
+            accounts,
+        }
     }
 }
 
@@ -25,9 +52,59 @@ impl EthSigning for EthSigningClient {
             Make sure that the wallet is setup correctly in the client in case transaction signing is expected to happen transparently".to_string(), None)))
     }
 
-    fn send_transaction(&self, _: Metadata, _: TransactionRequest) -> BoxFuture<RpcH256> {
-        Box::new(future::failed(errors::unsupported("eth_sendTransaction is not implemented because the gateway cannot sign transactions. \
-            Make sure that the wallet is setup correctly in the client in case transaction signing is expected to happen transparently".to_string(), None)))
+    fn send_transaction(&self, _: Metadata, request: TransactionRequest) -> BoxFuture<RpcH256> {
+        let from: Address = match request.from {
+            Some(from) => from.into(),
+            None => return Box::new(future::failed(errors::invalid_params("from", "missing"))),
+        };
+        let nonce = match request.nonce {
+            Some(nonce) => nonce.into(),
+            None => match self.blockchain.pending_nonce(&from) {
+                Ok(nonce) => nonce,
+                Err(err) => return Box::new(future::failed(jsonrpc_error(err))),
+            },
+        };
+        let txn = Transaction {
+            nonce,
+            gas_price: request
+                .gas_price
+                .map(Into::into)
+                .unwrap_or_else(|| self.blockchain.gas_price()),
+            gas: self.blockchain.block_gas_limit(),
+            action: match request.to {
+                Some(to) => Action::Call(to.into()),
+                None => Action::Create,
+            },
+            value: request.value.map(Into::into).unwrap_or_default(),
+            data: request.data.map(Into::into).unwrap_or_default(),
+        };
+
+        // Estimate the gas limit unless one was given.
+        let gas: BoxFuture<_> = match request.gas {
+            Some(gas) => Box::new(future::ok(gas.into())),
+            None => Box::new(
+                self.blockchain
+                    .estimate_gas(txn.clone().fake_sign(from), BlockId::Latest)
+                    .map_err(errors::call),
+            ),
+        };
+
+        let blockchain = self.blockchain.clone();
+        let accounts = self.accounts.clone();
+        Box::new(gas.and_then(move |gas| {
+            let txn = Transaction { gas, ..txn };
+            let chain_id = blockchain.spec().chain_id();
+            let signature = accounts
+                .sign(from, None, txn.hash(Some(chain_id)))
+                .map_err(|err| errors::account("Could not sign transaction.", err))?;
+            let signed = SignedTransaction::new(txn.with_signature(signature, Some(chain_id)))
+                .map_err(execution_error)?;
+
+            blockchain
+                .send_transaction(signed)
+                .map(|(hash, _result)| hash.into())
+                .map_err(transaction_error)
+        }))
     }
 
     fn sign_transaction(
@@ -39,3 +116,43 @@ impl EthSigning for EthSigningClient {
             Make sure that the wallet is setup correctly in the client in case transaction signing is expected to happen transparently".to_string(), None)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{new_blockchain, test_config};
+
+    #[test]
+    fn test_send_transaction_from_unlocked_account() {
+        let blockchain = Arc::new(new_blockchain(test_config()));
+        let accounts = Arc::new(AccountProvider::transient_provider());
+        let client = EthSigningClient::new(blockchain.clone(), accounts.clone());
+
+        let from = accounts.new_account("secret").unwrap();
+        let request: TransactionRequest = serde_json::from_str(&format!(
+            r#"{{"from": "{:?}", "to": "0x0000000000000000000000000000000000000002"}}"#,
+            from
+        ))
+        .unwrap();
+
+        // Locked accounts cannot sign.
+        assert!(client
+            .send_transaction(Default::default(), request.clone())
+            .wait()
+            .is_err());
+
+        accounts
+            .unlock_account_temporarily(from, "secret".to_string())
+            .unwrap();
+        let hash = client
+            .send_transaction(Default::default(), request)
+            .wait()
+            .unwrap();
+        let receipt = blockchain
+            .get_txn_receipt_by_hash(hash.into())
+            .wait()
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.block_number, 1);
+    }
+}
@@ -0,0 +1,244 @@
+//! Evm rpc implementation.
+use std::{sync::Arc, time::Duration};
+
+use ethereum_types::{H256, U256};
+use jsonrpc_core::Result;
+use jsonrpc_macros::Trailing;
+use parity_rpc::v1::{
+    helpers::errors,
+    types::{Bytes, H160 as RpcH160, H256 as RpcH256, U256 as RpcU256},
+};
+
+use crate::{
+    blockchain::{Blockchain, MiningMode},
+    traits::{evm::MineOptions, Evm},
+    util::jsonrpc_error,
+};
+
+/// Parse a hex quantity of at most 256 bits, e.g., "0x1f".
+fn parse_quantity(name: &str, value: &str) -> Result<U256> {
+    let invalid = || {
+        errors::invalid_params(
+            name,
+            format!(
+                "expected a hex quantity of at most 256 bits, got {:?}",
+                value
+            ),
+        )
+    };
+    if !value.starts_with("0x") {
+        return Err(invalid());
+    }
+    let digits = &value[2..];
+    if digits.is_empty() || digits.len() > 64 {
+        return Err(invalid());
+    }
+
+    digits.chars().try_fold(U256::zero(), |acc, c| {
+        c.to_digit(16)
+            .map(|digit| (acc << 4) | U256::from(digit))
+            .ok_or_else(invalid)
+    })
+}
+
+/// Evm rpc implementation.
+pub struct EvmClient {
+    blockchain: Arc<Blockchain>,
+    max_mine_blocks: u64,
+}
+
+impl EvmClient {
+    /// Creates new EvmClient. A single `evm_mine` call may mine at most
+    /// `max_mine_blocks` blocks.
+    pub fn new(blockchain: Arc<Blockchain>, max_mine_blocks: u64) -> Self {
+        EvmClient {
+            blockchain,
+            max_mine_blocks,
+        }
+    }
+}
+
+impl Evm for EvmClient {
+    fn set_next_block_timestamp(&self, timestamp: u64) -> Result<bool> {
+        self.blockchain
+            .set_next_block_timestamp(timestamp)
+            .map_err(jsonrpc_error)?;
+        Ok(true)
+    }
+
+    fn mine(&self, options: Trailing<MineOptions>) -> Result<RpcU256> {
+        let options = options.unwrap_or_default();
+        let count = options.blocks.unwrap_or(1);
+        if count > self.max_mine_blocks {
+            return Err(errors::invalid_params(
+                "blocks",
+                format!(
+                    "at most {} blocks can be mined at once",
+                    self.max_mine_blocks
+                ),
+            ));
+        }
+
+        self.blockchain
+            .mine_blocks(count, options.interval)
+            .map(Into::into)
+            .map_err(jsonrpc_error)
+    }
+
+    fn set_block_number(&self, number: String) -> Result<RpcU256> {
+        let number = parse_quantity("number", &number)?;
+        if number > U256::from(u64::max_value()) {
+            return Err(errors::invalid_params(
+                "number",
+                "block number is too large",
+            ));
+        }
+        let number = self
+            .blockchain
+            .set_block_number(number.low_u64())
+            .map_err(jsonrpc_error)?;
+        Ok(number.into())
+    }
+
+    fn set_balance(&self, address: RpcH160, balance: String) -> Result<bool> {
+        let balance = parse_quantity("balance", &balance)?;
+        self.blockchain
+            .set_balance(address.into(), balance)
+            .map_err(jsonrpc_error)?;
+        Ok(true)
+    }
+
+    fn set_account_nonce(&self, address: RpcH160, nonce: String) -> Result<bool> {
+        let nonce = parse_quantity("nonce", &nonce)?;
+        self.blockchain
+            .set_nonce(address.into(), nonce)
+            .map_err(jsonrpc_error)?;
+        Ok(true)
+    }
+
+    fn set_code(&self, address: RpcH160, code: Bytes) -> Result<bool> {
+        self.blockchain
+            .set_code(address.into(), code.into())
+            .map_err(jsonrpc_error)?;
+        Ok(true)
+    }
+
+    fn set_storage_at(&self, address: RpcH160, slot: String, value: String) -> Result<bool> {
+        let slot = parse_quantity("slot", &slot)?;
+        let value = parse_quantity("value", &value)?;
+        self.blockchain
+            .set_storage(address.into(), H256::from(slot), H256::from(value))
+            .map_err(jsonrpc_error)?;
+        Ok(true)
+    }
+
+    fn set_interval_mining(&self, secs: u64) -> Result<bool> {
+        let mining_mode = match secs {
+            0 => MiningMode::Auto,
+            secs => MiningMode::Interval(Duration::from_secs(secs)),
+        };
+        self.blockchain.set_mining_mode(mining_mode);
+        Ok(true)
+    }
+
+    fn get_automine(&self) -> Result<bool> {
+        Ok(self.blockchain.mining_mode() == MiningMode::Auto)
+    }
+
+    fn get_interval_mining(&self) -> Result<u64> {
+        match self.blockchain.mining_mode() {
+            MiningMode::Interval(period) => Ok(period.as_secs()),
+            MiningMode::Auto | MiningMode::Manual => Ok(0),
+        }
+    }
+
+    fn drop_transaction(&self, txn_hash: RpcH256) -> Result<bool> {
+        Ok(self.blockchain.drop_transaction(txn_hash.into()))
+    }
+
+    fn reorg(&self, depth: u64, replacements: Trailing<u64>) -> Result<RpcU256> {
+        let replacements = replacements.unwrap_or_default();
+        if replacements > self.max_mine_blocks {
+            return Err(errors::invalid_params(
+                "replacements",
+                format!(
+                    "at most {} blocks can be mined at once",
+                    self.max_mine_blocks
+                ),
+            ));
+        }
+
+        self.blockchain
+            .reorg(depth, replacements)
+            .map(Into::into)
+            .map_err(jsonrpc_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use futures::Future;
+
+    use super::*;
+    use crate::{
+        blockchain::{BlockchainConfig, MiningLog},
+        test_support::{new_blockchain, test_config},
+    };
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse_quantity("value", "0x0").unwrap(), U256::zero());
+        assert_eq!(parse_quantity("value", "0x1f").unwrap(), U256::from(31));
+        let max = format!("0x{}", "f".repeat(64));
+        assert_eq!(parse_quantity("value", &max).unwrap(), U256::max_value());
+
+        // Too large, malformed or not hex prefixed.
+        let too_large = format!("0x1{}", "0".repeat(64));
+        assert!(parse_quantity("value", &too_large).is_err());
+        assert!(parse_quantity("value", "0x").is_err());
+        assert!(parse_quantity("value", "0xg").is_err());
+        assert!(parse_quantity("value", "10").is_err());
+    }
+
+    #[test]
+    fn test_set_interval_mining() {
+        let blockchain = Arc::new(new_blockchain(BlockchainConfig {
+            mining_log: MiningLog::Quiet,
+            ..test_config()
+        }));
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.spawn(Blockchain::interval_mining(blockchain.clone()));
+        let client = EvmClient::new(blockchain.clone(), 1);
+
+        assert!(client.get_automine().unwrap());
+        assert_eq!(client.get_interval_mining().unwrap(), 0);
+
+        // Empty blocks are mined every period.
+        assert!(client.set_interval_mining(1).unwrap());
+        assert!(!client.get_automine().unwrap());
+        assert_eq!(client.get_interval_mining().unwrap(), 1);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut last = blockchain.best_block_number();
+        while last < 2 {
+            assert!(Instant::now() < deadline, "only {} blocks mined", last);
+            thread::sleep(Duration::from_millis(10));
+            let number = blockchain.best_block_number();
+            assert!(number >= last);
+            last = number;
+        }
+
+        // Back to auto mining, no more empty blocks. A tick already in
+        // flight may still mine one.
+        assert!(client.set_interval_mining(0).unwrap());
+        assert!(client.get_automine().unwrap());
+        thread::sleep(Duration::from_millis(100));
+        let stopped = blockchain.best_block_number();
+        assert!(stopped >= last);
+        thread::sleep(Duration::from_millis(1_500));
+        assert_eq!(blockchain.best_block_number(), stopped);
+
+        runtime.shutdown_now().wait().unwrap();
+    }
+}
@@ -19,18 +19,32 @@
 //! This doesn't re-implement all of the RPC APIs, just those which aren't
 //! significantly generic to be reused.
 
+// short for "try_boxfuture"
+// unwrap a result, returning a BoxFuture<_, Err> on failure.
+macro_rules! try_bf {
+    ($res:expr) => {
+        match $res {
+            Ok(val) => val,
+            Err(e) => return Box::new(::jsonrpc_core::futures::future::err(e.into())),
+        }
+    };
+}
+
+pub mod debug;
 pub mod eth;
 pub mod eth_filter;
 #[cfg(feature = "pubsub")]
 pub mod eth_pubsub;
 pub mod eth_signing;
+pub mod evm;
 pub mod net;
 pub mod oasis;
+pub mod personal;
 pub mod web3;
 
 #[cfg(feature = "pubsub")]
 pub use self::eth_pubsub::EthPubSubClient;
 pub use self::{
-    eth::EthClient, eth_filter::EthFilterClient, eth_signing::EthSigningClient, net::NetClient,
-    oasis::OasisClient, web3::Web3Client,
+    debug::DebugClient, eth::EthClient, eth_filter::EthFilterClient, eth_signing::EthSigningClient,
+    evm::EvmClient, net::NetClient, oasis::OasisClient, personal::PersonalClient, web3::Web3Client,
 };
@@ -1,22 +1,44 @@
 use std::sync::Arc;
 
+use ekiden_crypto::mrae::nonce::{Nonce, NONCE_SIZE};
 use ekiden_keymanager::{client::MockClient, ContractId};
 use ethereum_types::Address;
+use failure::format_err;
 use futures::prelude::*;
 use hash::keccak;
-use jsonrpc_core::{futures::future, BoxFuture};
+use jsonrpc_core::{futures::future, BoxFuture, Result};
 use jsonrpc_macros::Trailing;
 use parity_rpc::v1::{
+    helpers::{errors, fake_sign},
     metadata::Metadata,
-    types::{BlockNumber, Bytes, H160 as RpcH160},
+    types::{BlockNumber, Bytes, CallRequest, H160 as RpcH160, H256 as RpcH256, U256 as RpcU256},
 };
+use rand;
 
 use crate::{
     blockchain::Blockchain,
-    traits::oasis::{Oasis, RpcExecutionPayload, RpcPublicKeyPayload},
-    util::{block_number_to_id, execution_error, jsonrpc_error},
+    confidential::crypto,
+    traits::{
+        oasis::{
+            Oasis, RpcContractCreation, RpcExecutionPayload, RpcKeyManagerPublicKeys,
+            RpcPublicKeyPayload, RpcReceiptWithFailureReason,
+        },
+        OasisDebug,
+    },
+    util::{
+        block_number_to_id, block_number_to_state_id, execution_error, jsonrpc_error,
+        transaction_error,
+    },
 };
 
+/// Key manager id of the key pair used to encrypt confidential calls of a
+/// caller, distinct from the keys of a contract at the same address.
+fn caller_key_id(caller: Address) -> ContractId {
+    let mut buffer = b"oasis_confidentialCall".to_vec();
+    buffer.extend_from_slice(&caller);
+    ContractId::from(&keccak(&buffer)[..])
+}
+
 /// Eth rpc implementation
 pub struct OasisClient {
     blockchain: Arc<Blockchain>,
@@ -36,23 +58,32 @@ impl OasisClient {
 impl Oasis for OasisClient {
     type Metadata = Metadata;
 
-    fn public_key(&self, contract: Address) -> BoxFuture<Option<RpcPublicKeyPayload>> {
+    fn public_key(&self, contract: Address) -> BoxFuture<RpcPublicKeyPayload> {
         let contract_id = ContractId::from(&keccak(contract.to_vec())[..]);
 
-        Box::new(future::ok(self.km_client.get_public_key(contract_id).map(
-            |pk_payload| RpcPublicKeyPayload {
-                public_key: Bytes::from(pk_payload.key.as_ref().to_vec()),
-                checksum: Bytes::from(pk_payload.checksum),
-                signature: Bytes::from(pk_payload.signature.as_ref().to_vec()),
-            },
-        )))
+        Box::new(future::done(
+            self.km_client
+                .get_public_key(contract_id)
+                .map(|pk_payload| RpcPublicKeyPayload {
+                    public_key: Bytes::from(pk_payload.key.as_ref().to_vec()),
+                    checksum: Bytes::from(pk_payload.checksum),
+                    timestamp: pk_payload.timestamp,
+                    signature: Bytes::from(pk_payload.signature.as_ref().to_vec()),
+                })
+                .ok_or_else(|| {
+                    jsonrpc_error(format_err!(
+                        "failed to derive keys for contract {:?}",
+                        contract
+                    ))
+                }),
+        ))
     }
 
     fn get_expiry(&self, address: RpcH160, num: Trailing<BlockNumber>) -> BoxFuture<u64> {
         let address: Address = RpcH160::into(address);
         let num = num.unwrap_or_default();
 
-        let state = match self.blockchain.state(block_number_to_id(num)) {
+        let state = match self.blockchain.state(block_number_to_state_id(num)) {
             Ok(state) => state,
             Err(err) => return Box::new(future::err(jsonrpc_error(err))),
         };
@@ -69,14 +100,432 @@ impl Oasis for OasisClient {
         Box::new(
             self.blockchain
                 .send_raw_transaction(raw.into())
-                .map_err(execution_error)
-                .then(move |maybe_result| {
-                    maybe_result.map(|(hash, result)| RpcExecutionPayload {
+                .map_err(transaction_error)
+                .and_then(|(hash, result)| match result {
+                    Some(result) => Ok(RpcExecutionPayload {
                         transaction_hash: hash.into(),
                         status_code: (result.status_code as u64).into(),
                         output: result.output.into(),
-                    })
+                    }),
+                    // Pending or queued, so there is no result yet.
+                    None => Err(execution_error(format!(
+                        "transaction {:?} has not been mined yet",
+                        hash
+                    ))),
                 }),
         )
     }
+
+    fn send_raw_transactions(&self, raw_txns: Vec<Bytes>) -> BoxFuture<Vec<RpcH256>> {
+        let raw_txns = raw_txns.into_iter().map(Into::into).collect();
+        Box::new(future::done(
+            self.blockchain
+                .send_raw_transactions(raw_txns)
+                .map(|hashes| hashes.into_iter().map(Into::into).collect())
+                .map_err(transaction_error),
+        ))
+    }
+
+    fn transaction_receipt(&self, hash: RpcH256) -> BoxFuture<Option<RpcReceiptWithFailureReason>> {
+        let hash = hash.into();
+        let failure_reason = self.blockchain.get_txn_failure_reason(hash);
+
+        Box::new(
+            self.blockchain
+                .get_txn_receipt_by_hash(hash)
+                .map(|receipt| {
+                    receipt.map(|receipt| RpcReceiptWithFailureReason {
+                        receipt: receipt.into(),
+                        failure_reason,
+                    })
+                })
+                .map_err(jsonrpc_error),
+        )
+    }
+
+    fn pending_block_number(&self) -> Result<RpcU256> {
+        Ok((self.blockchain.best_block_number() + 1).into())
+    }
+
+    fn contract_creation_tx(&self, address: RpcH160) -> Result<Option<RpcContractCreation>> {
+        Ok(self
+            .blockchain
+            .get_contract_creation(address.into())
+            .map(|(hash, number)| RpcContractCreation {
+                transaction_hash: hash.into(),
+                block_number: number.into(),
+            }))
+    }
+
+    fn block_gas_ratio(&self, num: BlockNumber) -> BoxFuture<Option<f64>> {
+        Box::new(
+            self.blockchain
+                .get_block(block_number_to_id(num))
+                .map(|block| block.map(|block| block.gas_used_ratio()))
+                .map_err(jsonrpc_error),
+        )
+    }
+
+    fn key_manager_public_keys(&self) -> Result<RpcKeyManagerPublicKeys> {
+        let signing_key = self.km_client.signing_public_key();
+        Ok(RpcKeyManagerPublicKeys {
+            signing_key: Bytes::from(signing_key.as_ref().to_vec()),
+            signature: None,
+        })
+    }
+}
+
+impl OasisDebug for OasisClient {
+    /// The call data is encrypted to the contract's key, as a client would,
+    /// with a key pair the key manager derives for the caller, which then
+    /// decrypts the output. Meant for tests and debugging, so it is only
+    /// registered along with the `debug` API.
+    fn confidential_call(
+        &self,
+        mut request: CallRequest,
+        num: Trailing<BlockNumber>,
+    ) -> BoxFuture<Bytes> {
+        let num = num.unwrap_or_default();
+        let id = block_number_to_state_id(num);
+
+        let contract: Address = match request.to {
+            Some(to) => to.into(),
+            None => {
+                return Box::new(future::err(errors::invalid_params(
+                    "to",
+                    "missing contract address",
+                )));
+            }
+        };
+        let is_confidential = self.blockchain.state(id).and_then(|state| {
+            state
+                .is_confidential(&contract)
+                .map_err(|err| format_err!("{}", err))
+        });
+        match is_confidential {
+            Ok(true) => {}
+            Ok(false) => {
+                return Box::new(future::err(jsonrpc_error(format_err!(
+                    "{:?} is not a confidential contract",
+                    contract
+                ))));
+            }
+            Err(err) => return Box::new(future::err(jsonrpc_error(err))),
+        }
+
+        // Encrypt the call data to the contract, as a client would.
+        let caller: Address = request.from.map(Into::into).unwrap_or_default();
+        let caller_keys = self.km_client.get_or_create_keys(caller_key_id(caller));
+        let contract_id = ContractId::from(&keccak(contract.to_vec())[..]);
+        let contract_keys = self.km_client.get_or_create_keys(contract_id);
+        let data = try_bf!(crypto::encrypt(
+            request.data.take().map(Bytes::into_vec).unwrap_or_default(),
+            Nonce::new(rand::random::<[u8; NONCE_SIZE]>()),
+            contract_keys.input_keypair.get_pk(),
+            caller_keys.input_keypair.get_pk(),
+            caller_keys.input_keypair.get_sk(),
+            vec![],
+        )
+        .map_err(jsonrpc_error));
+        request.data = Some(data.into());
+        if request.gas.is_none() {
+            request.gas = Some(self.blockchain.call_gas_cap().into());
+        }
+        let signed = try_bf!(fake_sign::sign_call(request.into(), false));
+
+        let caller_secret_key = caller_keys.input_keypair.get_sk();
+        Box::new(
+            self.blockchain
+                .simulate_confidential_transaction(signed, id)
+                .map_err(errors::call)
+                .and_then(move |executed| match executed.exception {
+                    Some(ref exception) => Err(errors::vm(exception, &executed.output)),
+                    None if executed.output.is_empty() => Ok(vec![]),
+                    None => crypto::decrypt(Some(executed.output), caller_secret_key)
+                        .map(|decryption| decryption.plaintext)
+                        .map_err(jsonrpc_error),
+                })
+                .map(Bytes::from),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ekiden_crypto::signature::{PublicKey as SigningPublicKey, Signature};
+    use ekiden_keymanager::{client::MockClient, PublicKey, SignedPublicKey};
+    use ethcore::{
+        transaction::{Action, Transaction},
+        types::ids::BlockId,
+    };
+    use ethereum_types::U256;
+    use serde_json;
+
+    use super::*;
+    use crate::{
+        blockchain::BLOCK_GAS_LIMIT,
+        test_support::{fake_txn, new_blockchain_with_km, test_config},
+    };
+
+    fn new_client(km_client: Arc<MockClient>) -> OasisClient {
+        let blockchain = new_blockchain_with_km(test_config(), km_client.clone());
+        OasisClient::new(Arc::new(blockchain), km_client)
+    }
+
+    #[test]
+    fn test_public_key_matches_contract_keys() {
+        let km_client = Arc::new(MockClient::new());
+        let client = new_client(km_client.clone());
+
+        let contract = Address::from(1);
+        let payload = client.public_key(contract).wait().unwrap();
+        let contract_id = ContractId::from(&keccak(contract.to_vec())[..]);
+        let keys = km_client.get_or_create_keys(contract_id);
+        assert_eq!(
+            payload.public_key,
+            Bytes::from(keys.input_keypair.get_pk().as_ref().to_vec())
+        );
+
+        // Keys, and so their timestamp, are only created once.
+        let again = client.public_key(contract).wait().unwrap();
+        assert_eq!(again.public_key, payload.public_key);
+        assert_eq!(again.timestamp, payload.timestamp);
+    }
+
+    #[test]
+    fn test_key_manager_public_keys() {
+        let client = new_client(Arc::new(MockClient::new()));
+        let keys = client.key_manager_public_keys().unwrap();
+        assert_eq!(keys.signing_key.0.len(), 32);
+        assert!(keys.signature.is_none());
+
+        // The keys are stable across calls and restarts.
+        let again = client.key_manager_public_keys().unwrap();
+        assert_eq!(again.signing_key, keys.signing_key);
+        let restarted = new_client(Arc::new(MockClient::new()));
+        let restarted = restarted.key_manager_public_keys().unwrap();
+        assert_eq!(restarted.signing_key, keys.signing_key);
+
+        // They authenticate the contract public keys.
+        let payload = client.public_key(Address::from(1)).wait().unwrap();
+        let signed = SignedPublicKey {
+            key: PublicKey::from(&payload.public_key.0[..]),
+            checksum: payload.checksum.0,
+            timestamp: payload.timestamp,
+            signature: Signature::from(&payload.signature.0[..]),
+        };
+        let signer = SigningPublicKey::from(&keys.signing_key.0[..]);
+        signed.verify(&signer).unwrap();
+        let other = SigningPublicKey::from(&[1u8; 32][..]);
+        assert!(signed.verify(&other).is_err());
+    }
+
+    #[test]
+    fn test_transaction_receipt_failure_reason() {
+        let client = new_client(Arc::new(MockClient::new()));
+        let sender = Address::from(1);
+        let contracts = vec![
+            // Succeeds: STOP.
+            (Address::from(0x1234), vec![0x00], None),
+            // REVERT(0, 0).
+            (
+                Address::from(0x1235),
+                vec![0x60, 0x00, 0x60, 0x00, 0xfd],
+                Some("reverted"),
+            ),
+            // Loops forever: JUMPDEST, PUSH1 0, JUMP.
+            (
+                Address::from(0x1236),
+                vec![0x5b, 0x60, 0x00, 0x56],
+                Some("outOfGas"),
+            ),
+        ];
+
+        for (nonce, (contract, runtime, reason)) in contracts.into_iter().enumerate() {
+            client.blockchain.set_code(contract, runtime).unwrap();
+            let txn = Transaction {
+                nonce: nonce.into(),
+                gas_price: U256::zero(),
+                gas: 100_000.into(),
+                action: Action::Call(contract),
+                value: U256::zero(),
+                data: vec![],
+            }
+            .fake_sign(sender);
+            let (hash, result) = client.blockchain.send_transaction(txn).unwrap();
+            let result = result.unwrap();
+            assert_eq!(result.failure_reason.as_ref().map(String::as_str), reason);
+            assert_eq!(result.status_code, reason.is_none() as u8);
+
+            let receipt = client
+                .transaction_receipt(hash.into())
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert_eq!(receipt.failure_reason.as_ref().map(String::as_str), reason);
+            let receipt = serde_json::to_value(receipt).unwrap();
+            assert_eq!(receipt["status"], format!("{:#x}", reason.is_none() as u8));
+        }
+
+        assert!(client
+            .transaction_receipt(RpcH256::from(1))
+            .wait()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_confidential_call_requires_confidential_contract() {
+        let client = new_client(Arc::new(MockClient::new()));
+        // Echo the call data: CALLDATASIZE, PUSH1 0, PUSH1 0, CALLDATACOPY,
+        // CALLDATASIZE, PUSH1 0, RETURN.
+        let contract = Address::from(0x1234);
+        let runtime = vec![0x36, 0x60, 0x00, 0x60, 0x00, 0x37, 0x36, 0x60, 0x00, 0xf3];
+        client.blockchain.set_code(contract, runtime).unwrap();
+
+        let call = |request: &str| {
+            let request: CallRequest = serde_json::from_str(request).unwrap();
+            client.confidential_call(request, None.into()).wait()
+        };
+        for address in vec![contract, Address::from(1)] {
+            let err = call(&format!(r#"{{"to": "{:#x}", "data": "0x01"}}"#, address)).unwrap_err();
+            assert!(
+                err.message.contains("is not a confidential contract"),
+                "{}",
+                err.message
+            );
+        }
+        // A contract address is required.
+        assert!(call(r#"{"data": "0x01"}"#).is_err());
+    }
+
+    /// Prefixes contract code with an Oasis contract header.
+    fn with_oasis_header(header: &str, code: Vec<u8>) -> Vec<u8> {
+        // Prefix, version and length of the JSON header, both big endian.
+        let mut prefixed = b"\0sis".to_vec();
+        prefixed.extend_from_slice(&[0, 1]);
+        prefixed.extend_from_slice(&[(header.len() >> 8) as u8, header.len() as u8]);
+        prefixed.extend_from_slice(header.as_bytes());
+        prefixed.extend(code);
+        prefixed
+    }
+
+    #[test]
+    fn test_confidential_call() {
+        let client = new_client(Arc::new(MockClient::new()));
+        // Echo the call data: CALLDATASIZE, PUSH1 0, PUSH1 0, CALLDATACOPY,
+        // CALLDATASIZE, PUSH1 0, RETURN.
+        let contract = Address::from(0x1234);
+        let runtime = vec![0x36, 0x60, 0x00, 0x60, 0x00, 0x37, 0x36, 0x60, 0x00, 0xf3];
+        client
+            .blockchain
+            .set_code(
+                contract,
+                with_oasis_header(r#"{"confidential":true}"#, runtime),
+            )
+            .unwrap();
+        assert!(client
+            .blockchain
+            .state(BlockId::Latest)
+            .unwrap()
+            .is_confidential(&contract)
+            .unwrap());
+
+        let input = "0x0102030405060708090a";
+        let request: CallRequest = serde_json::from_str(&format!(
+            r#"{{"from": "{:#x}", "to": "{:#x}", "data": "{}"}}"#,
+            Address::from(1),
+            contract,
+            input
+        ))
+        .unwrap();
+        let output = client
+            .confidential_call(request, BlockNumber::Latest.into())
+            .wait()
+            .unwrap();
+        assert_eq!(output, Bytes::from(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]));
+    }
+
+    #[test]
+    fn test_pending_block_number() {
+        let client = new_client(Arc::new(MockClient::new()));
+        assert_eq!(client.pending_block_number().unwrap(), RpcU256::from(1));
+        client.blockchain.mine_pending_block();
+        assert_eq!(client.pending_block_number().unwrap(), RpcU256::from(2));
+    }
+
+    #[test]
+    fn test_contract_creation_tx() {
+        let client = new_client(Arc::new(MockClient::new()));
+        let sender = Address::from(1);
+
+        // Deploy a contract whose runtime code is STOP.
+        let init_code = vec![
+            0x60, 0x01, 0x80, 0x60, 0x0b, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3, 0x00,
+        ];
+        let txn = fake_txn(sender, 0, Action::Create, init_code);
+        let (hash, _) = client.blockchain.send_transaction(txn).unwrap();
+        let contract = client
+            .blockchain
+            .get_txn_receipt_by_hash(hash)
+            .wait()
+            .unwrap()
+            .unwrap()
+            .contract_address
+            .unwrap();
+
+        let creation = client
+            .contract_creation_tx(contract.into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(creation.transaction_hash, hash.into());
+        assert_eq!(creation.block_number, RpcU256::from(1));
+
+        // Neither externally owned accounts nor contracts not deployed by a
+        // transaction have a creation transaction.
+        let genesis_contract = Address::from(0x1234);
+        client
+            .blockchain
+            .set_code(genesis_contract, vec![0x00])
+            .unwrap();
+        for address in vec![sender, genesis_contract] {
+            assert!(client
+                .contract_creation_tx(address.into())
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_block_gas_ratio() {
+        let client = new_client(Arc::new(MockClient::new()));
+        let ratio = |number: u64| client.block_gas_ratio(BlockNumber::Num(number)).wait();
+        assert_eq!(ratio(0).unwrap(), Some(0.0));
+
+        // Burn all the gas of the transaction: JUMPDEST, PUSH1 0, JUMP.
+        let contract = Address::from(0x1234);
+        client
+            .blockchain
+            .set_code(contract, vec![0x5b, 0x60, 0x00, 0x56])
+            .unwrap();
+        let txn = Transaction {
+            nonce: U256::zero(),
+            gas_price: U256::zero(),
+            gas: 4_000_000.into(),
+            action: Action::Call(contract),
+            value: U256::zero(),
+            data: vec![],
+        }
+        .fake_sign(Address::from(1));
+        client.blockchain.send_transaction(txn).unwrap();
+
+        let ratio = ratio(1).unwrap().unwrap();
+        assert!(ratio > 0.0 && ratio < 1.0, "{}", ratio);
+        assert_eq!(ratio, 4_000_000.0 / BLOCK_GAS_LIMIT as f64);
+
+        assert_eq!(
+            client.block_gas_ratio(BlockNumber::Num(2)).wait().unwrap(),
+            None
+        );
+    }
 }
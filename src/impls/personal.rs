@@ -0,0 +1,82 @@
+//! Personal rpc implementation.
+use std::sync::Arc;
+
+use ethcore::account_provider::AccountProvider;
+use ethereum_types::Address;
+use jsonrpc_core::Result;
+use jsonrpc_macros::Trailing;
+use parity_rpc::v1::{helpers::errors, types::H160 as RpcH160};
+
+use crate::traits::Personal;
+
+/// Default unlock duration (in seconds).
+const DEFAULT_UNLOCK_DURATION_SECS: u64 = 300;
+
+/// Personal rpc implementation.
+pub struct PersonalClient {
+    accounts: Arc<AccountProvider>,
+}
+
+impl PersonalClient {
+    /// Creates new PersonalClient.
+    pub fn new(accounts: Arc<AccountProvider>) -> Self {
+        PersonalClient { accounts }
+    }
+}
+
+impl Personal for PersonalClient {
+    fn new_account(&self, passphrase: String) -> Result<RpcH160> {
+        self.accounts
+            .new_account(&passphrase)
+            .map(Into::into)
+            .map_err(|err| errors::account("Could not create account.", err))
+    }
+
+    fn unlock_account(
+        &self,
+        address: RpcH160,
+        passphrase: String,
+        duration: Trailing<u64>,
+    ) -> Result<bool> {
+        let address: Address = address.into();
+        let duration: Option<u64> = duration.into();
+
+        let result = match duration.unwrap_or(DEFAULT_UNLOCK_DURATION_SECS) {
+            0 => self
+                .accounts
+                .unlock_account_permanently(address, passphrase),
+            secs => match secs.checked_mul(1000) {
+                Some(ms) if ms <= u32::max_value() as u64 => self
+                    .accounts
+                    .unlock_account_timed(address, passphrase, ms as u32),
+                _ => return Err(errors::invalid_params("duration", "too long")),
+            },
+        };
+
+        result
+            .map(|_| true)
+            .map_err(|err| errors::account("Unable to unlock the account.", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_and_unlock_account() {
+        let accounts = Arc::new(AccountProvider::transient_provider());
+        let client = PersonalClient::new(accounts.clone());
+
+        let address = client.new_account("secret".to_string()).unwrap();
+        assert_eq!(accounts.accounts().unwrap(), vec![address.clone().into()]);
+
+        assert!(client
+            .unlock_account(address.clone(), "wrong".to_string(), None.into())
+            .is_err());
+        assert!(client
+            .unlock_account(address.clone(), "secret".to_string(), None.into())
+            .unwrap());
+        assert!(accounts.is_unlocked(&address.into()));
+    }
+}
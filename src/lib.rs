@@ -15,20 +15,26 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 //! Oasis local chain.
+#![cfg_attr(test, feature(test))]
 
+extern crate bip39;
 extern crate clap;
 extern crate futures;
 extern crate lazy_static;
+extern crate num_cpus;
 #[macro_use]
 extern crate log;
 extern crate parking_lot;
+extern crate rand;
 #[macro_use]
 extern crate serde_derive;
 extern crate jsonrpc_core;
+extern crate serde_json;
 #[macro_use]
 extern crate jsonrpc_macros;
 extern crate ethcore;
 extern crate ethereum_types;
+extern crate ethkey;
 extern crate failure;
 extern crate hashdb;
 extern crate jsonrpc_http_server;
@@ -37,16 +43,23 @@ extern crate jsonrpc_ws_server;
 extern crate keccak_hash as hash;
 extern crate parity_reactor;
 extern crate parity_rpc;
+extern crate rlp;
 extern crate tokio;
 extern crate tokio_threadpool;
+extern crate triehash;
 extern crate zeroize;
 
 extern crate ekiden_crypto;
 extern crate ekiden_keymanager;
 
+#[cfg(test)]
+extern crate test;
+
+mod access_list;
 mod blockchain;
 mod confidential;
 mod genesis;
+mod hd_wallet;
 mod impls;
 mod informant;
 mod middleware;
@@ -57,10 +70,12 @@ mod rpc_apis;
 mod run;
 mod servers;
 mod storage;
+#[cfg(test)]
+mod test_support;
 mod traits;
 pub mod util;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use clap::ArgMatches;
 use ethereum_types::U256;
@@ -69,8 +84,15 @@ use failure::Fallible;
 use ekiden_keymanager::client::MockClient;
 
 pub use self::{
-    blockchain::{BLOCK_GAS_LIMIT, MIN_GAS_PRICE_GWEI},
-    run::RunningGateway,
+    blockchain::{
+        default_simulator_threads, BlockTime, BlockchainConfig, MiningLog, MiningMode,
+        BLOCK_GAS_LIMIT, CALL_GAS_CAP, CALL_TIMEOUT, MAX_LOGS_BLOCK_RANGE, MAX_MINE_BLOCKS,
+        MAX_TRANSACTION_SIZE, MIN_GAS_PRICE_GWEI,
+    },
+    hd_wallet::{DEFAULT_ACCOUNT_BALANCE, DEFAULT_ACCOUNT_COUNT, DEFAULT_MNEMONIC},
+    middleware::SLOW_REQUEST_THRESHOLD,
+    rpc_apis::{Api, ApiSet},
+    run::{InProcessGateway, RunningGateway},
 };
 
 pub fn start(
@@ -81,8 +103,15 @@ pub fn start(
     num_threads: usize,
     ws_port: u16,
     ws_max_connections: usize,
-    gas_price: U256,
-    block_gas_limit: U256,
+    slow_request_threshold: Duration,
+    apis: ApiSet,
+    config: BlockchainConfig,
+    max_mine_blocks: u64,
+    chain: &str,
+    genesis_alloc: Option<&str>,
+    mnemonic: &str,
+    account_count: usize,
+    account_balance: Option<U256>,
 ) -> Fallible<RunningGateway> {
     let km_client = Arc::new(MockClient::new());
 
@@ -94,7 +123,14 @@ pub fn start(
         num_threads,
         ws_port,
         ws_max_connections,
-        gas_price,
-        block_gas_limit,
+        slow_request_threshold,
+        apis,
+        config,
+        max_mine_blocks,
+        chain,
+        genesis_alloc,
+        mnemonic,
+        account_count,
+        account_balance,
     )
 }
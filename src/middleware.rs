@@ -1,10 +1,19 @@
 //! RPC Middleware
 
 use informant::RpcStats;
-use jsonrpc_core as rpc;
+use jsonrpc_core::{self as rpc, futures::Future};
 use jsonrpc_ws_server as ws;
 use parity_rpc::{informant::ActivityNotifier, v1::types::H256, Metadata, Origin};
-use std::{sync::Arc, vec::Vec};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+    vec::Vec,
+};
+
+/// Default latency above which a request is logged as slow.
+pub const SLOW_REQUEST_THRESHOLD: Duration = Duration::from_secs(1);
 
 /// Custom JSON-RPC error codes
 const ERROR_BATCH_SIZE: i64 = -32099;
@@ -83,17 +92,84 @@ fn generate_error_response(request: rpc::Request, gen: &dyn ErrGen) -> rpc::Futu
     })))
 }
 
-/// RPC middleware that enforces batch size limits.
+/// Call count and latency of an RPC method.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MethodStats {
+    pub count: u64,
+    pub total_millis: u64,
+    pub max_millis: u64,
+}
+
+/// Per-method RPC request metrics.
+///
+/// The calls of a batch are timed as a whole.
+pub struct RequestMetrics {
+    slow_threshold: Duration,
+    methods: Mutex<HashMap<String, MethodStats>>,
+}
+
+impl RequestMetrics {
+    /// Create new metrics, logging requests slower than `slow_threshold`.
+    pub fn new(slow_threshold: Duration) -> Self {
+        RequestMetrics {
+            slow_threshold,
+            methods: Default::default(),
+        }
+    }
+
+    /// Record a request of the given methods which took `elapsed`.
+    fn record(&self, methods: &[String], elapsed: Duration) {
+        let millis = elapsed.as_secs() * 1_000 + u64::from(elapsed.subsec_millis());
+        if elapsed > self.slow_threshold {
+            warn!(
+                "Slow JSON-RPC request: {} took {} ms",
+                methods.join(", "),
+                millis
+            );
+        }
+
+        let mut stats = self.methods.lock();
+        for method in methods {
+            let stats = stats.entry(method.clone()).or_default();
+            stats.count += 1;
+            stats.total_millis += millis;
+            stats.max_millis = stats.max_millis.max(millis);
+        }
+    }
+
+    /// Snapshot of the metrics of each called method.
+    pub fn snapshot(&self) -> HashMap<String, MethodStats> {
+        self.methods.lock().clone()
+    }
+}
+
+/// Names of the methods called by a request.
+fn request_methods(request: &rpc::Request) -> Vec<String> {
+    let call_method = |call: &rpc::Call| match call {
+        rpc::Call::MethodCall(method) => Some(method.method.clone()),
+        rpc::Call::Notification(notification) => Some(notification.method.clone()),
+        rpc::Call::Invalid(_) => None,
+    };
+    match request {
+        rpc::Request::Single(call) => call_method(call).into_iter().collect(),
+        rpc::Request::Batch(calls) => calls.iter().filter_map(call_method).collect(),
+    }
+}
+
+/// RPC middleware that enforces batch size limits and records request
+/// metrics.
 pub struct Middleware<T: ActivityNotifier> {
     notifier: T,
     max_batch_size: usize,
+    metrics: Arc<RequestMetrics>,
 }
 
 impl<T: ActivityNotifier> Middleware<T> {
-    pub fn new(notifier: T, max_batch_size: usize) -> Self {
+    pub fn new(notifier: T, max_batch_size: usize, metrics: Arc<RequestMetrics>) -> Self {
         Middleware {
             notifier,
             max_batch_size,
+            metrics,
         }
     }
 }
@@ -121,7 +197,14 @@ impl<M: rpc::Metadata, T: ActivityNotifier> rpc::Middleware<M> for Middleware<T>
             }
         }
 
-        Box::new(process(request, meta))
+        // Only observe the request, passing its response through unchanged.
+        let methods = request_methods(&request);
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+        Box::new(process(request, meta).then(move |response| {
+            metrics.record(&methods, start.elapsed());
+            response
+        }))
     }
 }
 
@@ -315,7 +398,11 @@ mod tests {
         use jsonrpc_core::Middleware as mw;
 
         // Middleware that accepts a max batch size of 1 request
-        let middleware = Middleware::new(TestNotifier {}, 1);
+        let middleware = Middleware::new(
+            TestNotifier {},
+            1,
+            Arc::new(RequestMetrics::new(SLOW_REQUEST_THRESHOLD)),
+        );
 
         let batch_1 = rpc::Request::Batch(vec![rpc::Call::MethodCall(rpc::MethodCall {
             jsonrpc: Some(rpc::Version::V2),
@@ -373,4 +460,47 @@ mod tests {
             _ => assert!(false, "Did not enforce batch size limit in batch"),
         };
     }
+
+    #[test]
+    fn should_record_request_metrics() {
+        let metrics = Arc::new(RequestMetrics::new(Duration::from_millis(0)));
+        let middleware = Middleware::new(TestNotifier {}, 10, metrics.clone());
+        let output = rpc::Output::from(Ok(rpc::Value::from(3)), rpc::Id::Num(1), None);
+
+        let response = middleware
+            .on_request(make_request(1), (), |_request, _meta| {
+                Box::new(rpc::futures::finished(Some(rpc::Response::Single(
+                    output.clone(),
+                ))))
+            })
+            .wait()
+            .unwrap();
+        // The response passes through unchanged.
+        assert_eq!(response, Some(rpc::Response::Single(output)));
+
+        let batch = rpc::Request::Batch(vec![
+            match make_request(2) {
+                rpc::Request::Single(call) => call,
+                _ => unreachable!(),
+            },
+            rpc::Call::Notification(rpc::Notification {
+                jsonrpc: Some(rpc::Version::V2),
+                method: "other".to_owned(),
+                params: None,
+            }),
+            rpc::Call::Invalid(rpc::Id::Num(3)),
+        ]);
+        middleware
+            .on_request(batch, (), |_request, _meta| {
+                Box::new(rpc::futures::finished(None))
+            })
+            .wait()
+            .unwrap();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot["test"].count, 2);
+        assert_eq!(snapshot["other"].count, 1);
+        assert!(snapshot["test"].max_millis <= snapshot["test"].total_millis);
+    }
 }
@@ -1,8 +1,12 @@
 //! Common parity helpers.
 use std::sync::Arc;
 
-use ethcore::{self, state::Account};
-use ethereum_types::{Address, H256};
+use ethcore::{
+    self,
+    state::{backend::Backend, Account, CleanupMode, State},
+};
+use ethereum_types::{Address, H256, U256};
+use failure::{format_err, Fallible};
 use hashdb::HashDB;
 
 /// Null backend for parity state.
@@ -43,3 +47,101 @@ impl ethcore::state::backend::Backend for NullBackend {
         false
     }
 }
+
+/// Largest nonce increase `StateExt::set_nonce` makes for an account with
+/// code.
+pub const MAX_CONTRACT_NONCE_INCREASE: u64 = 1 << 16;
+
+/// Direct modifications of parity state, bypassing transaction execution.
+///
+/// Changes only live in the state cache until the state is committed.
+pub trait StateExt {
+    /// Set the balance of an account.
+    fn set_balance(&mut self, address: &Address, balance: &U256) -> Fallible<()>;
+
+    /// Set the nonce of an account.
+    ///
+    /// The nonce must fit in 64 bits. The nonce of an account with code can
+    /// only be increased, by at most `MAX_CONTRACT_NONCE_INCREASE` at a time.
+    fn set_nonce(&mut self, address: &Address, nonce: &U256) -> Fallible<()>;
+
+    /// Replace the code of an account.
+    fn set_code(&mut self, address: &Address, code: Vec<u8>) -> Fallible<()>;
+
+    /// Clear the storage of an account, keeping its balance, nonce and code.
+    fn clear_storage(&mut self, address: &Address) -> Fallible<()>;
+}
+
+impl<B: Backend> StateExt for State<B> {
+    fn set_balance(&mut self, address: &Address, balance: &U256) -> Fallible<()> {
+        let current = self.balance(address)?;
+        if *balance > current {
+            self.add_balance(address, &(*balance - current), CleanupMode::NoEmpty)?;
+        } else if *balance < current {
+            self.sub_balance(address, &(current - *balance), &mut CleanupMode::NoEmpty)?;
+        }
+        Ok(())
+    }
+
+    fn set_nonce(&mut self, address: &Address, nonce: &U256) -> Fallible<()> {
+        if *nonce > U256::from(u64::max_value()) {
+            return Err(format_err!("nonce must fit in 64 bits"));
+        }
+        let start_nonce = *self.account_start_nonce();
+        if *nonce < start_nonce {
+            return Err(format_err!(
+                "nonce cannot be below the account start nonce ({})",
+                start_nonce
+            ));
+        }
+
+        let has_code = self.code(address)?.map_or(false, |code| !code.is_empty());
+        if !has_code {
+            // Without code there is no contract storage to keep, so the
+            // account can be replaced with one holding the new nonce.
+            let balance = self.balance(address)?;
+            self.new_contract(address, balance, *nonce - start_nonce);
+            return Ok(());
+        }
+
+        // Replacing a contract account would drop its storage, so its nonce
+        // is stepped instead. That cannot go backwards, and is bounded so a
+        // large nonce does not stall the caller.
+        let mut current = self.nonce(address)?;
+        if *nonce < current {
+            return Err(format_err!(
+                "nonce of a contract cannot be decreased (current nonce is {})",
+                current
+            ));
+        }
+        if *nonce - current > U256::from(MAX_CONTRACT_NONCE_INCREASE) {
+            return Err(format_err!(
+                "nonce of a contract can be increased by at most {} at a time",
+                MAX_CONTRACT_NONCE_INCREASE
+            ));
+        }
+        while current < *nonce {
+            self.inc_nonce(address)?;
+            current = current + U256::one();
+        }
+        Ok(())
+    }
+
+    fn set_code(&mut self, address: &Address, code: Vec<u8>) -> Fallible<()> {
+        Ok(self.init_code(address, code)?)
+    }
+
+    fn clear_storage(&mut self, address: &Address) -> Fallible<()> {
+        let balance = self.balance(address)?;
+        let nonce = self.nonce(address)?;
+        let code = self.code(address)?;
+
+        // A freshly created contract account starts with empty storage.
+        let nonce_offset = nonce - *self.account_start_nonce();
+        self.new_contract(address, balance, nonce_offset);
+        if let Some(code) = code {
+            self.init_code(address, (*code).clone())?;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 //! Pub/sub support.
 use std::{
+    cmp,
     process::abort,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc, RwLock, Weak,
     },
     time::Duration,
@@ -24,10 +25,58 @@ pub trait Listener: Send + Sync {
     fn notify_completed_transaction(&self, entry: &TxEntry, output: Vec<u8>);
 }
 
+/// Identifier of a listener registered with a `Broker`.
+pub type ListenerId = u64;
+
 struct Inner {
     blockchain: Arc<Blockchain>,
     last_notified_block: AtomicU64,
-    listeners: RwLock<Vec<Weak<dyn Listener>>>,
+    /// Number of chain reorgs taken into account.
+    seen_reorgs: AtomicUsize,
+    next_listener_id: AtomicU64,
+    listeners: RwLock<Vec<(ListenerId, Weak<dyn Listener>)>>,
+}
+
+impl Inner {
+    /// Notify the listeners of the blocks mined since the last notification,
+    /// given the best block. Listeners which were dropped are pruned.
+    ///
+    /// After a reorg, the blocks replacing already notified ones are notified
+    /// again.
+    fn notify_listeners(&self, to: u64) {
+        let listeners: Vec<Arc<dyn Listener>> = {
+            let mut listeners = self.listeners.write().unwrap();
+            listeners.retain(|(_, listener)| listener.upgrade().is_some());
+            listeners
+                .iter()
+                .filter_map(|(_, listener)| listener.upgrade())
+                .collect()
+        };
+
+        let seen_reorgs = self.seen_reorgs.load(Ordering::SeqCst);
+        let reorgs = self.blockchain.reorgs_since(seen_reorgs);
+        let mut last_notified_block = self.last_notified_block.load(Ordering::SeqCst);
+        if let Some(head) = reorgs.iter().min() {
+            last_notified_block = cmp::min(last_notified_block, *head);
+            self.last_notified_block
+                .store(last_notified_block, Ordering::SeqCst);
+        }
+        self.seen_reorgs
+            .store(seen_reorgs + reorgs.len(), Ordering::SeqCst);
+
+        // If there are no new blocks, return early.
+        if to <= last_notified_block {
+            return;
+        }
+
+        let from = last_notified_block + 1;
+
+        for listener in listeners {
+            listener.notify_blocks(from, to);
+        }
+
+        self.last_notified_block.store(to, Ordering::SeqCst);
+    }
 }
 
 pub struct Broker {
@@ -40,14 +89,34 @@ impl Broker {
             inner: Arc::new(Inner {
                 blockchain,
                 last_notified_block: AtomicU64::new(0),
+                seen_reorgs: AtomicUsize::new(0),
+                next_listener_id: AtomicU64::new(0),
                 listeners: RwLock::new(vec![]),
             }),
         }
     }
 
-    pub fn add_listener(&self, listener: Weak<dyn Listener>) {
+    /// Register a listener, which is notified until it is dropped or removed.
+    pub fn add_listener(&self, listener: Weak<dyn Listener>) -> ListenerId {
+        let id = self.inner.next_listener_id.fetch_add(1, Ordering::SeqCst);
+        let mut listeners = self.inner.listeners.write().unwrap();
+        listeners.retain(|(_, listener)| listener.upgrade().is_some());
+        listeners.push((id, listener));
+        id
+    }
+
+    /// Deregister a listener, returning whether it was registered.
+    pub fn remove_listener(&self, id: ListenerId) -> bool {
         let mut listeners = self.inner.listeners.write().unwrap();
-        listeners.push(listener);
+        let count = listeners.len();
+        listeners.retain(|(listener_id, _)| *listener_id != id);
+        listeners.len() < count
+    }
+
+    /// Number of registered listeners. Dropped listeners are only pruned on
+    /// the next tick or registration.
+    pub fn listener_count(&self) -> usize {
+        self.inner.listeners.read().unwrap().len()
     }
 
     pub fn start(&self, interval: Duration) -> impl Future<Item = (), Error = ()> {
@@ -58,27 +127,10 @@ impl Broker {
             .for_each(move |_| {
                 // Get latest block and notify all listeners of the difference.
                 let inner = inner.clone();
-                inner.blockchain.get_latest_block().map(move |blk| {
-                    let last_notified_block = inner.last_notified_block.load(Ordering::SeqCst);
-                    let listeners = inner.listeners.read().unwrap();
-
-                    let to = blk.number_u64();
-
-                    // If there are no new blocks, return early.
-                    if to <= last_notified_block {
-                        return;
-                    }
-
-                    let from = last_notified_block + 1;
-
-                    for listener in listeners.iter() {
-                        if let Some(listener) = listener.upgrade() {
-                            listener.notify_blocks(from, to);
-                        }
-                    }
-
-                    inner.last_notified_block.store(to, Ordering::SeqCst);
-                })
+                inner
+                    .blockchain
+                    .get_latest_block()
+                    .map(move |blk| inner.notify_listeners(blk.number_u64()))
             })
             .map_err(move |err| {
                 error!("Pub/sub notifier error: {:?}", err,);
@@ -86,3 +138,65 @@ impl Broker {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support::{new_blockchain, test_config};
+
+    #[derive(Default)]
+    struct BlockRecorder {
+        blocks: Mutex<Vec<(u64, u64)>>,
+    }
+
+    impl Listener for BlockRecorder {
+        fn notify_blocks(&self, from_block: u64, to_block: u64) {
+            self.blocks.lock().unwrap().push((from_block, to_block));
+        }
+
+        fn notify_completed_transaction(&self, _entry: &TxEntry, _output: Vec<u8>) {}
+    }
+
+    #[test]
+    fn test_dropped_listeners_pruned() {
+        let broker = Broker::new(Arc::new(new_blockchain(test_config())));
+
+        let listener: Arc<dyn Listener> = Arc::new(BlockRecorder::default());
+        broker.add_listener(Arc::downgrade(&listener));
+        assert_eq!(broker.listener_count(), 1);
+        drop(listener);
+        assert_eq!(broker.listener_count(), 1);
+        broker.inner.notify_listeners(0);
+        assert_eq!(broker.listener_count(), 0);
+
+        // Live listeners are notified until removed.
+        let recorder = Arc::new(BlockRecorder::default());
+        let listener: Arc<dyn Listener> = recorder.clone();
+        let id = broker.add_listener(Arc::downgrade(&listener));
+        broker.inner.notify_listeners(2);
+        assert_eq!(*recorder.blocks.lock().unwrap(), vec![(1, 2)]);
+        assert!(broker.remove_listener(id));
+        assert!(!broker.remove_listener(id));
+        broker.inner.notify_listeners(3);
+        assert_eq!(*recorder.blocks.lock().unwrap(), vec![(1, 2)]);
+        assert_eq!(broker.listener_count(), 0);
+    }
+
+    #[test]
+    fn test_reorged_blocks_notified_again() {
+        let blockchain = Arc::new(new_blockchain(test_config()));
+        let broker = Broker::new(blockchain.clone());
+        let recorder = Arc::new(BlockRecorder::default());
+        let listener: Arc<dyn Listener> = recorder.clone();
+        broker.add_listener(Arc::downgrade(&listener));
+
+        blockchain.mine_blocks(3, None).unwrap();
+        broker.inner.notify_listeners(3);
+        assert_eq!(blockchain.reorg(2, 3).unwrap(), 4);
+        broker.inner.notify_listeners(4);
+        broker.inner.notify_listeners(4);
+        assert_eq!(*recorder.blocks.lock().unwrap(), vec![(1, 3), (2, 4)]);
+    }
+}
@@ -18,10 +18,11 @@ use std::{collections::HashSet, io, sync::Arc};
 
 use informant::RpcStats;
 use jsonrpc_core::MetaIoHandler;
-use middleware::{Middleware, WsDispatcher, WsStats};
+use middleware::{Middleware, RequestMetrics, WsDispatcher, WsStats};
 use parity_reactor::TokioRemote;
 use parity_rpc::{self as rpc, DomainsValidation, Metadata};
 use rpc_apis::{self, ApiSet};
+use serde_json;
 
 use servers;
 
@@ -117,6 +118,7 @@ pub struct Dependencies<D: rpc_apis::Dependencies> {
     pub apis: Arc<D>,
     pub remote: TokioRemote,
     pub stats: Arc<RpcStats>,
+    pub metrics: Arc<RequestMetrics>,
 }
 
 pub fn new_ws<D: rpc_apis::Dependencies>(
@@ -135,7 +137,11 @@ pub fn new_ws<D: rpc_apis::Dependencies>(
     let handler = {
         let mut handler = MetaIoHandler::with_middleware((
             WsDispatcher::new(deps.stats.clone(), conf.max_req_per_sec),
-            Middleware::new(deps.apis.activity_notifier(), conf.max_batch_size),
+            Middleware::new(
+                deps.apis.activity_notifier(),
+                conf.max_batch_size,
+                deps.metrics.clone(),
+            ),
         ));
         let apis = conf.apis.list_apis();
         deps.apis.extend_with_set(&mut handler, &apis);
@@ -169,7 +175,7 @@ pub fn new_ws<D: rpc_apis::Dependencies>(
     }
 }
 
-pub fn new_http<D: rpc_apis::Dependencies>(
+pub fn new_http<D: rpc_apis::Dependencies + Send + Sync + 'static>(
     id: &str,
     options: &str,
     conf: HttpConfiguration,
@@ -189,6 +195,9 @@ pub fn new_http<D: rpc_apis::Dependencies>(
     let cors_domains = into_domains(conf.cors);
     let allowed_hosts = into_domains(with_domain(conf.hosts, &Some(url.clone().into())));
 
+    let apis = deps.apis.clone();
+    let health = move || serde_json::to_string(&apis.health()).expect("health report serializes");
+
     let start_result = servers::start_http(
         &addr,
         cors_domains,
@@ -197,6 +206,7 @@ pub fn new_http<D: rpc_apis::Dependencies>(
         remote,
         rpc::RpcExtractor,
         conf.server_threads,
+        health,
     );
 
     match start_result {
@@ -248,6 +258,7 @@ where
     let mut handler = MetaIoHandler::with_middleware(Middleware::new(
         deps.apis.activity_notifier(),
         max_batch_size,
+        deps.metrics.clone(),
     ));
     let apis = apis.list_apis();
     deps.apis.extend_with_set(&mut handler, &apis);
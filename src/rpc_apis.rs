@@ -14,17 +14,18 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::{cmp::PartialEq, collections::HashSet, str::FromStr, sync::Arc};
+use std::{cmp::PartialEq, collections::HashSet, str::FromStr, sync::Arc, time::Instant};
 
 use ekiden_keymanager::client::MockClient;
+use ethcore::account_provider::AccountProvider;
 use jsonrpc_core::{self as core, MetaIoHandler};
 use parity_rpc::{informant::ActivityNotifier, Host, Metadata};
 
 use crate::{
     blockchain::Blockchain,
     impls::{
-        EthClient, EthFilterClient, EthPubSubClient, EthSigningClient, NetClient, OasisClient,
-        Web3Client,
+        DebugClient, EthClient, EthFilterClient, EthPubSubClient, EthSigningClient, EvmClient,
+        NetClient, OasisClient, PersonalClient, Web3Client,
     },
     pubsub::Broker,
 };
@@ -41,6 +42,12 @@ pub enum Api {
     EthPubSub,
     /// Oasis (Safe)
     Oasis,
+    /// Evm (Unsafe, mutates the chain state)
+    Evm,
+    /// Personal (Unsafe, spends from the unlocked development accounts)
+    Personal,
+    /// Debug (Unsafe, exposes the state dump and plaintext confidential calls)
+    Debug,
 }
 
 impl FromStr for Api {
@@ -55,6 +62,9 @@ impl FromStr for Api {
             "eth" => Ok(Eth),
             "pubsub" => Ok(EthPubSub),
             "oasis" => Ok(Oasis),
+            "evm" => Ok(Evm),
+            "personal" => Ok(Personal),
+            "debug" => Ok(Debug),
             api => Err(format!("Unknown api: {}", api)),
         }
     }
@@ -140,6 +150,19 @@ pub trait Dependencies {
     fn extend_with_set<S>(&self, handler: &mut MetaIoHandler<Metadata, S>, apis: &HashSet<Api>)
     where
         S: core::Middleware<Metadata>;
+
+    /// Report liveness and chain progress for the HTTP health endpoint.
+    fn health(&self) -> Health;
+}
+
+/// Response of the HTTP `GET /health` endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    /// Number of the best block.
+    pub best_block: u64,
+    /// Seconds elapsed since the RPC dependencies were created.
+    pub uptime_secs: u64,
 }
 
 /// RPC dependencies for a full node.
@@ -147,7 +170,12 @@ pub struct FullDependencies {
     pub blockchain: Arc<Blockchain>,
     pub broker: Arc<Broker>,
     pub km_client: Arc<MockClient>,
+    pub accounts: Arc<AccountProvider>,
     pub ws_address: Option<Host>,
+    /// Maximum number of blocks mined by a single `evm_mine` call.
+    pub max_mine_blocks: u64,
+    /// Time at which the gateway started, used to report uptime.
+    pub started: Instant,
 }
 
 impl FullDependencies {
@@ -160,7 +188,7 @@ impl FullDependencies {
         S: core::Middleware<Metadata>,
     {
         use parity_rpc::v1::{Eth, EthFilter, EthPubSub, EthSigning, Net, Web3};
-        use traits::Oasis;
+        use traits::{Debug, EthRaw, Evm, Oasis, OasisDebug, Personal};
 
         for api in apis {
             match *api {
@@ -171,10 +199,30 @@ impl FullDependencies {
                     handler.extend_with(NetClient::new().to_delegate());
                 }
                 Api::Eth => {
-                    let client = EthClient::new(self.blockchain.clone());
-                    handler.extend_with(client.to_delegate());
-
-                    let signing_client = EthSigningClient::new();
+                    let client = EthClient::new(self.blockchain.clone(), self.accounts.clone());
+                    handler.extend_with(Eth::to_delegate(client.clone()));
+                    handler.extend_with(EthRaw::to_delegate(client.clone()));
+                    // Supersede `eth_getTransactionReceipt` with a variant
+                    // reporting the effective gas price.
+                    let receipt_client = client.clone();
+                    handler.add_method("eth_getTransactionReceipt", move |params| {
+                        receipt_client.transaction_receipt_with_gas_price(params)
+                    });
+                    let receipts_client = client.clone();
+                    handler.add_method("eth_getBlockReceipts", move |params| {
+                        receipts_client.block_receipts(params)
+                    });
+                    let access_list_client = client.clone();
+                    handler.add_method_with_meta("eth_createAccessList", move |params, meta| {
+                        access_list_client.create_access_list(meta, params)
+                    });
+                    // Supersede `eth_call` with a variant accepting state overrides.
+                    handler.add_method_with_meta("eth_call", move |params, meta| {
+                        client.call_with_overrides(meta, params)
+                    });
+
+                    let signing_client =
+                        EthSigningClient::new(self.blockchain.clone(), self.accounts.clone());
                     handler.extend_with(signing_client.to_delegate());
 
                     if !for_generic_pubsub {
@@ -184,16 +232,34 @@ impl FullDependencies {
                 }
                 Api::EthPubSub => {
                     if !for_generic_pubsub {
-                        let pubsub_client = EthPubSubClient::new(self.blockchain.clone());
-                        self.broker.add_listener(pubsub_client.handler());
+                        let pubsub_client =
+                            EthPubSubClient::new(self.blockchain.clone(), self.broker.clone());
                         handler.extend_with(pubsub_client.to_delegate());
                     }
                 }
                 Api::Oasis => {
+                    handler.extend_with(Oasis::to_delegate(OasisClient::new(
+                        self.blockchain.clone(),
+                        self.km_client.clone(),
+                    )));
+                }
+                Api::Evm => {
                     handler.extend_with(
-                        OasisClient::new(self.blockchain.clone(), self.km_client.clone())
+                        EvmClient::new(self.blockchain.clone(), self.max_mine_blocks).to_delegate(),
+                    );
+                }
+                Api::Personal => {
+                    handler.extend_with(PersonalClient::new(self.accounts.clone()).to_delegate());
+                }
+                Api::Debug => {
+                    handler.extend_with(
+                        DebugClient::new(self.blockchain.clone(), self.accounts.clone())
                             .to_delegate(),
                     );
+                    handler.extend_with(OasisDebug::to_delegate(OasisClient::new(
+                        self.blockchain.clone(),
+                        self.km_client.clone(),
+                    )));
                 }
             }
         }
@@ -215,28 +281,42 @@ impl Dependencies for FullDependencies {
     {
         self.extend_api(handler, apis, false)
     }
+
+    fn health(&self) -> Health {
+        Health {
+            best_block: self.blockchain.best_block_number(),
+            uptime_secs: self.started.elapsed().as_secs(),
+        }
+    }
 }
 
 impl ApiSet {
     pub fn list_apis(&self) -> HashSet<Api> {
-        let public_list: HashSet<Api> = [Api::Web3, Api::Net, Api::Eth, Api::EthPubSub, Api::Oasis]
-            .into_iter()
-            .cloned()
-            .collect();
+        let mut public_list: HashSet<Api> =
+            [Api::Web3, Api::Net, Api::Eth, Api::EthPubSub, Api::Oasis]
+                .into_iter()
+                .cloned()
+                .collect();
 
         match *self {
             ApiSet::List(ref apis) => apis.clone(),
             ApiSet::UnsafeContext => public_list,
             #[cfg(test)]
             ApiSet::SafeContext => public_list,
-            ApiSet::All => public_list,
+            ApiSet::All => {
+                public_list.insert(Api::Evm);
+                public_list.insert(Api::Personal);
+                public_list.insert(Api::Debug);
+                public_list
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Api, ApiSet};
+    use super::{Api, ApiSet, Health};
+    use serde_json;
 
     #[test]
     fn test_api_parsing() {
@@ -245,9 +325,24 @@ mod test {
         assert_eq!(Api::Eth, "eth".parse().unwrap());
         assert_eq!(Api::EthPubSub, "pubsub".parse().unwrap());
         assert_eq!(Api::Oasis, "oasis".parse().unwrap());
+        assert_eq!(Api::Evm, "evm".parse().unwrap());
+        assert_eq!(Api::Personal, "personal".parse().unwrap());
+        assert_eq!(Api::Debug, "debug".parse().unwrap());
         assert!("rp".parse::<Api>().is_err());
     }
 
+    #[test]
+    fn test_health_serialization() {
+        let health = Health {
+            best_block: 3,
+            uptime_secs: 42,
+        };
+        assert_eq!(
+            serde_json::to_string(&health).unwrap(),
+            r#"{"bestBlock":3,"uptimeSecs":42}"#
+        );
+    }
+
     #[test]
     fn test_api_set_default() {
         assert_eq!(ApiSet::UnsafeContext, ApiSet::default());
@@ -259,6 +354,13 @@ mod test {
             ApiSet::List(vec![Api::Web3, Api::Eth].into_iter().collect()),
             "web3,eth".parse().unwrap()
         );
+        // Unsafe APIs are opt-in.
+        let apis = "safe,evm".parse::<ApiSet>().unwrap().list_apis();
+        assert!(apis.contains(&Api::Evm));
+        assert!(apis.contains(&Api::Eth));
+        assert!(!ApiSet::default().list_apis().contains(&Api::Evm));
+        assert!(!ApiSet::default().list_apis().contains(&Api::Personal));
+        assert!(!ApiSet::default().list_apis().contains(&Api::Debug));
     }
 
     #[test]
@@ -296,9 +398,18 @@ mod test {
         assert_eq!(
             "all".parse::<ApiSet>().unwrap(),
             ApiSet::List(
-                vec![Api::Web3, Api::Net, Api::Eth, Api::EthPubSub, Api::Oasis,]
-                    .into_iter()
-                    .collect()
+                vec![
+                    Api::Web3,
+                    Api::Net,
+                    Api::Eth,
+                    Api::EthPubSub,
+                    Api::Oasis,
+                    Api::Evm,
+                    Api::Personal,
+                    Api::Debug,
+                ]
+                .into_iter()
+                .collect()
             )
         );
     }
@@ -21,15 +21,25 @@ use std::{
 };
 
 use ekiden_keymanager::client::MockClient;
+use ethcore::account_provider::AccountProvider;
 use ethereum_types::U256;
+use ethkey::KeyPair;
 use failure::{format_err, Fallible};
 use informant;
+use jsonrpc_core::MetaIoHandler;
 use log::{info, warn};
+use middleware::{Middleware, RequestMetrics, SLOW_REQUEST_THRESHOLD};
 use parity_reactor::EventLoop;
+use parity_rpc::Metadata;
 use rpc::{self, HttpConfiguration, WsConfiguration};
-use rpc_apis;
+use rpc_apis::{self, ApiSet, ClientNotifier, Dependencies};
 
-use crate::{blockchain::Blockchain, pubsub::Broker};
+use crate::{
+    blockchain::{Blockchain, BlockchainConfig, StateOverride},
+    genesis, hd_wallet,
+    pubsub::Broker,
+    util,
+};
 
 pub fn execute(
     km_client: Arc<MockClient>,
@@ -39,19 +49,35 @@ pub fn execute(
     num_threads: usize,
     ws_port: u16,
     ws_max_connections: usize,
-    gas_price: U256,
-    block_gas_limit: U256,
+    slow_request_threshold: Duration,
+    apis: ApiSet,
+    mut config: BlockchainConfig,
+    max_mine_blocks: u64,
+    chain: &str,
+    genesis_alloc: Option<&str>,
+    mnemonic: &str,
+    account_count: usize,
+    account_balance: Option<U256>,
 ) -> Fallible<RunningGateway> {
+    let keys = hd_wallet::derive_accounts(mnemonic, account_count)?;
     let mut runtime = tokio::runtime::Runtime::new()?;
+    let spec = genesis::load_spec(chain)?;
+    if let Some(path) = genesis_alloc {
+        config.genesis_alloc.extend(genesis::load_alloc(path)?);
+    }
+    let (accounts, balances) =
+        dev_accounts(&keys, account_balance, &spec, &mut config.genesis_alloc)?;
+    info!(
+        "{}",
+        hd_wallet::describe_accounts(&keys, &balances, mnemonic)
+    );
 
-    let blockchain = Arc::new(Blockchain::new(
-        gas_price,
-        block_gas_limit,
-        km_client.clone(),
-    ));
+    let blockchain = Arc::new(Blockchain::new(config, spec, km_client.clone())?);
     let broker = Arc::new(Broker::new(blockchain.clone()));
     runtime.spawn(broker.start(Duration::new(pubsub_interval_secs, 0)));
 
+    spawn_mining(&mut runtime, &blockchain);
+
     let rpc_stats = Arc::new(informant::RpcStats::default());
 
     // Spin up event loop.
@@ -63,6 +89,7 @@ pub fn execute(
     ws_conf.hosts = None;
     ws_conf.interface = interface.into();
     ws_conf.port = ws_port;
+    ws_conf.apis = apis.clone();
 
     // Max # of concurrent connections. the default is 100, which is "low" and "should be increased":
     // https://github.com/tomusdrw/ws-rs/blob/f12d19c4c19422fc79af28a3181f598bc07ecd1e/src/lib.rs#L128
@@ -74,19 +101,25 @@ pub fn execute(
     http_conf.interface = interface.into();
     http_conf.port = http_port;
     http_conf.server_threads = num_threads;
+    http_conf.apis = apis;
 
     // Define RPC handlers.
     let deps_for_rpc_apis = Arc::new(rpc_apis::FullDependencies {
         blockchain: blockchain.clone(),
         broker: broker.clone(),
         km_client: km_client.clone(),
+        // Managed accounts are kept in memory only and lost on restart.
+        accounts: Arc::new(accounts),
         ws_address: ws_conf.address(),
+        max_mine_blocks,
+        started: Instant::now(),
     });
 
     let dependencies = rpc::Dependencies {
         apis: deps_for_rpc_apis.clone(),
         remote: event_loop.raw_remote(),
         stats: rpc_stats.clone(),
+        metrics: Arc::new(RequestMetrics::new(slow_request_threshold)),
     };
 
     // Start RPC servers.
@@ -96,6 +129,7 @@ pub fn execute(
         .map_err(|err| format_err!("{}", err))?;
 
     let running_client = RunningGateway {
+        mine_pending_on_shutdown: false,
         runtime,
         blockchain,
         km_client,
@@ -106,11 +140,71 @@ pub fn execute(
     Ok(running_client)
 }
 
+/// Mine the pending transactions at a fixed interval in interval mining mode,
+/// or after the instamine delay in auto mining mode.
+///
+/// The mining mode may change at runtime, so both tasks always run.
+fn spawn_mining(runtime: &mut tokio::runtime::Runtime, blockchain: &Arc<Blockchain>) {
+    runtime.spawn(Blockchain::interval_mining(blockchain.clone()));
+    runtime.spawn(Blockchain::delayed_instamine(blockchain.clone()));
+}
+
+/// Fund the development accounts in the genesis allocation and unlock them
+/// for signing, returning their genesis balances.
+///
+/// A balance set by the genesis allocation is kept. Otherwise the accounts
+/// get the given balance or, without one, keep the balance the genesis spec
+/// gives them, the accounts the spec does not fund getting the default
+/// balance.
+fn dev_accounts(
+    keys: &[KeyPair],
+    balance: Option<U256>,
+    spec: &Spec,
+    genesis_alloc: &mut StateOverride,
+) -> Fallible<(AccountProvider, Vec<U256>)> {
+    let accounts = AccountProvider::transient_provider();
+    let mut balances = Vec::with_capacity(keys.len());
+    for key in keys {
+        let address = key.address();
+        let spec_balance = spec
+            .genesis_state()
+            .get()
+            .get(&address)
+            .map(|account| account.balance);
+        let alloc_balance = genesis_alloc
+            .get(&address)
+            .and_then(|account| account.balance);
+        let funded = match (alloc_balance, balance, spec_balance) {
+            (Some(alloc_balance), _, _) => alloc_balance,
+            (None, None, Some(spec_balance)) => spec_balance,
+            (None, balance, _) => {
+                let balance =
+                    balance.unwrap_or_else(|| util::dev_to_wei(hd_wallet::DEFAULT_ACCOUNT_BALANCE));
+                genesis_alloc
+                    .entry(address)
+                    .or_insert_with(Default::default)
+                    .balance = Some(balance);
+                balance
+            }
+        };
+        balances.push(funded);
+
+        let address = accounts
+            .insert_account(key.secret().clone(), "")
+            .map_err(|err| format_err!("failed to import account: {}", err))?;
+        accounts
+            .unlock_account_permanently(address, String::new())
+            .map_err(|err| format_err!("failed to unlock account {:?}: {:?}", address, err))?;
+    }
+    Ok((accounts, balances))
+}
+
 /// Gateway currently executing in background threads.
 ///
 /// Should be destroyed by calling `shutdown()`, otherwise execution will continue in the
 /// background.
 pub struct RunningGateway {
+    mine_pending_on_shutdown: bool,
     runtime: tokio::runtime::Runtime,
     blockchain: Arc<Blockchain>,
     km_client: Arc<MockClient>,
@@ -120,9 +214,16 @@ pub struct RunningGateway {
 }
 
 impl RunningGateway {
+    /// Sets whether `shutdown()` mines the pending transactions into a final
+    /// block. Otherwise, they are dropped.
+    pub fn set_mine_pending_on_shutdown(&mut self, mine_pending_on_shutdown: bool) {
+        self.mine_pending_on_shutdown = mine_pending_on_shutdown;
+    }
+
     /// Shuts down the gateway.
     pub fn shutdown(self) {
         let RunningGateway {
+            mine_pending_on_shutdown,
             runtime,
             blockchain,
             km_client,
@@ -141,6 +242,19 @@ impl RunningGateway {
         drop(event_loop);
         drop(http_server);
         drop(ws_server);
+
+        // No more transactions can arrive, so flush those not yet mined.
+        if mine_pending_on_shutdown {
+            while blockchain.pending_transaction_count() > 0 {
+                let number = blockchain.mine_pending_block();
+                info!("Mined pending transactions in block {}", number);
+            }
+        }
+        let dropped = blockchain.discard_pending_transactions();
+        if dropped > 0 {
+            warn!("Dropping {} unmined transactions", dropped);
+        }
+
         drop(blockchain);
         drop(km_client);
 
@@ -148,6 +262,72 @@ impl RunningGateway {
     }
 }
 
+/// Gateway serving RPC requests in-process, without binding any sockets.
+///
+/// Meant for integration tests, which can drive all APIs through
+/// `handle_request_sync`.
+pub struct InProcessGateway {
+    _runtime: tokio::runtime::Runtime,
+    handler: MetaIoHandler<Metadata, Middleware<ClientNotifier>>,
+}
+
+impl InProcessGateway {
+    /// Creates a new chain and an RPC handler with all APIs enabled.
+    pub fn new(
+        mut config: BlockchainConfig,
+        max_mine_blocks: u64,
+        chain: &str,
+        genesis_alloc: Option<&str>,
+        mnemonic: &str,
+        account_count: usize,
+        account_balance: Option<U256>,
+    ) -> Fallible<Self> {
+        let keys = hd_wallet::derive_accounts(mnemonic, account_count)?;
+        let mut runtime = tokio::runtime::Runtime::new()?;
+        let spec = genesis::load_spec(chain)?;
+        if let Some(path) = genesis_alloc {
+            config.genesis_alloc.extend(genesis::load_alloc(path)?);
+        }
+        let (accounts, _) = dev_accounts(&keys, account_balance, &spec, &mut config.genesis_alloc)?;
+
+        let km_client = Arc::new(MockClient::new());
+        let blockchain = Arc::new(Blockchain::new(config, spec, km_client.clone())?);
+        spawn_mining(&mut runtime, &blockchain);
+
+        let deps = rpc_apis::FullDependencies {
+            blockchain: blockchain.clone(),
+            broker: Arc::new(Broker::new(blockchain.clone())),
+            km_client,
+            accounts: Arc::new(accounts),
+            ws_address: None,
+            max_mine_blocks,
+            started: Instant::now(),
+        };
+        let mut handler = MetaIoHandler::with_middleware(Middleware::new(
+            deps.activity_notifier(),
+            HttpConfiguration::default().max_batch_size,
+            Arc::new(RequestMetrics::new(SLOW_REQUEST_THRESHOLD)),
+        ));
+        deps.extend_with_set(&mut handler, &ApiSet::All.list_apis());
+
+        Ok(InProcessGateway {
+            _runtime: runtime,
+            handler,
+        })
+    }
+
+    /// RPC handler with all APIs.
+    pub fn handler(&self) -> &MetaIoHandler<Metadata, Middleware<ClientNotifier>> {
+        &self.handler
+    }
+
+    /// Handles a serialized JSON-RPC request, returning the serialized response.
+    pub fn handle_request_sync(&self, request: &str) -> Option<String> {
+        self.handler
+            .handle_request_sync(request, Metadata::default())
+    }
+}
+
 fn wait_for_drop<T>(w: Weak<T>) {
     let sleep_duration = Duration::from_secs(1);
     let warn_timeout = Duration::from_secs(60);
@@ -171,3 +351,169 @@ fn wait_for_drop<T>(w: Weak<T>) {
 
     warn!("Shutdown timeout reached, exiting uncleanly");
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        blockchain::{MiningLog, MAX_MINE_BLOCKS},
+        test_support::test_config,
+    };
+
+    fn call(gateway: &InProcessGateway, method: &str, params: Value) -> Value {
+        let request = format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "method": "{}", "params": {}}}"#,
+            method, params
+        );
+        let response = gateway.handle_request_sync(&request).unwrap();
+        let mut response: Value = serde_json::from_str(&response).unwrap();
+        assert!(response.get("error").is_none(), "{}", response);
+        response["result"].take()
+    }
+
+    #[test]
+    fn test_in_process_deploy_and_read_storage() {
+        let gateway = new_gateway_with_accounts(
+            hd_wallet::DEFAULT_MNEMONIC,
+            hd_wallet::DEFAULT_ACCOUNT_COUNT,
+            None,
+        )
+        .unwrap();
+
+        let from = call(&gateway, "personal_newAccount", Value::from(vec!["secret"]));
+        call(
+            &gateway,
+            "personal_unlockAccount",
+            Value::Array(vec![from.clone(), Value::from("secret")]),
+        );
+
+        // PUSH1 0x2a, PUSH1 0, SSTORE, i.e., set slot 0 to 42.
+        let mut request = serde_json::Map::new();
+        request.insert("from".to_string(), from);
+        request.insert("data".to_string(), Value::from("0x602a600055"));
+        let hash = call(
+            &gateway,
+            "eth_sendTransaction",
+            Value::Array(vec![Value::Object(request)]),
+        );
+
+        let receipt = call(
+            &gateway,
+            "eth_getTransactionReceipt",
+            Value::Array(vec![hash]),
+        );
+        let contract = receipt["contractAddress"].clone();
+        let value = call(
+            &gateway,
+            "eth_getStorageAt",
+            Value::Array(vec![contract, Value::from("0x0"), Value::from("latest")]),
+        );
+        assert_eq!(value, format!("0x{:064x}", 42));
+    }
+
+    fn new_gateway_with_accounts(
+        mnemonic: &str,
+        count: usize,
+        balance: Option<U256>,
+    ) -> Fallible<InProcessGateway> {
+        let config = BlockchainConfig {
+            mining_log: MiningLog::Quiet,
+            ..test_config()
+        };
+        InProcessGateway::new(
+            config,
+            MAX_MINE_BLOCKS,
+            genesis::OASIS_SPEC,
+            None,
+            mnemonic,
+            count,
+            balance,
+        )
+    }
+
+    #[test]
+    fn test_mnemonic_accounts_funded_and_unlocked() {
+        let mnemonic = "test test test test test test test test test test test junk";
+        let gateway = new_gateway_with_accounts(mnemonic, 2, Some(util::dev_to_wei(5))).unwrap();
+
+        let keys = hd_wallet::derive_accounts(mnemonic, 2).unwrap();
+        let accounts = call(&gateway, "eth_accounts", Value::Array(vec![]));
+        let mut expected: Vec<_> = keys
+            .iter()
+            .map(|key| Value::from(format!("{:?}", key.address())))
+            .collect();
+        let mut accounts = accounts.as_array().unwrap().clone();
+        accounts.sort_by_key(|account| account.to_string());
+        expected.sort_by_key(|account| account.to_string());
+        assert_eq!(accounts, expected);
+
+        let balance = call(
+            &gateway,
+            "eth_getBalance",
+            Value::Array(vec![expected[0].clone(), Value::from("latest")]),
+        );
+        assert_eq!(balance, format!("{:#x}", util::dev_to_wei(5)));
+
+        // The accounts can send transactions without being unlocked first.
+        let mut request = serde_json::Map::new();
+        request.insert("from".to_string(), expected[0].clone());
+        request.insert("to".to_string(), expected[1].clone());
+        request.insert("value".to_string(), Value::from("0x1"));
+        call(
+            &gateway,
+            "eth_sendTransaction",
+            Value::Array(vec![Value::Object(request)]),
+        );
+    }
+
+    #[test]
+    fn test_default_balances() {
+        let balance_of = |gateway: &InProcessGateway, key: &KeyPair| {
+            call(
+                gateway,
+                "eth_getBalance",
+                Value::Array(vec![
+                    Value::from(format!("{:?}", key.address())),
+                    Value::from("latest"),
+                ]),
+            )
+        };
+
+        // The spec funds the default accounts, which keep their spec balance.
+        let gateway = new_gateway_with_accounts(hd_wallet::DEFAULT_MNEMONIC, 1, None).unwrap();
+        let keys = hd_wallet::derive_accounts(hd_wallet::DEFAULT_MNEMONIC, 1).unwrap();
+        assert_eq!(
+            balance_of(&gateway, &keys[0]),
+            format!("{:#x}", util::dev_to_wei(10_000))
+        );
+
+        // Other accounts get the default balance.
+        let mnemonic = "test test test test test test test test test test test junk";
+        let gateway = new_gateway_with_accounts(mnemonic, 1, None).unwrap();
+        let keys = hd_wallet::derive_accounts(mnemonic, 1).unwrap();
+        assert_eq!(
+            balance_of(&gateway, &keys[0]),
+            format!(
+                "{:#x}",
+                util::dev_to_wei(hd_wallet::DEFAULT_ACCOUNT_BALANCE)
+            )
+        );
+
+        // An explicit balance overrides the spec balance.
+        let gateway =
+            new_gateway_with_accounts(hd_wallet::DEFAULT_MNEMONIC, 1, Some(util::dev_to_wei(5)))
+                .unwrap();
+        let keys = hd_wallet::derive_accounts(hd_wallet::DEFAULT_MNEMONIC, 1).unwrap();
+        assert_eq!(
+            balance_of(&gateway, &keys[0]),
+            format!("{:#x}", util::dev_to_wei(5))
+        );
+    }
+
+    #[test]
+    fn test_invalid_mnemonic_rejected() {
+        assert!(new_gateway_with_accounts("range drive remove", 1, None).is_err());
+    }
+}
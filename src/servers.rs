@@ -30,7 +30,9 @@ use std::net::SocketAddr;
 pub type HttpServer = http::Server;
 
 /// Start http server asynchronously and returns result with `Server` handle on success or an error.
-pub fn start_http<M, S, H, T>(
+///
+/// Besides JSON-RPC, the server answers `GET /health` with the JSON report produced by `health`.
+pub fn start_http<M, S, H, T, F>(
     addr: &SocketAddr,
     cors_domains: http::DomainsValidation<http::AccessControlAllowOrigin>,
     allowed_hosts: http::DomainsValidation<http::Host>,
@@ -38,22 +40,27 @@ pub fn start_http<M, S, H, T>(
     remote: tokio_core::reactor::Remote,
     extractor: T,
     threads: usize,
+    health: F,
 ) -> ::std::io::Result<HttpServer>
 where
     M: jsonrpc_core::Metadata,
     S: jsonrpc_core::Middleware<M>,
     H: Into<jsonrpc_core::MetaIoHandler<M, S>>,
     T: HttpMetaExtractor<Metadata = M>,
+    F: Fn() -> String + Send + Sync + 'static,
 {
     let extractor = http_common::MetaExtractor::new(extractor);
     let builder = http::ServerBuilder::with_meta_extractor(handler, extractor)
         .threads(threads)
         .event_loop_remote(remote)
-        .request_middleware(|request: hyper::Request<hyper::Body>| {
+        .request_middleware(move |request: hyper::Request<hyper::Body>| {
             // If the requested url is /status, terminate with 200 OK response.
+            // A GET of /health is answered with the health report.
             // Otherwise, proceed with normal request handling.
             if request.uri() == "/status" {
                 http::Response::ok("").into()
+            } else if *request.method() == hyper::Method::Get && request.uri() == "/health" {
+                http::Response::ok(health()).into()
             } else {
                 request.into()
             }
@@ -1,39 +1,209 @@
 //! Storage wrappers.
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     sync::{Arc, RwLock},
 };
 
 use ethcore::mkvs::MKVS;
 
-/// In-memory trivial key/value storage.
+/// Value history of a key, by version. `None` marks a removal.
+type History = BTreeMap<u64, Option<Vec<u8>>>;
+
+/// Uncommitted writes of a view. `None` marks a removal.
+type Overlay = HashMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// In-memory versioned key/value storage.
+///
+/// Every key keeps the history of its values, so creating a view of the
+/// storage at any committed version is O(1). Writes through a view go to an
+/// overlay shared by the clones of that view and only become visible to other
+/// views once committed as a new version.
+///
+/// The storage also holds unversioned metadata, kept apart from the keys of
+/// the state.
 #[derive(Clone)]
-pub struct MemoryMKVS(Arc<RwLock<HashMap<Vec<u8>, Vec<u8>>>>);
+pub struct MemoryMKVS {
+    store: Arc<RwLock<HashMap<Vec<u8>, History>>>,
+    version: u64,
+    overlay: Arc<RwLock<Overlay>>,
+    metadata: Arc<RwLock<HashMap<Vec<u8>, Vec<u8>>>>,
+}
 
 impl MemoryMKVS {
     pub fn new() -> Self {
-        MemoryMKVS(Arc::new(RwLock::new(HashMap::new())))
+        MemoryMKVS {
+            store: Arc::new(RwLock::new(HashMap::new())),
+            version: 0,
+            overlay: Arc::new(RwLock::new(HashMap::new())),
+            metadata: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Create an empty-overlay view of the storage at the given version.
+    pub fn at(&self, version: u64) -> Self {
+        MemoryMKVS {
+            store: self.store.clone(),
+            version,
+            overlay: Arc::new(RwLock::new(HashMap::new())),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    /// Commit the writes of this view as the given version.
+    ///
+    /// Writes replace any values already stored at that version. Only the
+    /// modified keys are copied.
+    pub fn commit(&self, version: u64) {
+        let mut store = self.store.write().unwrap();
+        for (key, value) in self.overlay.write().unwrap().drain() {
+            store
+                .entry(key)
+                .or_insert_with(BTreeMap::new)
+                .insert(version, value);
+        }
+    }
+
+    /// Whether nothing was ever committed to the storage.
+    pub fn is_empty(&self) -> bool {
+        self.store.read().unwrap().is_empty()
+    }
+
+    /// Discard all versions after the given one. Returns whether any value
+    /// was discarded.
+    pub fn truncate(&self, version: u64) -> bool {
+        let mut store = self.store.write().unwrap();
+        let mut discarded = false;
+        store.retain(|_, history| {
+            discarded |= !history.split_off(&(version + 1)).is_empty();
+            !history.is_empty()
+        });
+        discarded
+    }
+
+    /// Metadata value of the given key.
+    pub fn get_metadata(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.metadata.read().unwrap().get(key).cloned()
+    }
+
+    /// Set a metadata value. Metadata is shared by all views and versions.
+    pub fn set_metadata(&self, key: &[u8], value: &[u8]) {
+        self.metadata
+            .write()
+            .unwrap()
+            .insert(key.to_vec(), value.to_vec());
     }
 }
 
 impl MKVS for MemoryMKVS {
     fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.0.read().unwrap().get(key).map(|v| v.clone())
+        if let Some(value) = self.overlay.read().unwrap().get(key) {
+            return value.clone();
+        }
+
+        self.store
+            .read()
+            .unwrap()
+            .get(key)
+            .and_then(|history| history.range(..=self.version).next_back())
+            .and_then(|(_, value)| value.clone())
     }
 
     fn insert(&mut self, key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
-        self.0
+        let previous = self.get(key);
+        self.overlay
             .write()
             .unwrap()
-            .insert(key.to_vec(), value.to_vec())
-            .map(|v| v.clone())
+            .insert(key.to_vec(), Some(value.to_vec()));
+        previous
     }
 
     fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
-        self.0.write().unwrap().remove(key).map(|v| v.clone())
+        let previous = self.get(key);
+        self.overlay.write().unwrap().insert(key.to_vec(), None);
+        previous
     }
 
     fn boxed_clone(&self) -> Box<dyn MKVS> {
         Box::new(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use test::Bencher;
+
+    use super::*;
+
+    fn store_with_keys(count: u32) -> MemoryMKVS {
+        let mut mkvs = MemoryMKVS::new();
+        for i in 0..count {
+            mkvs.insert(&i.to_be_bytes(), b"value");
+        }
+        mkvs.commit(0);
+        mkvs
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut genesis = MemoryMKVS::new();
+        genesis.insert(b"a", b"1");
+        genesis.insert(b"b", b"1");
+        genesis.commit(0);
+
+        let mut block1 = genesis.at(0);
+        block1.insert(b"a", b"2");
+        block1.remove(b"b");
+        // Uncommitted writes are only visible through the view and its clones.
+        assert_eq!(block1.boxed_clone().get(b"a"), Some(b"2".to_vec()));
+        assert_eq!(genesis.at(0).get(b"a"), Some(b"1".to_vec()));
+        block1.commit(1);
+
+        let view0 = genesis.at(0);
+        let view1 = genesis.at(1);
+        assert_eq!(view0.get(b"a"), Some(b"1".to_vec()));
+        assert_eq!(view0.get(b"b"), Some(b"1".to_vec()));
+        assert_eq!(view1.get(b"a"), Some(b"2".to_vec()));
+        assert_eq!(view1.get(b"b"), None);
+
+        assert!(genesis.truncate(0));
+        assert_eq!(genesis.at(1).get(b"a"), Some(b"1".to_vec()));
+        assert_eq!(genesis.at(1).get(b"b"), Some(b"1".to_vec()));
+        assert!(!genesis.truncate(0));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let store = store_with_keys(1);
+        store.at(0).set_metadata(b"a", b"1");
+        assert_eq!(store.at(1).get_metadata(b"a"), Some(b"1".to_vec()));
+        // Metadata is not part of any version.
+        assert_eq!(store.at(0).get(b"a"), None);
+        assert!(!store.truncate(0));
+        assert_eq!(store.get_metadata(b"a"), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn test_discarded_view() {
+        let genesis = store_with_keys(1);
+
+        let mut view = genesis.at(0);
+        view.insert(b"a", b"1");
+        drop(view);
+
+        assert_eq!(genesis.at(1).get(b"a"), None);
+    }
+
+    // View creation must not depend on the store size.
+
+    #[bench]
+    fn bench_view_small_store(b: &mut Bencher) {
+        let mkvs = store_with_keys(10);
+        b.iter(|| mkvs.at(0));
+    }
+
+    #[bench]
+    fn bench_view_large_store(b: &mut Bencher) {
+        let mkvs = store_with_keys(100_000);
+        b.iter(|| mkvs.at(0));
+    }
+}
@@ -0,0 +1,44 @@
+//! Fixtures shared by the unit tests.
+use std::sync::Arc;
+
+use ekiden_keymanager::client::MockClient;
+use ethcore::transaction::{Action, SignedTransaction, Transaction};
+use ethereum_types::{Address, U256};
+
+use crate::{
+    blockchain::{Blockchain, BlockchainConfig},
+    genesis,
+};
+
+/// Configuration of test chains: the defaults, with free gas.
+pub fn test_config() -> BlockchainConfig {
+    BlockchainConfig {
+        gas_price: U256::zero(),
+        ..Default::default()
+    }
+}
+
+/// Create a chain with the Oasis spec and the given configuration.
+pub fn new_blockchain(config: BlockchainConfig) -> Blockchain {
+    new_blockchain_with_km(config, Arc::new(MockClient::new()))
+}
+
+/// Create a chain with the Oasis spec, the given configuration and key
+/// manager.
+pub fn new_blockchain_with_km(config: BlockchainConfig, km_client: Arc<MockClient>) -> Blockchain {
+    Blockchain::new(config, genesis::oasis_spec(), km_client).unwrap()
+}
+
+/// Create a transaction with a fake signature from the given sender, free
+/// gas and enough of it for simple calls.
+pub fn fake_txn(sender: Address, nonce: u64, action: Action, data: Vec<u8>) -> SignedTransaction {
+    Transaction {
+        nonce: nonce.into(),
+        gas_price: U256::zero(),
+        gas: 1_000_000.into(),
+        action,
+        value: U256::zero(),
+        data,
+    }
+    .fake_sign(sender)
+}
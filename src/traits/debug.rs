@@ -0,0 +1,26 @@
+//! Debug RPC interface, for inspecting the simulated chain.
+use jsonrpc_core::{BoxFuture, Result};
+
+use parity_rpc::v1::types::Bytes;
+
+use crate::blockchain::StateDump;
+
+build_rpc_trait! {
+    pub trait Debug {
+        /// Returns the RLP encoding of the block with the given number, i.e.,
+        /// its header, transactions and uncles.
+        ///
+        /// The chain keeps no state or receipt tries, so the state and
+        /// receipts roots of the header are those of empty tries. Block hashes
+        /// are not derived from headers either: the keccak of the encoded
+        /// header is not the block hash.
+        #[rpc(name = "debug_getBlockRlp")]
+        fn block_rlp(&self, u64) -> BoxFuture<Bytes>;
+
+        /// Returns a snapshot of the chain: the best block, the mined blocks,
+        /// the transaction counts, and the balance, nonce and code size of the
+        /// accounts the chain knows about, including the managed accounts.
+        #[rpc(name = "debug_dumpState")]
+        fn dump_state(&self) -> Result<StateDump>;
+    }
+}
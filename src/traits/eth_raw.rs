@@ -0,0 +1,22 @@
+//! Eth RPC interface for raw signed transactions.
+use jsonrpc_core::BoxFuture;
+
+use parity_rpc::v1::types::{BlockNumber, Bytes, Index, H256};
+
+build_rpc_trait! {
+    pub trait EthRaw {
+        /// Returns the RLP of the signed transaction with the given hash.
+        #[rpc(name = "eth_getRawTransactionByHash")]
+        fn raw_transaction_by_hash(&self, H256) -> BoxFuture<Option<Bytes>>;
+
+        /// Returns the RLP of the signed transaction at the given index of
+        /// the block with the given hash.
+        #[rpc(name = "eth_getRawTransactionByBlockHashAndIndex")]
+        fn raw_transaction_by_block_hash_and_index(&self, H256, Index) -> BoxFuture<Option<Bytes>>;
+
+        /// Returns the RLP of the signed transaction at the given index of
+        /// the block with the given number.
+        #[rpc(name = "eth_getRawTransactionByBlockNumberAndIndex")]
+        fn raw_transaction_by_block_number_and_index(&self, BlockNumber, Index) -> BoxFuture<Option<Bytes>>;
+    }
+}
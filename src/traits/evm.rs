@@ -0,0 +1,88 @@
+//! Evm RPC interface, for controlling the simulated chain in tests.
+use jsonrpc_core::Result;
+use jsonrpc_macros::Trailing;
+
+use parity_rpc::v1::types::{Bytes, H160, H256, U256};
+
+build_rpc_trait! {
+    pub trait Evm {
+        /// Sets the timestamp (seconds since the epoch) of the next mined block.
+        /// It must be greater than the latest block's timestamp, and below
+        /// 2^64 - 1.
+        #[rpc(name = "evm_setNextBlockTimestamp")]
+        fn set_next_block_timestamp(&self, u64) -> Result<bool>;
+
+        /// Mines a block containing the pending transactions and returns its
+        /// number. Works in any mining mode.
+        ///
+        /// Optionally mines several blocks at once, the pending transactions
+        /// going into the first one, and returns the number of the last one.
+        #[rpc(name = "evm_mine")]
+        fn mine(&self, Trailing<MineOptions>) -> Result<U256>;
+
+        /// Advances the best block number, without mining, and returns it.
+        /// The skipped blocks are empty. The number is a hex quantity, cannot
+        /// be decreased and must be below 2^64 - 2.
+        #[rpc(name = "evm_setBlockNumber")]
+        fn set_block_number(&self, String) -> Result<U256>;
+
+        /// Sets the balance of an account in the latest state, without mining
+        /// a block. The balance is a hex quantity.
+        #[rpc(name = "evm_setBalance")]
+        fn set_balance(&self, H160, String) -> Result<bool>;
+
+        /// Sets the nonce of an account in the latest state, without mining
+        /// a block. The nonce is a hex quantity that fits in 64 bits. The
+        /// nonce of a contract can only be increased.
+        #[rpc(name = "evm_setAccountNonce")]
+        fn set_account_nonce(&self, H160, String) -> Result<bool>;
+
+        /// Places runtime bytecode at an address in the latest state, without
+        /// running any constructor or mining a block.
+        #[rpc(name = "evm_setCode")]
+        fn set_code(&self, H160, Bytes) -> Result<bool>;
+
+        /// Writes a 32-byte word to a storage slot of an account in the latest
+        /// state, without mining a block. The slot and value are hex
+        /// quantities; the value is plaintext, even for confidential contracts.
+        #[rpc(name = "evm_setStorageAt")]
+        fn set_storage_at(&self, H160, String, String) -> Result<bool>;
+
+        /// Switches to interval mining with the given block period (in
+        /// seconds) without restarting, or to auto mining if it is zero.
+        #[rpc(name = "evm_setIntervalMining")]
+        fn set_interval_mining(&self, u64) -> Result<bool>;
+
+        /// Returns whether transactions are mined as soon as they are
+        /// submitted, i.e., whether in auto mining mode.
+        #[rpc(name = "evm_getAutomine")]
+        fn get_automine(&self) -> Result<bool>;
+
+        /// Returns the block period (in seconds) of interval mining, or zero
+        /// if not in interval mining mode.
+        #[rpc(name = "evm_getIntervalMining")]
+        fn get_interval_mining(&self) -> Result<u64>;
+
+        /// Removes a transaction which has not been mined yet from the pending
+        /// transactions, freeing its nonce. Returns false if the transaction
+        /// is unknown or already mined.
+        #[rpc(name = "evm_dropTransaction")]
+        fn drop_transaction(&self, H256) -> Result<bool>;
+
+        /// Discards the given number of blocks from the top of the chain and
+        /// mines the given number of empty blocks (zero by default) in their
+        /// place, so that blocks mined from then on get new hashes. Returns
+        /// the new best block number.
+        #[rpc(name = "evm_reorg")]
+        fn reorg(&self, u64, Trailing<u64>) -> Result<U256>;
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MineOptions {
+    /// Number of blocks to mine (defaults to 1).
+    pub blocks: Option<u64>,
+    /// Seconds between the timestamps of the mined blocks.
+    pub interval: Option<u64>,
+}
@@ -1,5 +1,13 @@
 //! RPC traits for the client.
 
+pub mod debug;
+pub mod eth_raw;
+pub mod evm;
 pub mod oasis;
+pub mod oasis_debug;
+pub mod personal;
 
-pub use self::oasis::Oasis;
+pub use self::{
+    debug::Debug, eth_raw::EthRaw, evm::Evm, oasis::Oasis, oasis_debug::OasisDebug,
+    personal::Personal,
+};
@@ -1,16 +1,16 @@
 //! Oasis RPC interface.
 use ethereum_types::Address;
-use jsonrpc_core::BoxFuture;
+use jsonrpc_core::{BoxFuture, Result};
 use jsonrpc_macros::Trailing;
 
-use parity_rpc::v1::types::{BlockNumber, Bytes, H160, H256, U64};
+use parity_rpc::v1::types::{BlockNumber, Bytes, Receipt, H160, H256, U256, U64};
 
 build_rpc_trait! {
     pub trait Oasis {
         type Metadata;
         /// Returns the public key of a contract, given its address.
         #[rpc(name = "oasis_getPublicKey")]
-        fn public_key(&self, Address) -> BoxFuture<Option<RpcPublicKeyPayload>>;
+        fn public_key(&self, Address) -> BoxFuture<RpcPublicKeyPayload>;
 
         /// Gets the expiration timestamp for a contract.
         /// The value is a Unix timestamp (seconds since the epoch).
@@ -21,6 +21,43 @@ build_rpc_trait! {
         /// status code and return value.
         #[rpc(name = "oasis_invoke")]
         fn invoke(&self, Bytes) -> BoxFuture<RpcExecutionPayload>;
+
+        /// Sends signed transactions to be mined, in order, in a single new
+        /// block, and returns their hashes. Nothing is mined if any of them is
+        /// invalid or fails to apply.
+        #[rpc(name = "oasis_sendRawTransactions")]
+        fn send_raw_transactions(&self, Vec<Bytes>) -> BoxFuture<Vec<H256>>;
+
+        /// Returns the receipt of a transaction, given its hash, along with
+        /// the reason why it failed, if it did.
+        #[rpc(name = "oasis_getTransactionReceipt")]
+        fn transaction_receipt(&self, H256) -> BoxFuture<Option<RpcReceiptWithFailureReason>>;
+
+        /// Returns the number of the block that would be mined next, i.e.,
+        /// the pending block. Unlike `eth_blockNumber`, which only reports
+        /// mined blocks.
+        #[rpc(name = "oasis_pendingBlockNumber")]
+        fn pending_block_number(&self) -> Result<U256>;
+
+        /// Returns the hash and block number of the transaction which created
+        /// a contract, given its address. Null for accounts not created by a
+        /// transaction, e.g., externally owned accounts and genesis contracts.
+        #[rpc(name = "oasis_getContractCreationTx")]
+        fn contract_creation_tx(&self, H160) -> Result<Option<RpcContractCreation>>;
+
+        /// Returns the fraction of its gas limit a block used, between 0 and
+        /// 1, as in the `gasUsedRatio` of `eth_feeHistory`. Null if there is
+        /// no such block.
+        #[rpc(name = "oasis_getBlockGasRatio")]
+        fn block_gas_ratio(&self, BlockNumber) -> BoxFuture<Option<f64>>;
+
+        /// Returns the long-term public key of the key manager, which signs
+        /// the contract public keys returned by `oasis_getPublicKey`.
+        ///
+        /// The mock key manager's key is deterministic and publicly known, so
+        /// it only serves to test a client's verification logic.
+        #[rpc(name = "oasis_getKeyManagerPublicKeys")]
+        fn key_manager_public_keys(&self) -> Result<RpcKeyManagerPublicKeys>;
     }
 }
 
@@ -36,12 +73,45 @@ pub struct RpcExecutionPayload {
     pub output: Bytes,
 }
 
+#[derive(Debug, Serialize)]
+pub struct RpcReceiptWithFailureReason {
+    /// Standard receipt, with a 0 or 1 status.
+    #[serde(flatten)]
+    pub receipt: Receipt,
+    /// Why the transaction failed, e.g. `reverted`, `outOfGas` or
+    /// `badInstruction`. Null if it succeeded.
+    #[serde(rename = "failureReason")]
+    pub failure_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RpcContractCreation {
+    /// Hash of the contract creation transaction.
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: H256,
+    /// Number of the block containing the transaction.
+    #[serde(rename = "blockNumber")]
+    pub block_number: U256,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcKeyManagerPublicKeys {
+    /// Ed25519 public key signing the contract public keys.
+    #[serde(rename = "signingKey")]
+    pub signing_key: Bytes,
+    /// Attestation of the signing key, if any. Null for the mock key
+    /// manager.
+    pub signature: Option<Bytes>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RpcPublicKeyPayload {
     /// Public key of the contract.
     pub public_key: Bytes,
     /// Checksum of the key manager state.
     pub checksum: Bytes,
+    /// Key creation time (seconds since the epoch).
+    pub timestamp: u64,
     /// Signature from the key manager authenticating the public key,
     /// i.e., Sign(ssk, (pk, t).
     pub signature: Bytes,
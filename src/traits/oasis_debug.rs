@@ -0,0 +1,15 @@
+//! Oasis RPC interface for debugging confidential contracts.
+use jsonrpc_core::BoxFuture;
+use jsonrpc_macros::Trailing;
+
+use parity_rpc::v1::types::{BlockNumber, Bytes, CallRequest};
+
+build_rpc_trait! {
+    pub trait OasisDebug {
+        /// Simulates a call to a confidential contract given its plaintext
+        /// call data, and returns the plaintext output. The call data is
+        /// encrypted to the contract's key as a client would.
+        #[rpc(name = "oasis_confidentialCall")]
+        fn confidential_call(&self, CallRequest, Trailing<BlockNumber>) -> BoxFuture<Bytes>;
+    }
+}
@@ -0,0 +1,23 @@
+//! Personal RPC interface.
+use jsonrpc_core::Result;
+use jsonrpc_macros::Trailing;
+
+use parity_rpc::v1::types::H160;
+
+build_rpc_trait! {
+    /// Management of accounts held by the simulator.
+    ///
+    /// Accounts are kept in memory only and are lost on restart.
+    pub trait Personal {
+        /// Creates a new account protected by the given passphrase and returns
+        /// its address.
+        #[rpc(name = "personal_newAccount")]
+        fn new_account(&self, String) -> Result<H160>;
+
+        /// Unlocks an account for the given duration in seconds, allowing
+        /// `eth_sendTransaction` to sign with it. The default duration is
+        /// 300 seconds; a duration of 0 unlocks the account indefinitely.
+        #[rpc(name = "personal_unlockAccount")]
+        fn unlock_account(&self, H160, String, Trailing<u64>) -> Result<bool>;
+    }
+}
@@ -9,6 +9,8 @@ use failure::Error;
 use jsonrpc_core::{self, ErrorCode, Value};
 use parity_rpc::v1::types::BlockNumber;
 
+use crate::blockchain::{StateId, TransactionError};
+
 pub fn get_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -20,9 +22,17 @@ pub fn gwei_to_wei(gwei: u64) -> U256 {
     U256::from(gwei).saturating_mul(U256::from(1_000_000_000))
 }
 
+pub fn dev_to_wei(dev: u64) -> U256 {
+    gwei_to_wei(dev).saturating_mul(U256::from(1_000_000_000))
+}
+
 /// Convert an RPC block number to block id.
+///
+/// Mined blocks have no "pending" id, so it resolves to the latest block.
+/// Callers that need the pending block itself should use
+/// `Blockchain::get_pending_block`, and state queries should use
+/// `block_number_to_state_id`.
 pub fn block_number_to_id(number: BlockNumber) -> BlockId {
-    // For "pending", just use latest block.
     match number {
         BlockNumber::Num(num) => BlockId::Number(num),
         BlockNumber::Earliest => BlockId::Earliest,
@@ -31,6 +41,17 @@ pub fn block_number_to_id(number: BlockNumber) -> BlockId {
     }
 }
 
+/// Convert an RPC block number to the id of the state a query runs against.
+///
+/// "pending" is the state of the pending block, which differs from the
+/// latest one when transactions are waiting to be mined.
+pub fn block_number_to_state_id(number: BlockNumber) -> StateId {
+    match number {
+        BlockNumber::Pending => StateId::Pending,
+        number => block_number_to_id(number).into(),
+    }
+}
+
 /// Constructs a JSON-RPC error from a string message, with error code -32603.
 pub fn jsonrpc_error(err: Error) -> jsonrpc_core::Error {
     jsonrpc_core::Error {
@@ -40,6 +61,21 @@ pub fn jsonrpc_error(err: Error) -> jsonrpc_core::Error {
     }
 }
 
+/// Constructs a JSON-RPC error for a rejected transaction.
+///
+/// Known rejection reasons are reported like Geth does, with error code
+/// -32000. Other errors are reported as execution errors.
+pub fn transaction_error(err: Error) -> jsonrpc_core::Error {
+    match err.downcast::<TransactionError>() {
+        Ok(err) => jsonrpc_core::Error {
+            code: ErrorCode::ServerError(-32000),
+            message: format!("{}", err),
+            data: None,
+        },
+        Err(err) => execution_error(err),
+    }
+}
+
 /// Constructs a JSON-RPC error for a transaction execution error.
 /// TODO: format error message
 pub fn execution_error<T: fmt::Display>(data: T) -> jsonrpc_core::Error {